@@ -0,0 +1,188 @@
+// Prints the flag struct and `hammer_config!` for a set of getopts options,
+// to help port a program from getopts. Replace `opts` with the program's
+// own, run it once and paste the output in.
+
+extern crate getopts;
+extern crate hammer;
+
+use getopts::{OptGroup, optflag, optopt, optmulti, Yes, No, Req, Multi};
+use hammer::text::canonical_field_name;
+
+// words that can't name a field
+static KEYWORDS: &'static [&'static str] = &[
+    "abstract", "alignof", "as", "be", "box", "break", "const", "continue",
+    "crate", "do", "else", "enum", "extern", "false", "final", "fn", "for",
+    "if", "impl", "in", "let", "loop", "macro", "match", "mod", "mut",
+    "offsetof", "once", "override", "priv", "proc", "pub", "pure", "ref",
+    "return", "self", "sizeof", "static", "struct", "super", "trait", "true",
+    "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while",
+    "yield"
+];
+
+fn main() {
+    let opts = [
+        optflag("h", "help", "print this help menu"),
+        optflag("v", "verbose", "print more"),
+        optopt("o", "output", "where to write the result", "FILE"),
+        optmulti("L", "library-path", "a directory to search for libraries", "DIR")
+    ];
+
+    print!("{}", from_getopts("Opts", opts.as_slice()));
+}
+
+/*
+The source of a struct named `name` with a field for each of `opts`, and a
+`hammer_config!` for it
+
+A flag without an argument is a `bool`, a required one with an argument a
+`String`, one given several times a `Vec<String>`, and anything else an
+`Option<String>`, including options whose argument is optional. Short
+names and descriptions carry over; a long name that isn't the field name
+with `-` for `_` is kept with `flag_name`. Every other argument goes into
+`rest`.
+*/
+fn from_getopts(name: &str, opts: &[OptGroup]) -> String {
+    let mut fields = vec!();
+    let mut calls = vec!();
+
+    for opt in opts.iter() {
+        let field = field_name(opt);
+
+        let ty = match (opt.hasarg, opt.occur) {
+            (No, _) => "bool",
+            (_, Multi) => "Vec<String>",
+            (Yes, Req) => "String",
+            _ => "Option<String>"
+        };
+
+        fields.push(format!("    {}: {},\n", field, ty));
+
+        if !opt.long_name.is_empty() && canonical_field_name(field.as_slice()) != format!("--{}", opt.long_name) {
+            calls.push(format!("flag_name(\"{}\", \"{}\")", field, opt.long_name.as_slice().escape_default()));
+        }
+
+        match opt.short_name.as_slice().chars().next() {
+            Some(short) => calls.push(format!("short(\"{}\", '{}')", field, String::from_char(1, short).as_slice().escape_default())),
+            None => ()
+        }
+
+        if !opt.desc.is_empty() {
+            calls.push(format!("help(\"{}\", \"{}\")", field, opt.desc.as_slice().escape_default()));
+        }
+    }
+
+    let mut out = format!("#[deriving(Decodable, Show)]\nstruct {} {{\n", name);
+    out.push_str(fields.concat().as_slice());
+    out.push_str("    rest: Vec<String>\n}\n\n");
+
+    if calls.is_empty() {
+        out.push_str(format!("hammer_config!({})\n", name).as_slice());
+    } else {
+        out.push_str(format!("hammer_config!({} |c| {{\n    c.{}\n}})\n", name, calls.connect("\n     .")).as_slice());
+    }
+
+    out
+}
+
+// the field for an option: its long name with `_` for `-` and anything else
+// that can't be in an identifier, or else its short name. A name starting
+// with a digit gets `opt_` in front, and a keyword or `rest` a `_` after
+// it, which doesn't change its flag
+fn field_name(opt: &OptGroup) -> String {
+    let name = if opt.long_name.is_empty() { &opt.short_name } else { &opt.long_name };
+    let mut field: String = name.as_slice().chars().map(|c| {
+        if c.is_alphanumeric() && (c as u32) < 128 { c } else { '_' }
+    }).collect();
+
+    if field.as_slice().chars().all(|c| c == '_') || field.as_slice().char_at(0).is_digit() {
+        field = format!("opt_{}", field);
+    }
+
+    if field.as_slice() == "rest" || KEYWORDS.contains(&field.as_slice()) {
+        field.push_char('_');
+    }
+
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_getopts;
+    use getopts::{optflag, optopt, optmulti, optflagopt, reqopt};
+
+    #[test]
+    fn test_from_getopts() {
+        let opts = [
+            optflag("v", "verbose", "print more"),
+            optopt("o", "output", "where to write \"results\"", "FILE"),
+            reqopt("", "dry-run", "", "MODE"),
+            optmulti("I", "include", "a directory to search", "DIR"),
+            optflagopt("", "color.mode", "when to color", "WHEN"),
+            optflag("q", "", "")
+        ];
+
+        let source = from_getopts("MyOpts", opts.as_slice());
+
+        assert_eq!(source, concat!(
+            "#[deriving(Decodable, Show)]\n",
+            "struct MyOpts {\n",
+            "    verbose: bool,\n",
+            "    output: Option<String>,\n",
+            "    dry_run: String,\n",
+            "    include: Vec<String>,\n",
+            "    color_mode: Option<String>,\n",
+            "    q: bool,\n",
+            "    rest: Vec<String>\n",
+            "}\n",
+            "\n",
+            "hammer_config!(MyOpts |c| {\n",
+            "    c.short(\"verbose\", 'v')\n",
+            "     .help(\"verbose\", \"print more\")\n",
+            "     .short(\"output\", 'o')\n",
+            "     .help(\"output\", \"where to write \\\"results\\\"\")\n",
+            "     .short(\"include\", 'I')\n",
+            "     .help(\"include\", \"a directory to search\")\n",
+            "     .flag_name(\"color_mode\", \"color.mode\")\n",
+            "     .help(\"color_mode\", \"when to color\")\n",
+            "     .short(\"q\", 'q')\n",
+            "})\n").to_string());
+    }
+
+    #[test]
+    fn test_from_getopts_without_settings() {
+        let source = from_getopts("Plain", &[optflag("", "force", "")]);
+
+        assert!(source.as_slice().contains("struct Plain {\n    force: bool,\n    rest: Vec<String>\n}\n"));
+        assert!(source.as_slice().ends_with("hammer_config!(Plain)\n"));
+    }
+
+    #[test]
+    fn test_names_that_cant_be_fields() {
+        let opts = [
+            optopt("", "type", "", "KIND"),
+            optflag("", "2fa", ""),
+            optmulti("", "rest", "", "ARG"),
+            optflag("'", "", ""),
+            optflag("\\", "backslash", "")
+        ];
+
+        let source = from_getopts("Odd", opts.as_slice());
+
+        assert_eq!(source, concat!(
+            "#[deriving(Decodable, Show)]\n",
+            "struct Odd {\n",
+            "    type_: Option<String>,\n",
+            "    opt_2fa: bool,\n",
+            "    rest_: Vec<String>,\n",
+            "    opt__: bool,\n",
+            "    backslash: bool,\n",
+            "    rest: Vec<String>\n",
+            "}\n",
+            "\n",
+            "hammer_config!(Odd |c| {\n",
+            "    c.flag_name(\"opt_2fa\", \"2fa\")\n",
+            "     .short(\"opt__\", '\\'')\n",
+            "     .short(\"backslash\", '\\\\')\n",
+            "})\n").to_string());
+    }
+}
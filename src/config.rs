@@ -0,0 +1,1688 @@
+/*!
+Configuration of a flag struct
+
+`hammer_config!` implements `FlagConfig` for a struct; the closure it's
+given receives a `FlagConfiguration` to add short aliases, defaults,
+environment variables and the other settings below to.
+*/
+
+use std::collections::hashmap::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::fmt;
+use std::os;
+use std::rc::Rc;
+
+use text::canonical_field_name;
+use util::{read_assignments, expand_home};
+
+/// Implemented by `hammer_config!` for every flag struct
+pub trait FlagConfig {
+    fn config(_: Option<Self>, c: FlagConfiguration) -> FlagConfiguration {
+        c
+    }
+}
+
+/**
+Somewhere other than the command line that a flag's value can come from
+
+When a flag isn't given, the sources of a `FlagConfiguration` are asked
+for it in order, and the first value found is used exactly like one given
+on the command line. Environment variables, the config file and defaults
+are sources too, in that order.
+
+```
+struct Keyring;
+
+impl ValueSource for Keyring {
+    fn name(&self) -> String { "keyring".to_string() }
+
+    fn lookup(&self, field: &str) -> Option<String> {
+        if field == "token" { keyring::get("mytool") } else { None }
+    }
+}
+
+hammer_config!(MyOpts |c| { c.source_before("default", box Keyring) })
+```
+*/
+pub trait ValueSource {
+    /// What the source is called in `FlagDecoder::trace`
+    fn name(&self) -> String;
+
+    /// The value of `field`, if the source has one
+    fn lookup(&self, field: &str) -> Option<String>;
+
+    /// Whether the source could have a value for `field` at all; a source
+    /// that can't is neither asked nor traced
+    fn covers(&self, _field: &str) -> bool { true }
+}
+
+/// The name of the source of environment variables
+pub static ENV_SOURCE: &'static str = "env";
+
+/// The name of the config file source
+pub static FILE_SOURCE: &'static str = "file";
+
+/// The name of the source of defaults
+pub static DEFAULT_SOURCE: &'static str = "default";
+
+/// Reads an environment variable in place of `os::getenv`; see `env_lookup`
+pub type EnvLookup = fn(&str) -> Option<String>;
+
+// how environment variables are read: from the process's environment unless
+// a lookup was configured. Like the other functions, it compares and prints
+// only by whether there is one.
+#[deriving(Clone)]
+struct EnvReader(Option<EnvLookup>);
+
+impl EnvReader {
+    fn get(&self, var: &str) -> Option<String> {
+        match *self {
+            EnvReader(Some(lookup)) => lookup(var),
+            EnvReader(None) => os::getenv(var)
+        }
+    }
+
+    fn is_custom(&self) -> bool {
+        let EnvReader(ref lookup) = *self;
+        lookup.is_some()
+    }
+
+    fn or(self, other: EnvReader) -> EnvReader {
+        if self.is_custom() { self } else { other }
+    }
+}
+
+impl PartialEq for EnvReader {
+    fn eq(&self, other: &EnvReader) -> bool {
+        self.is_custom() == other.is_custom()
+    }
+}
+
+impl fmt::Show for EnvReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.is_custom() { "env_lookup" } else { "getenv" })
+    }
+}
+
+struct EnvSource {
+    vars: HashMap<String, String>,
+    reader: EnvReader
+}
+
+impl ValueSource for EnvSource {
+    fn name(&self) -> String { ENV_SOURCE.to_string() }
+
+    fn lookup(&self, field: &str) -> Option<String> {
+        self.vars.find_equiv(&field).and_then(|var| self.reader.get(var.as_slice()))
+    }
+
+    fn covers(&self, field: &str) -> bool {
+        self.vars.contains_key_equiv(&field)
+    }
+}
+
+// the file is read the first time a value is looked up in it
+struct FileSource {
+    path: Option<String>,
+    values: RefCell<Option<HashMap<String, String>>>
+}
+
+impl ValueSource for FileSource {
+    fn name(&self) -> String { FILE_SOURCE.to_string() }
+
+    fn lookup(&self, field: &str) -> Option<String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return None
+        };
+
+        let mut values = self.values.borrow_mut();
+
+        if values.is_none() {
+            *values = Some(read_assignments(path.as_slice()));
+        }
+
+        values.get_ref().find_equiv(&field).map(|v| v.clone())
+    }
+
+    fn covers(&self, _field: &str) -> bool {
+        self.path.is_some()
+    }
+}
+
+struct DefaultSource {
+    defaults: HashMap<String, String>
+}
+
+impl ValueSource for DefaultSource {
+    fn name(&self) -> String { DEFAULT_SOURCE.to_string() }
+
+    fn lookup(&self, field: &str) -> Option<String> {
+        self.defaults.find_equiv(&field).map(|d| d.clone())
+    }
+
+    fn covers(&self, field: &str) -> bool {
+        self.defaults.contains_key_equiv(&field)
+    }
+}
+
+// sources can't be compared or printed, so configurations compare and
+// print them by name
+#[deriving(Clone)]
+struct SourceList(Vec<Rc<Box<ValueSource>>>);
+
+impl SourceList {
+    fn names(&self) -> Vec<String> {
+        let SourceList(ref sources) = *self;
+        sources.iter().map(|s| s.name()).collect()
+    }
+}
+
+impl PartialEq for SourceList {
+    fn eq(&self, other: &SourceList) -> bool {
+        self.names() == other.names()
+    }
+}
+
+impl fmt::Show for SourceList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.names())
+    }
+}
+
+fn references<'a, I: Iterator<&'a String>>(refs: &mut Vec<(String, String)>, setting: &str, fields: I) {
+    refs.extend(fields.map(|field| (setting.to_string(), field.clone())));
+}
+
+// a setting's value the way it's written on a command line or in help,
+// for messages about it, e.g. `-v` for a short alias
+trait Shown {
+    fn shown(&self) -> String;
+}
+
+impl Shown for String {
+    fn shown(&self) -> String { self.clone() }
+}
+
+impl Shown for char {
+    fn shown(&self) -> String { self.to_string() }
+}
+
+impl Shown for Vec<char> {
+    fn shown(&self) -> String {
+        self.iter().map(|c| format!("-{}", c)).collect::<Vec<String>>().connect(" ")
+    }
+}
+
+impl Shown for Vec<String> {
+    fn shown(&self) -> String { self.connect(" ") }
+}
+
+impl Shown for Vec<(String, String)> {
+    fn shown(&self) -> String {
+        self.iter().map(|&(ref k, ref v)| format!("{}={}", k, v)).collect::<Vec<String>>().connect(" ")
+    }
+}
+
+impl Shown for (int, int) {
+    fn shown(&self) -> String {
+        let (min, max) = *self;
+        format!("{} to {}", min, max)
+    }
+}
+
+impl Shown for DuplicatePolicy {
+    fn shown(&self) -> String { format!("{}", self) }
+}
+
+impl Shown for HelpTier {
+    fn shown(&self) -> String { format!("{}", self) }
+}
+
+impl Shown for UsageOrder {
+    fn shown(&self) -> String { format!("{}", self) }
+}
+
+impl Shown for Syntax {
+    fn shown(&self) -> String {
+        let parts = [("equals", self.equals), ("clustering", self.clustering), ("abbreviation", self.abbreviation),
+                     ("attached_values", self.attached_values), ("negation", self.negation), ("passthrough", self.passthrough)];
+        let off: Vec<&str> = parts.iter().filter(|&&(_, on)| !on).map(|&(name, _)| name).collect();
+
+        if off.is_empty() { "the standard syntax".to_string() } else { format!("no {}", off.connect(", ")) }
+    }
+}
+
+// adds the entries of `other` to `map`; a field that's in both with different
+// values is an error naming the setting, the field and both values
+fn merge_map<V: PartialEq + Shown>(setting: &str, map: &mut HashMap<String, V>, other: HashMap<String, V>) -> Result<(), String> {
+    for (field, value) in other.move_iter() {
+        match map.find(&field) {
+            Some(existing) if *existing != value => {
+                return Err(format!("{} is configured twice for {}: {} and {}", setting, field, existing.shown(), value.shown()));
+            },
+            _ => ()
+        }
+
+        map.insert(field, value);
+    }
+
+    Ok(())
+}
+
+// takes `other` for a setting of the whole program that's still `default`
+// here; both set to different values is an error naming the setting
+fn merge_setting<V: PartialEq + Shown>(setting: &str, value: &mut V, other: V, default: &V) -> Result<(), String> {
+    if other == *default || other == *value {
+        return Ok(());
+    }
+
+    if *value != *default {
+        return Err(format!("{} is configured twice: {} and {}", setting, value.shown(), other.shown()));
+    }
+
+    *value = other;
+    Ok(())
+}
+
+// adds the elements of `other` that `list` doesn't have yet
+fn merge_list<T: PartialEq>(list: &mut Vec<T>, other: Vec<T>) {
+    for item in other.move_iter() {
+        if !list.contains(&item) { list.push(item) }
+    }
+}
+
+/// Suggests values for a flag, given what's been typed of it so far
+pub type Completer = fn(&str) -> Vec<String>;
+
+/// Checks a value of a flag, returning what's wrong with it, such as
+/// `must be even`
+pub type Validator = fn(&str) -> Result<(), String>;
+
+// functions can't be compared or printed either, so configurations compare
+// and print the fields that have one
+#[deriving(Clone)]
+struct FnTable<F>(HashMap<String, F>);
+
+impl<F> FnTable<F> {
+    // the table, if any, with `function` added for `field`
+    fn add(table: Option<FnTable<F>>, field: &str, function: F) -> FnTable<F> {
+        let mut functions = match table {
+            Some(FnTable(functions)) => functions,
+            None => HashMap::new()
+        };
+
+        functions.insert(field.to_string(), function);
+        FnTable(functions)
+    }
+
+    fn find<'a>(&'a self, field: &str) -> Option<&'a F> {
+        let FnTable(ref functions) = *self;
+        functions.find_equiv(&field)
+    }
+
+    // both tables in one; functions can't be compared, so a field in both is
+    // an error
+    fn merge(setting: &str, table: Option<FnTable<F>>, other: Option<FnTable<F>>) -> Result<Option<FnTable<F>>, String> {
+        let FnTable(functions) = match other {
+            Some(other) => other,
+            None => return Ok(table)
+        };
+
+        let mut table = table;
+
+        for (field, function) in functions.move_iter() {
+            if table.as_ref().and_then(|t| t.find(field.as_slice())).is_some() {
+                return Err(format!("{} is configured twice for {}", setting, field));
+            }
+
+            table = Some(FnTable::add(table, field.as_slice(), function));
+        }
+
+        Ok(table)
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let FnTable(ref functions) = *self;
+        let mut fields: Vec<String> = functions.keys().map(|f| f.clone()).collect();
+        fields.sort();
+        fields
+    }
+}
+
+impl<F> PartialEq for FnTable<F> {
+    fn eq(&self, other: &FnTable<F>) -> bool {
+        self.fields() == other.fields()
+    }
+}
+
+impl<F> fmt::Show for FnTable<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.fields())
+    }
+}
+
+/// What to do when a flag is given more than once
+#[deriving(Clone, PartialEq, Show)]
+pub enum DuplicatePolicy {
+    /// Use the first occurrence and leave the others in the remaining arguments
+    KeepFirst,
+    /// Consume every occurrence and use the last one
+    KeepLast,
+    /// Consume every occurrence; lists keep all of them, other fields the last
+    Accumulate,
+    /// Report an error
+    Error
+}
+
+/// Which help output a flag appears in
+#[deriving(Clone, PartialEq, PartialOrd, Show)]
+pub enum HelpTier {
+    /// Everyday flags, shown by `-h` and everything after it
+    Common,
+    /// Shown by `--help`
+    Full,
+    /// Only shown by `--help --verbose`
+    Expert
+}
+
+/// The order flags are listed in by `usage`
+#[deriving(Clone, PartialEq, Show)]
+pub enum UsageOrder {
+    /// The order the fields are declared in
+    DeclarationOrder,
+    /// Flags with a short alias first, sorted by it, then the rest sorted
+    /// by their long form
+    ShortFirst
+}
+
+/**
+The command line syntaxes the decoder accepts, for matching a parser a
+program is moving away from; see `FlagConfiguration::syntax`
+
+More syntaxes may be added, so build it from `Syntax::standard()`:
+
+```
+c.syntax(Syntax { abbreviation: false, ..Syntax::standard() })
+```
+*/
+#[deriving(Clone, PartialEq, Show)]
+pub struct Syntax {
+    /// `--count=5` as well as `--count 5`
+    pub equals: bool,
+    /// `-vc` for `-v -c`, and `-vv` for a `counted` flag given twice;
+    /// without it, short aliases must be arguments of their own
+    pub clustering: bool,
+    /// `--co` for `--color`, when no other flag starts with `--co`
+    pub abbreviation: bool,
+    /// `-n10` for `-n 10`
+    pub attached_values: bool,
+    /// `--no-color` to turn a switch off
+    pub negation: bool,
+    /// `--` to end the flags, leaving everything after it to the rest
+    /// field; without it, `--` is an ordinary argument
+    pub passthrough: bool
+}
+
+impl Syntax {
+    /// Every syntax
+    pub fn standard() -> Syntax {
+        Syntax { equals: true, clustering: true, abbreviation: true, attached_values: true, negation: true, passthrough: true }
+    }
+}
+
+/** Contains the configuration associated with a FlagConfig,
+such as the short versions of flags and description of the program.
+*/
+#[deriving(Clone, Show, PartialEq)]
+pub struct FlagConfiguration {
+    short_aliases: HashMap<String, Vec<char>>,
+    long_names: HashMap<String, String>,
+    description: Option<String>,
+    help: HashMap<String, String>,
+    rest_field: String,
+    defaults: HashMap<String, String>,
+    list_defaults: HashMap<String, Vec<String>>,
+    map_defaults: HashMap<String, Vec<(String, String)>>,
+    appended_defaults: HashSet<String>,
+    required: HashSet<String>,
+    separators: HashMap<String, String>,
+    delimiters: HashMap<String, char>,
+    env_vars: HashMap<String, String>,
+    env_reader: EnvReader,
+    config_file: Option<String>,
+    version: Option<String>,
+    early_exits: Vec<String>,
+    confirmation: Option<String>,
+    help_command: bool,
+    completion: bool,
+    lenient_lists: HashSet<String>,
+    counted: HashSet<String>,
+    negated: HashSet<String>,
+    assignments: Option<String>,
+    override_flag: Option<String>,
+    value_files: HashMap<String, String>,
+    deduped: HashSet<String>,
+    sensitive: HashSet<String>,
+    ignored: Vec<(String, String, String)>,
+    expanded: HashSet<String>,
+    paths: HashSet<String>,
+    raw_paths: HashSet<String>,
+    lenient_expansion: bool,
+    missing_values: bool,
+    strict_spelling: bool,
+    strict_spelling_var: Option<String>,
+    reject_unknown: bool,
+    argfiles: bool,
+    collect_errors: bool,
+    case_insensitive: bool,
+    duplicates: Option<DuplicatePolicy>,
+    field_duplicates: HashMap<String, DuplicatePolicy>,
+    flattened: HashSet<String>,
+    starred: bool,
+    order: UsageOrder,
+    syntax: Syntax,
+    tiers: HashMap<String, HelpTier>,
+    hidden: HashSet<String>,
+    program: Option<String>,
+    sources: SourceList,
+    ranges: HashMap<String, (int, int)>,
+    validators: Option<FnTable<Validator>>,
+    completers: Option<FnTable<Completer>>,
+    problems: Vec<String>
+}
+
+impl FlagConfiguration {
+    pub fn new() -> FlagConfiguration {
+        FlagConfiguration {
+            short_aliases: HashMap::new(),
+            long_names: HashMap::new(),
+            description: None,
+            help: HashMap::new(),
+            rest_field: "rest".to_string(),
+            defaults: HashMap::new(),
+            list_defaults: HashMap::new(),
+            map_defaults: HashMap::new(),
+            appended_defaults: HashSet::new(),
+            required: HashSet::new(),
+            separators: HashMap::new(),
+            delimiters: HashMap::new(),
+            env_vars: HashMap::new(),
+            env_reader: EnvReader(None),
+            config_file: None,
+            version: None,
+            early_exits: vec!(),
+            confirmation: None,
+            help_command: false,
+            completion: false,
+            lenient_lists: HashSet::new(),
+            counted: HashSet::new(),
+            negated: HashSet::new(),
+            assignments: None,
+            override_flag: None,
+            value_files: HashMap::new(),
+            deduped: HashSet::new(),
+            sensitive: HashSet::new(),
+            ignored: vec!(),
+            expanded: HashSet::new(),
+            paths: HashSet::new(),
+            raw_paths: HashSet::new(),
+            lenient_expansion: false,
+            missing_values: false,
+            strict_spelling: false,
+            strict_spelling_var: None,
+            reject_unknown: false,
+            argfiles: false,
+            collect_errors: false,
+            case_insensitive: false,
+            duplicates: None,
+            field_duplicates: HashMap::new(),
+            flattened: HashSet::new(),
+            starred: false,
+            order: DeclarationOrder,
+            syntax: Syntax::standard(),
+            tiers: HashMap::new(),
+            hidden: HashSet::new(),
+            program: None,
+            sources: SourceList(vec!(
+                Rc::new(box EnvSource { vars: HashMap::new(), reader: EnvReader(None) } as Box<ValueSource>),
+                Rc::new(box FileSource { path: None, values: RefCell::new(None) } as Box<ValueSource>),
+                Rc::new(box DefaultSource { defaults: HashMap::new() } as Box<ValueSource>)
+            )),
+            ranges: HashMap::new(),
+            validators: None,
+            completers: None,
+            problems: vec!()
+        }
+    }
+
+    /// Add new "short" version of a flag
+    ///
+    /// ```flag_config.short("verbose", 'v')```
+    pub fn short(mut self, string: &str, char: char) -> FlagConfiguration {
+        self.short_aliases.insert(string.to_string(), vec!(char));
+        self
+    }
+
+    /// Give a flag several short versions, any of which can be used on the
+    /// command line; the first is the one `short_for` returns, and usage
+    /// lists them all
+    ///
+    /// ```flag_config.shorts("help", &['h', '?'])```
+    pub fn shorts(mut self, string: &str, chars: &[char]) -> FlagConfiguration {
+        self.short_aliases.insert(string.to_string(), Vec::from_slice(chars));
+        self
+    }
+
+    /// Give a flag a long form other than the one made from its field name;
+    /// `name` is given without the leading `--`
+    ///
+    /// ```flag_config.flag_name("output_file", "out")```
+    pub fn flag_name(mut self, field: &str, name: &str) -> FlagConfiguration {
+        self.long_names.insert(field.to_string(), name.to_string());
+        self
+    }
+
+    /// Add a line of help text, shown under the flag by `usage`
+    ///
+    /// ```flag_config.help("verbose", "enable verbose output")```
+    pub fn help(mut self, field: &str, text: &str) -> FlagConfiguration {
+        self.help.insert(field.to_string(), text.to_string());
+        self
+    }
+
+    /// Add a short version of a flag and its help text at once; the same as
+    /// `short` followed by `help`
+    ///
+    /// ```flag_config.flag("verbose", 'v', "enable verbose output")```
+    pub fn flag(self, field: &str, short: char, text: &str) -> FlagConfiguration {
+        self.short(field, short).help(field, text)
+    }
+
+    /// Add a description
+    ///
+    /// ```flag_config.descr("Foo is a program to do bar")```
+    pub fn desc(mut self, string: &str) -> FlagConfiguration {
+        self.description = Some(string.to_string());
+        self
+    }
+
+    /// Set the program's name, used in generated usage when the caller
+    /// doesn't give one
+    ///
+    /// ```flag_config.program_name("mytool")```
+    pub fn program_name(mut self, name: &str) -> FlagConfiguration {
+        self.program = Some(name.to_string());
+        self
+    }
+
+    /// Add a version, reported when `--version` is given
+    ///
+    /// ```flag_config.version("1.2.3")```
+    pub fn version(mut self, string: &str) -> FlagConfiguration {
+        self.version = Some(string.to_string());
+        self
+    }
+
+    /// Stop decoding as soon as a flag is given, like `--help` does
+    ///
+    /// `decode_args` then returns an `ExitedEarly` error naming the field,
+    /// even if required flags are missing.
+    ///
+    /// ```flag_config.exits_early("list_formats")```
+    pub fn exits_early(mut self, field: &str) -> FlagConfiguration {
+        self.early_exits.push(field.to_string());
+        self
+    }
+
+    /// Ask for confirmation unless a `bool` flag such as `--yes` is given,
+    /// as destructive tools do
+    ///
+    /// When the flag isn't on the command line and stdin is a terminal,
+    /// `Decoded::needs_confirmation` is true and `decode_args` returns a
+    /// `ConfirmationNeeded` error; prompting is left to the program, which
+    /// can get the `Decoded` from `decode` or `decode_or_exit`.
+    ///
+    /// ```flag_config.short("yes", 'y').confirm_with("yes")```
+    pub fn confirm_with(mut self, field: &str) -> FlagConfiguration {
+        self.confirmation = Some(field.to_string());
+        self
+    }
+
+    /// Treat `help` as the first argument like `--help`, as tools with
+    /// subcommands do
+    ///
+    /// ```flag_config.help_command()```
+    pub fn help_command(mut self) -> FlagConfiguration {
+        self.help_command = true;
+        self
+    }
+
+    /// Answer `--hammer-complete WORDS...` as the first argument with the
+    /// `completions` of the words, for shell completion scripts to call
+    ///
+    /// Without this, `--hammer-complete` is an argument like any other.
+    ///
+    /// ```flag_config.shell_completion()```
+    pub fn shell_completion(mut self) -> FlagConfiguration {
+        self.completion = true;
+        self
+    }
+
+    /// Drop repeated values from a list flag, so `--tag a --tag a --tag b`
+    /// gives `a` and `b`
+    ///
+    /// Values are compared as given, before they're converted, and each one
+    /// stays where it first appeared.
+    ///
+    /// ```flag_config.dedup("tag")```
+    pub fn dedup(mut self, field: &str) -> FlagConfiguration {
+        self.deduped.insert(field.to_string());
+        self
+    }
+
+    /// Never repeat a flag's value in messages, warnings or an echoed
+    /// command line; it's shown as `*****` instead
+    ///
+    /// The decoded struct still gets the real value.
+    ///
+    /// ```flag_config.sensitive("password")```
+    pub fn sensitive(mut self, field: &str) -> FlagConfiguration {
+        self.sensitive.insert(field.to_string());
+        self
+    }
+
+    /// Warn that a flag has no effect when another flag is given too
+    ///
+    /// The warning is reported in `FlagDecoder::warnings` and never stops
+    /// decoding; `reason` also appears as a note under the flag in `--help`.
+    ///
+    /// ```flag_config.ignored_when("output", "dry_run", "output is not written in dry-run mode")```
+    pub fn ignored_when(mut self, field: &str, other: &str, reason: &str) -> FlagConfiguration {
+        self.ignored.push((field.to_string(), other.to_string(), reason.to_string()));
+        self
+    }
+
+    /// Skip list elements that can't be converted instead of failing
+    ///
+    /// Each skipped element is reported in `FlagDecoder::warnings`.
+    ///
+    /// ```flag_config.lenient_list("ports")```
+    pub fn lenient_list(mut self, field: &str) -> FlagConfiguration {
+        self.lenient_lists.insert(field.to_string());
+        self
+    }
+
+    /// Count how many times a flag is given into an integer field, so
+    /// `-v -v`, `-vv` and `--verbose --verbose` all give 2
+    ///
+    /// The flag takes no value, and is 0 when it isn't given.
+    ///
+    /// ```flag_config.counted("verbose")```
+    pub fn counted(mut self, field: &str) -> FlagConfiguration {
+        self.counted.insert(field.to_string());
+        self
+    }
+
+    /// Collect `NAME=VALUE` arguments at the very start of the command line
+    /// into a `HashMap<String, String>` field, as `env NAME=VALUE cmd` does
+    ///
+    /// Collection stops at the first argument that isn't an assignment, so
+    /// assignments after a flag or a positional argument are left alone.
+    ///
+    /// ```flag_config.leading_assignments("env")```
+    pub fn leading_assignments(mut self, field: &str) -> FlagConfiguration {
+        self.assignments = Some(field.to_string());
+        self
+    }
+
+    /// Suggest values for a flag when the program is asked to complete a
+    /// command line, as with `shell_completion`; suggestions that don't
+    /// start with what was typed are left out
+    ///
+    /// The completer is only called for completion, never while decoding.
+    ///
+    /// ```flag_config.complete_with("device", list_devices)```
+    pub fn complete_with(mut self, field: &str, completer: Completer) -> FlagConfiguration {
+        self.completers = Some(FnTable::add(self.completers.take(), field, completer));
+        self
+    }
+
+    /// Only accept numbers from `min` to `max` for a flag
+    ///
+    /// A `default` outside the range is an error in the configuration.
+    ///
+    /// ```flag_config.range("port", 1, 65535)```
+    pub fn range(mut self, field: &str, min: int, max: int) -> FlagConfiguration {
+        self.ranges.insert(field.to_string(), (min, max));
+        self
+    }
+
+    /// Check every value of a flag with `validator` before it's converted
+    ///
+    /// Like `range`, the `default` is checked along with the rest of the
+    /// configuration.
+    ///
+    /// ```flag_config.validate("port", not_privileged)```
+    pub fn validate(mut self, field: &str, validator: Validator) -> FlagConfiguration {
+        self.validators = Some(FnTable::add(self.validators.take(), field, validator));
+        self
+    }
+
+    /// Add a flag that sets any field by its flag's name, as in
+    /// `-o count=5`; a name of one letter is a short flag, anything longer a
+    /// long one
+    ///
+    /// The name is the flag without its `--`, so a field renamed with
+    /// `flag_name` is set by its new name.
+    ///
+    /// A flag given directly wins over an override of its field. When a
+    /// field is overridden more than once, the last value is used, and list
+    /// fields get every value.
+    ///
+    /// ```flag_config.override_flag("o")```
+    pub fn override_flag(mut self, name: &str) -> FlagConfiguration {
+        self.override_flag = Some(name.to_string());
+        self
+    }
+
+    /// Add a `--field-from FILE` flag that reads more values for a list flag
+    /// from a file, one per line
+    ///
+    /// Blank lines and `#` comments are skipped. The values from files are
+    /// added after the ones given directly, in the order the files were given.
+    ///
+    /// ```flag_config.values_from_file("exclude")```
+    pub fn values_from_file(self, field: &str) -> FlagConfiguration {
+        let flag = format!("{}_from", field);
+        self.values_from_file_as(field, flag.as_slice())
+    }
+
+    /// Like `values_from_file`, but with a different name for the new flag,
+    /// given as a field name
+    ///
+    /// ```flag_config.values_from_file_as("exclude", "exclude_list")```
+    pub fn values_from_file_as(mut self, field: &str, flag: &str) -> FlagConfiguration {
+        self.value_files.insert(field.to_string(), flag.to_string());
+        self
+    }
+
+    /// Expand environment variables like `$HOME` or `${CI_PROJECT}` in a
+    /// flag's value, wherever it comes from; `$$` stands for a literal `$`
+    ///
+    /// Unset variables are an error, unless `lenient_expansion` is set.
+    ///
+    /// ```flag_config.expand_env("output_dir")```
+    pub fn expand_env(mut self, field: &str) -> FlagConfiguration {
+        self.expanded.insert(field.to_string());
+        self
+    }
+
+    /// Treat a flag's value as a path: a leading `~` is replaced by the home
+    /// directory, and `usage` shows it as `<PATH>`
+    ///
+    /// ```flag_config.path("output_dir")```
+    pub fn path(mut self, field: &str) -> FlagConfiguration {
+        self.paths.insert(field.to_string());
+        self
+    }
+
+    /// Leave a leading `~` alone in a `path` flag
+    ///
+    /// ```flag_config.no_home_expansion("raw_path")```
+    pub fn no_home_expansion(mut self, field: &str) -> FlagConfiguration {
+        self.raw_paths.insert(field.to_string());
+        self
+    }
+
+    /// Leave references to unset variables as they are in `expand_env` fields
+    ///
+    /// ```flag_config.lenient_expansion()```
+    pub fn lenient_expansion(mut self) -> FlagConfiguration {
+        self.lenient_expansion = true;
+        self
+    }
+
+    /// Treat a flag at the end of the command line that needs a value as if
+    /// it were given an empty one, instead of reporting an error
+    ///
+    /// ```flag_config.lenient_missing_value()```
+    pub fn lenient_missing_value(mut self) -> FlagConfiguration {
+        self.missing_values = true;
+        self
+    }
+
+    /// Only accept flags spelled out in full, or their short aliases
+    ///
+    /// Abbreviations like `--co` for `--color` become an error, so scripts
+    /// don't break when a new flag makes them ambiguous.
+    ///
+    /// ```flag_config.strict_spelling()```
+    pub fn strict_spelling(mut self) -> FlagConfiguration {
+        self.strict_spelling = true;
+        self
+    }
+
+    /// Spell strictly, as with `strict_spelling`, whenever the environment
+    /// variable `var` is set to `1`
+    ///
+    /// ```flag_config.strict_spelling_from_env("MYAPP_STRICT_FLAGS")```
+    pub fn strict_spelling_from_env(mut self, var: &str) -> FlagConfiguration {
+        self.strict_spelling_var = Some(var.to_string());
+        self
+    }
+
+    /// Report arguments that look like flags but aren't any of the
+    /// struct's, like a misspelled `--colr`, instead of leaving them over
+    ///
+    /// Arguments after `--` are never flags, so the rest field can still be
+    /// given arguments starting with `-` that way. `-` on its own and
+    /// negative numbers aren't flags either.
+    ///
+    /// ```flag_config.reject_unknown_flags()```
+    pub fn reject_unknown_flags(mut self) -> FlagConfiguration {
+        self.reject_unknown = true;
+        self
+    }
+
+    /// Read more arguments from files named on the command line as `@file`
+    ///
+    /// The file has an argument on each line, taken as it's written, so an
+    /// argument can have spaces around it or start with `#`; only a byte
+    /// order mark and `\r\n` line endings are removed. It's read before
+    /// anything is decoded, so its flags work just like ones given directly.
+    /// Files can name other files, up to 8 deep. `@@name` is the argument
+    /// `@name`, and nothing after `--` is expanded. Errors point at the
+    /// arguments as expanded.
+    ///
+    /// ```flag_config.allow_argfiles()```
+    pub fn allow_argfiles(mut self) -> FlagConfiguration {
+        self.argfiles = true;
+        self
+    }
+
+    /// Keep decoding after a flag is wrong, and report every problem with
+    /// the command line at once instead of only the first
+    ///
+    /// The error then lists each problem on a line of its own.
+    ///
+    /// ```flag_config.collect_errors()```
+    pub fn collect_errors(mut self) -> FlagConfiguration {
+        self.collect_errors = true;
+        self
+    }
+
+    /// Match long flags regardless of case, so `--COUNT` and `--Count` are
+    /// both `--count`, and `--CO` abbreviates it; `--HELP` and `--VERSION`
+    /// work too
+    ///
+    /// Short aliases still match only their own case, since `-v` and `-V`
+    /// are usually different flags, and values keep the case they're given
+    /// in.
+    ///
+    /// ```flag_config.case_insensitive()```
+    pub fn case_insensitive(mut self) -> FlagConfiguration {
+        self.case_insensitive = true;
+        self
+    }
+
+    /// Set what happens when any flag is given more than once
+    ///
+    /// Without this, lists accumulate every occurrence and other fields
+    /// `KeepFirst`.
+    ///
+    /// ```flag_config.duplicates(Error)```
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> FlagConfiguration {
+        self.duplicates = Some(policy);
+        self
+    }
+
+    /// Set what happens when one flag is given more than once, overriding
+    /// `duplicates`
+    ///
+    /// ```flag_config.duplicates_for("output", Error)```
+    pub fn duplicates_for(mut self, field: &str, policy: DuplicatePolicy) -> FlagConfiguration {
+        self.field_duplicates.insert(field.to_string(), policy);
+        self
+    }
+
+    /// Merge the flags of a field that is itself a flag struct into this one
+    ///
+    /// The nested struct's fields become flags of their own, named as if
+    /// they were declared here, so their short aliases, defaults and other
+    /// settings are given on this configuration too; the nested struct's own
+    /// `hammer_config!` isn't consulted. Nested structs inside a flattened
+    /// field must be flattened as well, and field names must not repeat
+    /// across the flattened structs.
+    ///
+    /// ```flag_config.flatten_field("common").short("verbose", 'v')```
+    pub fn flatten_field(mut self, field: &str) -> FlagConfiguration {
+        self.flattened.insert(field.to_string());
+        self
+    }
+
+    /// Like `flatten_field`, but the nested struct's own `hammer_config!` is
+    /// merged into this configuration too, as with `merge`; a field both
+    /// configure differently is a `ConfigError` when decoding
+    ///
+    /// ```flag_config.flatten::<Verbosity>("verbosity")```
+    pub fn flatten<T: FlagConfig>(self, field: &str) -> FlagConfiguration {
+        let nested = FlagConfig::config(None::<T>, FlagConfiguration::new());
+        let config = self.flatten_field(field);
+
+        match config.clone().merge(nested) {
+            Ok(merged) => merged,
+            Err(problem) => {
+                let mut config = config;
+                config.problems.push(format!("flattening {}: {}", field, problem));
+                config
+            }
+        }
+    }
+
+    /**
+    Add the settings of `other`, such as the ones a module gives for the
+    fields it contributes to a flattened struct
+
+    The settings of each field are combined, but a field both configure
+    differently, like with two short aliases, is an error naming the field
+    and both values. Settings of the whole program, like the description,
+    are taken from `other` only when they aren't set here, and switches like
+    `collect_errors` are on when either turns them on; the rest field, the
+    syntax and the usage order are an error when both change them
+    differently.
+
+    ```
+    let config = try!(FlagConfiguration::new().short("verbose", 'v').merge(network_config()));
+    ```
+    */
+    pub fn merge(mut self, other: FlagConfiguration) -> Result<FlagConfiguration, String> {
+        let FlagConfiguration {
+            short_aliases, long_names, description, help, rest_field, defaults, list_defaults,
+            map_defaults, appended_defaults, required, separators, delimiters, env_vars,
+            env_reader, config_file, version, early_exits, confirmation, help_command, completion, lenient_lists,
+            counted, negated, assignments, override_flag, value_files, deduped, sensitive,
+            ignored, expanded, paths, raw_paths, lenient_expansion, missing_values,
+            strict_spelling, strict_spelling_var, reject_unknown, argfiles, collect_errors,
+            case_insensitive, duplicates, field_duplicates, flattened, starred, order, syntax,
+            tiers, hidden, program, sources, ranges, validators, completers, problems
+        } = other;
+
+        try!(merge_map("short", &mut self.short_aliases, short_aliases));
+        try!(merge_map("flag_name", &mut self.long_names, long_names));
+        try!(merge_map("help", &mut self.help, help));
+        try!(merge_map("default", &mut self.defaults, defaults));
+        try!(merge_map("default_list", &mut self.list_defaults, list_defaults));
+        try!(merge_map("default_map", &mut self.map_defaults, map_defaults));
+        try!(merge_map("join_lines", &mut self.separators, separators));
+        try!(merge_map("delimited", &mut self.delimiters, delimiters));
+        try!(merge_map("env", &mut self.env_vars, env_vars));
+        try!(merge_map("values_from_file", &mut self.value_files, value_files));
+        try!(merge_map("duplicates_for", &mut self.field_duplicates, field_duplicates));
+        try!(merge_map("help_tier", &mut self.tiers, tiers));
+        try!(merge_map("range", &mut self.ranges, ranges));
+
+        self.validators = try!(FnTable::merge("validate", self.validators.take(), validators));
+        self.completers = try!(FnTable::merge("complete_with", self.completers.take(), completers));
+
+        self.appended_defaults.extend(appended_defaults.move_iter());
+        self.required.extend(required.move_iter());
+        self.lenient_lists.extend(lenient_lists.move_iter());
+        self.counted.extend(counted.move_iter());
+        self.negated.extend(negated.move_iter());
+        self.deduped.extend(deduped.move_iter());
+        self.sensitive.extend(sensitive.move_iter());
+        self.expanded.extend(expanded.move_iter());
+        self.paths.extend(paths.move_iter());
+        self.raw_paths.extend(raw_paths.move_iter());
+        self.flattened.extend(flattened.move_iter());
+        self.hidden.extend(hidden.move_iter());
+        self.problems.extend(problems.move_iter());
+
+        merge_list(&mut self.early_exits, early_exits);
+        merge_list(&mut self.ignored, ignored);
+
+        self.description = self.description.or(description);
+        self.version = self.version.or(version);
+        self.confirmation = self.confirmation.or(confirmation);
+        self.assignments = self.assignments.or(assignments);
+        self.override_flag = self.override_flag.or(override_flag);
+        self.strict_spelling_var = self.strict_spelling_var.or(strict_spelling_var);
+        self.env_reader = self.env_reader.or(env_reader);
+        self.duplicates = self.duplicates.or(duplicates);
+        self.program = self.program.or(program);
+
+        try!(merge_setting("rest_field", &mut self.rest_field, rest_field, &"rest".to_string()));
+        try!(merge_setting("sort_usage", &mut self.order, order, &DeclarationOrder));
+        try!(merge_setting("syntax", &mut self.syntax, syntax, &Syntax::standard()));
+
+        self.help_command = self.help_command || help_command;
+        self.completion = self.completion || completion;
+        self.lenient_expansion = self.lenient_expansion || lenient_expansion;
+        self.missing_values = self.missing_values || missing_values;
+        self.strict_spelling = self.strict_spelling || strict_spelling;
+        self.reject_unknown = self.reject_unknown || reject_unknown;
+        self.argfiles = self.argfiles || argfiles;
+        self.collect_errors = self.collect_errors || collect_errors;
+        self.case_insensitive = self.case_insensitive || case_insensitive;
+        self.starred = self.starred || starred;
+
+        // the sources of other's own, then the built-in ones again, now
+        // with every env var, default and the config file
+        let SourceList(sources) = sources;
+
+        for source in sources.move_iter() {
+            let name = source.name();
+            if name.as_slice() != ENV_SOURCE && name.as_slice() != FILE_SOURCE && name.as_slice() != DEFAULT_SOURCE {
+                let SourceList(ref mut own) = self.sources;
+                own.push(source);
+            }
+        }
+
+        let merged_file = self.config_file.is_none() && config_file.is_some();
+        self.config_file = self.config_file.or(config_file);
+
+        let env = box EnvSource { vars: self.env_vars.clone(), reader: self.env_reader.clone() };
+        let defaults = box DefaultSource { defaults: self.defaults.clone() };
+        let mut merged = self.replace_source(ENV_SOURCE, env).replace_source(DEFAULT_SOURCE, defaults);
+
+        if merged_file {
+            let file = box FileSource { path: merged.config_file.clone(), values: RefCell::new(None) };
+            merged = merged.replace_source(FILE_SOURCE, file);
+        }
+
+        Ok(merged)
+    }
+
+    /// List flags in `usage` in declaration order, marking required ones
+    /// with `*`, instead of putting the optional ones in brackets after them
+    ///
+    /// ```flag_config.starred_usage()```
+    pub fn starred_usage(mut self) -> FlagConfiguration {
+        self.starred = true;
+        self
+    }
+
+    /// Set which help output a flag appears in; flags are `Common` otherwise
+    ///
+    /// ```flag_config.help_tier("debug_gc", Expert)```
+    pub fn help_tier(mut self, field: &str, tier: HelpTier) -> FlagConfiguration {
+        self.tiers.insert(field.to_string(), tier);
+        self
+    }
+
+    /// Leave a flag out of every help output, whatever its tier
+    ///
+    /// ```flag_config.hidden("trace_internals")```
+    pub fn hidden(mut self, field: &str) -> FlagConfiguration {
+        self.hidden.insert(field.to_string());
+        self
+    }
+
+    /// Change the order flags are listed in by `usage`; required flags are
+    /// still listed before optional ones
+    ///
+    /// ```flag_config.sort_usage(ShortFirst)```
+    pub fn sort_usage(mut self, order: UsageOrder) -> FlagConfiguration {
+        self.order = order;
+        self
+    }
+
+    /// Choose which syntaxes are accepted; arguments in any other syntax
+    /// aren't recognized as flags
+    ///
+    /// ```flag_config.syntax(Syntax { equals: false, ..Syntax::standard() })```
+    pub fn syntax(mut self, syntax: Syntax) -> FlagConfiguration {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Change the name of the "extra arguments" field
+    ///
+    /// The associated field must be of `type Vec<String>`
+    ///
+    /// ```flag_config.rest_field("remaining")```
+    pub fn rest_field(mut self, string: &str) -> FlagConfiguration {
+        self.rest_field = string.to_string();
+        self
+    }
+
+    /// Use a default value when a flag isn't supplied
+    ///
+    /// The value is converted exactly like a value given on the command line,
+    /// and the flag is no longer required.
+    ///
+    /// ```flag_config.default("count", "10")```
+    pub fn default(mut self, field: &str, value: &str) -> FlagConfiguration {
+        self.defaults.insert(field.to_string(), value.to_string());
+
+        let source = box DefaultSource { defaults: self.defaults.clone() };
+        self.replace_source(DEFAULT_SOURCE, source)
+    }
+
+    /// Use default values for a list when the flag isn't given
+    ///
+    /// Values given on the command line replace the defaults, unless the
+    /// field is also marked with `default_append`.
+    ///
+    /// ```flag_config.default_list("includes", &["src", "tests"])```
+    pub fn default_list(mut self, field: &str, values: &[&str]) -> FlagConfiguration {
+        self.list_defaults.insert(field.to_string(), values.iter().map(|v| v.to_string()).collect());
+        self
+    }
+
+    /// Use default entries for the `leading_assignments` map when no
+    /// assignments lead the command line
+    ///
+    /// ```flag_config.default_map("env", &[("LANG", "C")])```
+    pub fn default_map(mut self, field: &str, entries: &[(&str, &str)]) -> FlagConfiguration {
+        let entries = entries.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect();
+        self.map_defaults.insert(field.to_string(), entries);
+        self
+    }
+
+    /// Add the values given on the command line to the `default_list` or
+    /// `default_map` of a field instead of replacing them
+    ///
+    /// ```flag_config.default_append("includes")```
+    pub fn default_append(mut self, field: &str) -> FlagConfiguration {
+        self.appended_defaults.insert(field.to_string());
+        self
+    }
+
+    /// Make a `bool` flag true unless it's turned off with `--flag=false`
+    ///
+    /// A bare `--flag` still means true.
+    ///
+    /// ```flag_config.default_true("color")```
+    pub fn default_true(self, field: &str) -> FlagConfiguration {
+        self.default(field, "true")
+    }
+
+    /// Make a `bool` flag true unless it's turned off with `--no-flag`
+    ///
+    /// Every switch can be turned off with `--no-flag`, but `usage` lists a
+    /// negated one only that way. A bare `--flag` is still accepted, with a
+    /// warning since it changes nothing, but giving it along with `--no-flag`
+    /// is an error.
+    ///
+    /// ```flag_config.negated("color")```
+    pub fn negated(mut self, field: &str) -> FlagConfiguration {
+        self.negated.insert(field.to_string());
+        self.default_true(field)
+    }
+
+    /// Read a flag from an environment variable when it isn't supplied
+    ///
+    /// The value is converted like one given on the command line, and an
+    /// error about it names the variable. A switch is on when its variable
+    /// is set to anything but an empty string or an "off" value like `0`.
+    ///
+    /// ```flag_config.env("token", "MYAPP_TOKEN")```
+    pub fn env(mut self, field: &str, var: &str) -> FlagConfiguration {
+        self.env_vars.insert(field.to_string(), var.to_string());
+
+        let source = box EnvSource { vars: self.env_vars.clone(), reader: self.env_reader.clone() };
+        self.replace_source(ENV_SOURCE, source)
+    }
+
+    /// Read environment variables with `lookup` instead of from the process's
+    /// environment, such as to test a program's flags without setting any
+    ///
+    /// It's used for the variables of `env`, `expand_env` and
+    /// `strict_spelling_from_env`.
+    ///
+    /// ```flag_config.env_lookup(fake_env)```
+    pub fn env_lookup(mut self, lookup: EnvLookup) -> FlagConfiguration {
+        self.env_reader = EnvReader(Some(lookup));
+
+        let source = box EnvSource { vars: self.env_vars.clone(), reader: self.env_reader.clone() };
+        self.replace_source(ENV_SOURCE, source)
+    }
+
+    /// Read flags that aren't supplied from a file of `field=value` lines
+    ///
+    /// Blank lines and lines starting with `#` are ignored, and a missing
+    /// file is treated as an empty one. A leading `~` in the path is the
+    /// home directory.
+    ///
+    /// ```flag_config.config_file("~/.myapp.conf")```
+    pub fn config_file(mut self, path: &str) -> FlagConfiguration {
+        self.config_file = Some(expand_home(path));
+
+        let source = box FileSource { path: self.config_file.clone(), values: RefCell::new(None) };
+        self.replace_source(FILE_SOURCE, source)
+    }
+
+    /// Look for flags that aren't supplied in `source`, after every other source
+    ///
+    /// ```flag_config.source(box Keyring)```
+    pub fn source(mut self, source: Box<ValueSource>) -> FlagConfiguration {
+        {
+            let SourceList(ref mut sources) = self.sources;
+            sources.push(Rc::new(source));
+        }
+
+        self
+    }
+
+    /// Look for flags that aren't supplied in `source` just before the
+    /// source called `name`, such as `"default"`; if there's no such source,
+    /// `source` goes last
+    ///
+    /// ```flag_config.source_before("default", box Keyring)```
+    pub fn source_before(mut self, name: &str, source: Box<ValueSource>) -> FlagConfiguration {
+        {
+            let SourceList(ref mut sources) = self.sources;
+            let pos = sources.iter().position(|s| s.name().as_slice() == name).unwrap_or(sources.len());
+            sources.insert(pos, Rc::new(source));
+        }
+
+        self
+    }
+
+    fn replace_source(mut self, name: &str, source: Box<ValueSource>) -> FlagConfiguration {
+        {
+            let SourceList(ref mut sources) = self.sources;
+
+            match sources.iter().position(|s| s.name().as_slice() == name) {
+                Some(pos) => *sources.get_mut(pos) = Rc::new(source),
+                None => ()
+            }
+        }
+
+        self
+    }
+
+    /// Require a flag that would otherwise be optional, such as an `Option` field
+    ///
+    /// ```flag_config.required("maybe")```
+    pub fn required(mut self, field: &str) -> FlagConfiguration {
+        self.required.insert(field.to_string());
+        self
+    }
+
+    /// Collect every occurrence of a `String` flag, joined with a separator
+    ///
+    /// ```flag_config.join_lines("line", "\n")```
+    pub fn join_lines(mut self, field: &str, separator: &str) -> FlagConfiguration {
+        self.separators.insert(field.to_string(), separator.to_string());
+        self
+    }
+
+    /// Split each value of a list flag on `delimiter`, so `--features a,b`
+    /// is the same as `--features a --features b`
+    ///
+    /// Empty pieces are kept, so `a,,b` has an empty element between `a` and
+    /// `b`, but an empty value has no elements at all: `--features=` gives an
+    /// empty list, even over a `default_list`.
+    ///
+    /// ```flag_config.delimited("features", ',')```
+    pub fn delimited(mut self, field: &str, delimiter: char) -> FlagConfiguration {
+        self.delimiters.insert(field.to_string(), delimiter);
+        self
+    }
+
+    pub fn short_for(&self, field: &str) -> Option<char> {
+        self.short_aliases.find_equiv(&field).and_then(|c| c.as_slice().head().map(|&c| c))
+    }
+
+    /// Every short alias of a field, in the order they were configured
+    pub fn shorts_for(&self, field: &str) -> Vec<char> {
+        self.short_aliases.find_equiv(&field).map(|c| c.clone()).unwrap_or(vec!())
+    }
+
+    /// How a field's flag is spelled on the command line and in every
+    /// message and help text, e.g. `--line-count`
+    pub fn display_name(&self, field: &str) -> String {
+        match self.long_names.find_equiv(&field) {
+            Some(name) => format!("--{}", name),
+            None => canonical_field_name(field)
+        }
+    }
+
+    /// The field whose `display_name` would be `flag`, if the flag could be
+    /// one; whether the struct has such a field isn't known here
+    pub fn field_for_flag(&self, flag: &str) -> Option<String> {
+        if !flag.starts_with("--") { return None }
+        let name = flag.slice_from(2);
+
+        match self.long_names.iter().find(|&(_, n)| n.as_slice() == name) {
+            Some((field, _)) => return Some(field.clone()),
+            None => ()
+        }
+
+        // a renamed field's own name isn't its flag any more
+        let field = name.replace("-", "_");
+        if self.long_names.contains_key(&field) { None } else { Some(field) }
+    }
+
+    pub fn is_negated(&self, field: &str) -> bool {
+        self.negated.contains_equiv(&field)
+    }
+
+    /// The flag that turns a `negated` switch off: `--no-color`
+    pub fn negated_name(&self, field: &str) -> String {
+        format!("--no-{}", self.display_name(field).as_slice().slice_from(2))
+    }
+
+    pub fn help_for(&self, field: &str) -> Option<String> {
+        self.help.find_equiv(&field).map(|h| h.clone())
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.description.as_ref().map(|d| d.clone())
+    }
+
+    pub fn separator_for(&self, field: &str) -> Option<String> {
+        self.separators.find_equiv(&field).map(|s| s.clone())
+    }
+
+    pub fn delimiter_for(&self, field: &str) -> Option<char> {
+        self.delimiters.find_equiv(&field).map(|&d| d)
+    }
+
+    pub fn program(&self) -> Option<String> {
+        self.program.clone()
+    }
+
+    pub fn program_version(&self) -> Option<String> {
+        self.version.as_ref().map(|v| v.clone())
+    }
+
+    /// The value of an environment variable, read as `env_lookup` says
+    pub fn env_value(&self, var: &str) -> Option<String> {
+        self.env_reader.get(var)
+    }
+
+    pub fn env_var_for(&self, field: &str) -> Option<String> {
+        self.env_vars.find_equiv(&field).map(|v| v.clone())
+    }
+
+    pub fn default_for(&self, field: &str) -> Option<String> {
+        self.defaults.find_equiv(&field).map(|d| d.clone())
+    }
+
+    pub fn list_default_for(&self, field: &str) -> Option<Vec<String>> {
+        self.list_defaults.find_equiv(&field).map(|d| d.clone())
+    }
+
+    pub fn map_default_for(&self, field: &str) -> Option<Vec<(String, String)>> {
+        self.map_defaults.find_equiv(&field).map(|d| d.clone())
+    }
+
+    pub fn appends_default(&self, field: &str) -> bool {
+        self.appended_defaults.contains_equiv(&field)
+    }
+
+    /// Whether any flag has `c` as its short alias
+    pub fn is_alias(&self, c: char) -> bool {
+        self.short_aliases.values().any(|a| a.contains(&c))
+    }
+
+    pub fn rest_field_name(&self) -> String {
+        self.rest_field.clone()
+    }
+
+    /// Every source, in the order they're consulted
+    pub fn sources(&self) -> Vec<Rc<Box<ValueSource>>> {
+        let SourceList(ref sources) = self.sources;
+        sources.clone()
+    }
+
+    /// The names of the sources, in the order they're consulted
+    pub fn source_names(&self) -> Vec<String> {
+        self.sources.names()
+    }
+
+    pub fn config_file_path(&self) -> Option<String> {
+        self.config_file.clone()
+    }
+
+    pub fn early_exit_fields(&self) -> Vec<String> {
+        self.early_exits.clone()
+    }
+
+    pub fn confirmation_field(&self) -> Option<String> {
+        self.confirmation.clone()
+    }
+
+    pub fn is_flattened(&self, field: &str) -> bool {
+        self.flattened.contains_equiv(&field)
+    }
+
+    pub fn usage_order(&self) -> UsageOrder {
+        self.order
+    }
+
+    pub fn accepted_syntax(&self) -> Syntax {
+        self.syntax.clone()
+    }
+
+    pub fn uses_starred_usage(&self) -> bool {
+        self.starred
+    }
+
+    pub fn help_tier_for(&self, field: &str) -> HelpTier {
+        self.tiers.find_equiv(&field).map(|t| *t).unwrap_or(Common)
+    }
+
+    pub fn is_hidden(&self, field: &str) -> bool {
+        self.hidden.contains_equiv(&field)
+    }
+
+    pub fn has_help_command(&self) -> bool {
+        self.help_command
+    }
+
+    pub fn offers_completion(&self) -> bool {
+        self.completion
+    }
+
+    /// Every `ignored_when` rule, as (field, other field, reason)
+    pub fn ignore_rules(&self) -> Vec<(String, String, String)> {
+        self.ignored.clone()
+    }
+
+    pub fn is_sensitive(&self, field: &str) -> bool {
+        self.sensitive.contains_equiv(&field)
+    }
+
+    pub fn sensitive_fields(&self) -> Vec<String> {
+        self.sensitive.iter().map(|f| f.clone()).collect()
+    }
+
+    pub fn dedups(&self, field: &str) -> bool {
+        self.deduped.contains_equiv(&field)
+    }
+
+    pub fn is_lenient_list(&self, field: &str) -> bool {
+        self.lenient_lists.contains_equiv(&field)
+    }
+
+    /// The `override_flag`, as it's given on the command line
+    pub fn override_flag_name(&self) -> Option<String> {
+        self.override_flag.as_ref().map(|name| {
+            if name.as_slice().char_len() == 1 { format!("-{}", name) } else { format!("--{}", name) }
+        })
+    }
+
+    pub fn assignments_field(&self) -> Option<String> {
+        self.assignments.clone()
+    }
+
+    pub fn completer_for(&self, field: &str) -> Option<Completer> {
+        self.completers.as_ref().and_then(|table| table.find(field).map(|c| *c))
+    }
+
+    /// What's wrong with `value` for `field` according to its `range` and
+    /// `validate` checks, if anything; like every error message, without a
+    /// period at the end
+    pub fn check_value(&self, field: &str, value: &str) -> Result<(), String> {
+        match self.ranges.find_equiv(&field) {
+            Some(&(min, max)) => match from_str::<f64>(value) {
+                Some(n) if n >= min as f64 && n <= max as f64 => (),
+                _ => return Err(format!("must be between {} and {}", min, max))
+            },
+            None => ()
+        }
+
+        match self.validators.as_ref().and_then(|table| table.find(field).map(|v| *v)) {
+            Some(validator) => validator(value).map_err(|problem| {
+                problem.as_slice().trim_right_chars('.').to_string()
+            }),
+            None => Ok(())
+        }
+    }
+
+    pub fn is_counted(&self, field: &str) -> bool {
+        self.counted.contains_equiv(&field)
+    }
+
+    /// Every short alias, as (field, alias), sorted by field
+    pub fn short_aliases(&self) -> Vec<(String, char)> {
+        let mut aliases: Vec<(String, char)> = self.short_aliases.iter().flat_map(|(f, cs)| {
+            cs.iter().map(|c| (f.clone(), *c)).collect::<Vec<(String, char)>>().move_iter()
+        }).collect();
+        aliases.sort();
+        aliases
+    }
+
+    /// The field name of the flag that reads values for `field` from files
+    pub fn values_file_for(&self, field: &str) -> Option<String> {
+        self.value_files.find_equiv(&field).map(|f| f.clone())
+    }
+
+    pub fn expands_env(&self, field: &str) -> bool {
+        self.expanded.contains_equiv(&field)
+    }
+
+    pub fn allows_missing_value(&self) -> bool {
+        self.missing_values
+    }
+
+    pub fn is_path(&self, field: &str) -> bool {
+        self.paths.contains_equiv(&field)
+    }
+
+    /// Whether a leading `~` in the flag's value is the home directory
+    pub fn expands_home(&self, field: &str) -> bool {
+        self.is_path(field) && !self.raw_paths.contains_equiv(&field)
+    }
+
+    pub fn expands_leniently(&self) -> bool {
+        self.lenient_expansion
+    }
+
+    pub fn rejects_unknown_flags(&self) -> bool {
+        self.reject_unknown
+    }
+
+    pub fn allows_argfiles(&self) -> bool {
+        self.argfiles
+    }
+
+    pub fn collects_errors(&self) -> bool {
+        self.collect_errors
+    }
+
+    pub fn ignores_case(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// Whether abbreviations are refused; see `strict_spelling`
+    pub fn spells_strictly(&self) -> bool {
+        self.strict_spelling || self.strict_spelling_var.as_ref()
+            .and_then(|var| self.env_reader.get(var.as_slice()))
+            .map(|value| value.as_slice() == "1")
+            .unwrap_or(false)
+    }
+
+    /// The duplicate policy for a field: its own, or else the global one
+    pub fn duplicate_policy_for(&self, field: &str) -> Option<DuplicatePolicy> {
+        self.field_duplicates.find_equiv(&field).map(|p| *p).or(self.duplicates)
+    }
+
+    /// The fields given their own policy with `duplicates_for`
+    pub fn duplicate_fields(&self) -> Vec<String> {
+        self.field_duplicates.keys().map(|k| k.clone()).collect()
+    }
+
+    /// What went wrong putting the configuration together, such as a
+    /// flattened struct configuring a field differently; decoding reports
+    /// these along with the configuration's other problems
+    pub fn problems(&self) -> Vec<String> {
+        self.problems.clone()
+    }
+
+    /**
+    Every field the configuration refers to by name, as (setting, field),
+    sorted by setting
+
+    A field that's renamed in the struct but not in the configuration would
+    otherwise quietly lose its settings, so decoding checks these first.
+    */
+    pub fn field_references(&self) -> Vec<(String, String)> {
+        let mut refs = vec!();
+
+        references(&mut refs, "short", self.short_aliases.keys());
+        references(&mut refs, "help", self.help.keys());
+        references(&mut refs, "flag_name", self.long_names.keys());
+        references(&mut refs, "default", self.defaults.keys());
+        references(&mut refs, "default_list", self.list_defaults.keys());
+        references(&mut refs, "default_map", self.map_defaults.keys());
+        references(&mut refs, "default_append", self.appended_defaults.iter());
+        references(&mut refs, "required", self.required.iter());
+        references(&mut refs, "join_lines", self.separators.keys());
+        references(&mut refs, "delimited", self.delimiters.keys());
+        references(&mut refs, "env", self.env_vars.keys());
+        references(&mut refs, "exits_early", self.early_exits.iter());
+        references(&mut refs, "confirm_with", self.confirmation.iter());
+        references(&mut refs, "lenient_list", self.lenient_lists.iter());
+        references(&mut refs, "counted", self.counted.iter());
+        references(&mut refs, "negated", self.negated.iter());
+
+        match self.completers {
+            Some(ref table) => references(&mut refs, "complete_with", table.fields().iter()),
+            None => ()
+        }
+        match self.validators {
+            Some(ref table) => references(&mut refs, "validate", table.fields().iter()),
+            None => ()
+        }
+        references(&mut refs, "range", self.ranges.keys());
+        references(&mut refs, "leading_assignments", self.assignments.iter());
+        references(&mut refs, "values_from_file", self.value_files.keys());
+        references(&mut refs, "dedup", self.deduped.iter());
+        references(&mut refs, "sensitive", self.sensitive.iter());
+        references(&mut refs, "expand_env", self.expanded.iter());
+        references(&mut refs, "path", self.paths.iter());
+        references(&mut refs, "no_home_expansion", self.raw_paths.iter());
+        references(&mut refs, "duplicates_for", self.field_duplicates.keys());
+        references(&mut refs, "flatten_field", self.flattened.iter());
+        references(&mut refs, "help_tier", self.tiers.keys());
+        references(&mut refs, "hidden", self.hidden.iter());
+        references(&mut refs, "ignored_when", self.ignored.iter().map(|&(ref field, _, _)| field));
+        references(&mut refs, "ignored_when", self.ignored.iter().map(|&(_, ref other, _)| other));
+
+        refs.sort();
+        refs.dedup();
+        refs
+    }
+
+    /// Whether a flag was marked with `required`, regardless of its type
+    pub fn requires(&self, field: &str) -> bool {
+        self.required.contains_equiv(&field)
+    }
+
+    /// Whether the user must supply a flag.
+    ///
+    /// `optional_type` says whether the field's type makes it optional on its
+    /// own (`bool` and `Option` fields). Both decoders and `describe` go through
+    /// this, so they always agree.
+    pub fn is_required(&self, field: &str, optional_type: bool) -> bool {
+        if self.required.contains_equiv(&field) { return true }
+        if self.defaults.contains_key_equiv(&field) { return false }
+        !optional_type
+    }
+}
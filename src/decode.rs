@@ -0,0 +1,5253 @@
+/*!
+Decoding command lines into flag structs
+
+`decode_args` is all most programs need; `decode` also returns what was
+left over, and `decode_into` and `decode_lines` cover updating an existing
+struct and reading many command lines from a file.
+*/
+
+use std::ascii::StrAsciiExt;
+use std::cmp;
+use std::collections::hashmap::{HashMap, HashSet};
+use std::os;
+use std::io::{Buffer, EndOfFile};
+use std::io::stdio;
+use std::io::process::{Command, InheritFd};
+use std::intrinsics::get_tydesc;
+use std::mem;
+use std::rc::Rc;
+use std::task;
+use serialize::{Decoder, Encodable};
+use libc;
+
+use {Flags, FlagParse, UsageParse};
+use config::{FlagConfig, FlagConfiguration, DuplicatePolicy, KeepFirst, KeepLast, Accumulate, Error};
+use config::{HelpTier, Common, Full, Expert, ENV_SOURCE, DEFAULT_SOURCE};
+use usage::{struct_layout, usage_with, full_usage, completions_with};
+use text::{shell_split, format_error_annotated, finish_block, shortened, REDACTED};
+use util::{split_flag, split_assignment, is_negative_number, expand_vars, expand_home, read_values, read_args, content_line, parse_switch, SWITCH_SPELLINGS};
+use util::{ValueKind, Unsigned, Signed, Float, Character, Switch, Text};
+use encode::{Encoded, EncodedSwitch, EncodedValue, EncodedList, EncodedNone};
+use token::{Token, tokenize, LongFlag, ShortFlag, Cluster, Value, Positional, Separator};
+
+#[doc(hidden)]
+pub use encode::FlagEncoder;
+
+#[deriving(Show, PartialEq)]
+enum DecoderState {
+    Processing,
+    ProcessingRest(int),
+    ProcessingList(int)
+}
+
+#[deriving(Show, PartialEq)]
+pub struct FlagDecoder {
+    args: Vec<String>,
+    source: Vec<Token>,
+    consumed: Vec<Token>,
+    value_index: Option<uint>,
+    layout: Rc<Layout>,
+    current_field: Option<String>,
+    error: Option<String>,
+    config: Rc<FlagConfiguration>,
+    state: DecoderState,
+    existing: HashMap<String, Encoded>,
+    list: Vec<(String, Option<uint>)>,
+    warnings: Vec<HammerWarning>,
+    probe: Option<Option<FlagMatch>>,
+    resolved: Option<String>,
+    from_env: bool,
+    trace: Vec<String>,
+    given: HashSet<String>,
+    depth: uint,
+    overrides: Vec<(String, String, uint)>,
+    assignments: Vec<(String, String)>,
+    origins: HashMap<String, String>,
+    clusters: HashSet<uint>,
+    separator: Option<uint>,
+    errors: Vec<HammerError>,
+    broken: Option<HammerError>,
+    placeholder: bool,
+    done: bool
+}
+
+// where the current field was found in the source, the value that was
+// joined to it with `=`, if any, and whether its short alias was found in a
+// cluster like `-vc`
+struct FlagMatch {
+    pos: uint,
+    value: Option<String>,
+    clustered: bool
+}
+
+impl FlagMatch {
+    fn new(pos: uint, value: Option<&str>) -> FlagMatch {
+        FlagMatch { pos: pos, value: value.map(|v| v.to_string()), clustered: false }
+    }
+}
+
+// what a struct looks like on the command line under one configuration:
+// its field names, every flag it accepts, the short aliases that take a
+// value, and the flags of each field, so matching an argument doesn't go
+// back to the configuration for them
+#[deriving(Show, PartialEq)]
+struct Layout {
+    names: Vec<String>,
+    paths: Vec<String>,
+    flags: Vec<String>,
+    value_shorts: Vec<char>,
+    fields: HashMap<String, FieldFlags>
+}
+
+#[deriving(Show, PartialEq)]
+struct FieldFlags {
+    flag: String,
+    negated: String,
+    shorts: Vec<char>,
+    counted: bool,
+    kind: ValueKind
+}
+
+impl Layout {
+    fn of<T: UsageParse>(config: &FlagConfiguration) -> Layout {
+        let (names, paths, flags, value_shorts, kinds) = struct_layout::<T>(config.clone());
+        let kinds: HashMap<String, ValueKind> = kinds.move_iter().collect();
+
+        let fields = names.iter().map(|name| {
+            let name = name.as_slice();
+
+            (name.to_string(), FieldFlags {
+                flag: config.display_name(name),
+                negated: config.negated_name(name),
+                shorts: config.shorts_for(name),
+                counted: config.is_counted(name),
+                kind: kinds.find_equiv(&name).map(|&k| k).unwrap_or(Text)
+            })
+        }).collect();
+
+        Layout { names: names, paths: paths, flags: flags, value_shorts: value_shorts, fields: fields }
+    }
+}
+
+impl FlagDecoder {
+    /// A decoder for the configuration of `T`; if that configuration is
+    /// broken, decoding returns the `ConfigError`
+    pub fn new<T: FlagConfig + UsageParse>(args: &[String]) -> FlagDecoder {
+        let (config, broken) = match checked_config::<T>() {
+            Ok(config) => (config, None),
+            Err(err) => (FlagConfiguration::new(), Some(err))
+        };
+
+        let mut decoder = FlagDecoder::configured::<T>(args, config);
+        decoder.broken = broken;
+        decoder
+    }
+
+    fn configured<T: UsageParse>(args: &[String], config: FlagConfiguration) -> FlagDecoder {
+        let layout = Layout::of::<T>(&config);
+        FlagDecoder::with_config(args, Rc::new(config), Rc::new(layout))
+    }
+
+    /*
+        Nothing after the first `--` is ever a flag, so it's kept out of the
+        source altogether and only handed to the rest field and `remaining`.
+        The `--` itself is dropped. Arguments move from `source` to
+        `consumed` as fields take them.
+    */
+    fn with_config(args: &[String], config: Rc<FlagConfiguration>, layout: Rc<Layout>) -> FlagDecoder {
+        let tokens = tokenize(args, config.accepted_syntax().passthrough);
+        let separator = tokens.iter().position(|token| token.kind == Separator);
+
+        FlagDecoder{
+            args: Vec::from_slice(args),
+            source: tokens.move_iter().take_while(|token| token.kind != Separator).collect(),
+            consumed: vec!(),
+            value_index: None,
+            layout: layout,
+            current_field: None,
+            error: None,
+            config: config,
+            state: Processing,
+            existing: HashMap::new(),
+            list: vec!(),
+            warnings: vec!(),
+            probe: None,
+            resolved: None,
+            from_env: false,
+            trace: vec!(),
+            given: HashSet::new(),
+            depth: 0,
+            overrides: vec!(),
+            assignments: vec!(),
+            origins: HashMap::new(),
+            clusters: HashSet::new(),
+            separator: separator,
+            errors: vec!(),
+            broken: None,
+            placeholder: false,
+            done: false
+        }
+    }
+
+    /// The arguments no field consumed, in the order they were given,
+    /// followed by the ones after `--`
+    pub fn remaining(&self) -> Vec<String> {
+        self.check_remaining();
+
+        let left: Vec<String> = self.source.iter().map(|token| token.raw.clone()).collect();
+        left.append(self.passthrough())
+    }
+
+    // the arguments after the first `--`
+    fn passthrough<'a>(&'a self) -> &'a [String] {
+        match self.separator {
+            Some(separator) => self.args.slice_from(separator + 1),
+            None => &[]
+        }
+    }
+
+    // an argument of the rest field: what's left of the source, then the
+    // arguments after `--`
+    fn rest_arg(&self, i: uint) -> (String, uint) {
+        if i < self.source.len() {
+            (self.source[i].raw.clone(), self.source[i].index)
+        } else {
+            (self.passthrough()[i - self.source.len()].clone(), self.separator.unwrap() + 1 + i - self.source.len())
+        }
+    }
+
+    /*
+        The arguments left over are exactly the ones that weren't consumed,
+        in their original order, so wrappers can hand them on to another
+        program. Every token knows its original position, which makes this
+        cheap to check.
+    */
+    fn check_remaining(&self) {
+        debug_assert!(self.source.len() + self.consumed.len() == self.separator.unwrap_or(self.args.len()),
+            "arguments were lost or taken twice: {} left, {} consumed", self.source, self.consumed);
+        debug_assert!(self.source.as_slice().windows(2).all(|w| w[0].index < w[1].index),
+            "leftover arguments were reordered: {}", self.source);
+        debug_assert!(self.source.iter().all(|token| token.raw == self.args[token.index] || self.clusters.contains(&token.index)),
+            "leftover arguments don't match the command line: {}", self.source);
+    }
+
+    /// What each argument turned out to be, once decoding is done
+    pub fn classify(&self) -> ClassifiedArgs {
+        let mut classified = ClassifiedArgs {
+            positionals: vec!(),
+            assignments: self.assignments.clone(),
+            overrides: self.overrides.iter().map(|&(ref field, ref value, _)| (field.clone(), value.clone())).collect(),
+            unknown_flags: vec!(),
+            passthrough: vec!()
+        };
+
+        self.check_remaining();
+
+        for token in self.source.iter() {
+            if token.is_flag() {
+                classified.unknown_flags.push(token.raw.clone());
+            } else {
+                classified.positionals.push(token.raw.clone());
+            }
+        }
+
+        classified.passthrough.push_all(self.passthrough());
+        classified
+    }
+
+    /// Problems that didn't stop decoding, such as skipped list elements
+    pub fn warnings(&self) -> Vec<HammerWarning> {
+        self.warnings.clone()
+    }
+
+    /// Where each field was looked for, in order, as `--flag: source`, where
+    /// the source is `cli` or the name of a `ValueSource`
+    pub fn trace(&self) -> Vec<String> {
+        self.trace.clone()
+    }
+
+    /// Every problem found so far, when decoding with `collect_errors`
+    pub fn errors(&self) -> Vec<HammerError> {
+        self.errors.clone()
+    }
+
+    /// Whether the last decode failed
+    pub fn errored(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// The message of the error the last decode failed with, if it did
+    pub fn error_message<'a>(&'a self) -> Option<&'a str> {
+        self.error.as_ref().map(|e| e.as_slice())
+    }
+
+    /*
+        These helper functions encapsulate the different ways of using a field name:
+        the field name prefixed by `--`, its short alias, an unambiguous prefix of the
+        long form, and any of the long forms with the value joined by `=`
+        (`--count=5`, `--co=5`). These methods should be the only place that needs to
+        be updated to support new forms. The long form itself always comes from
+        `FlagConfiguration::display_name`, which knows about `flag_name`.
+    */
+
+    fn display_name(&self) -> String {
+        let field = self.current_field.get_ref().as_slice();
+
+        match self.layout.fields.find_equiv(&field) {
+            Some(flags) => flags.flag.clone(),
+            None => self.config.display_name(field)
+        }
+    }
+
+    // Like `display_name`, these come from the layout; a field it doesn't
+    // know, in a struct decoded by hand, is looked up in the configuration.
+
+    fn negated_name(&self, field: &str) -> String {
+        match self.layout.fields.find_equiv(&field) {
+            Some(flags) => flags.negated.clone(),
+            None => self.config.negated_name(field)
+        }
+    }
+
+    fn shorts_for(&self, field: &str) -> Vec<char> {
+        match self.layout.fields.find_equiv(&field) {
+            Some(flags) => flags.shorts.clone(),
+            None => self.config.shorts_for(field)
+        }
+    }
+
+    fn is_counted(&self, field: &str) -> bool {
+        match self.layout.fields.find_equiv(&field) {
+            Some(flags) => flags.counted,
+            None => self.config.is_counted(field)
+        }
+    }
+
+    fn field_required(&self, optional_type: bool) -> bool {
+        self.config.is_required(self.current_field.get_ref().as_slice(), optional_type)
+    }
+
+    // the value the field had before decoding, when decoding in place
+    fn existing(&self) -> Option<Encoded> {
+        self.current_field.as_ref().and_then(|f| self.existing.find(f)).map(|e| e.clone())
+    }
+
+    fn missing<T>(&self) -> HammerResult<T> {
+        HammerError::new(format!("{} is required", self.display_name()))
+    }
+
+    // looks for the current field on the command line; `read_option` keeps
+    // what it found in `probe` so the value's own read doesn't look again
+    fn probe(&mut self) -> HammerResult<Option<FlagMatch>> {
+        match self.probe.take() {
+            Some(found) => return Ok(found),
+            None => ()
+        }
+
+        let entry = format!("{}: cli", self.display_name());
+        self.trace.push(entry);
+        self.field_pos()
+    }
+
+    // the flag comes from the layout without a copy, since this runs for
+    // every field; only a field the layout doesn't know has it worked out
+    fn field_pos(&self) -> HammerResult<Option<FlagMatch>> {
+        let field = self.current_field.get_ref().as_slice();
+
+        match self.layout.fields.find_equiv(&field) {
+            Some(flags) => self.flag_pos(flags.flag.as_slice()),
+            None => self.flag_pos(self.config.display_name(field).as_slice())
+        }
+    }
+
+    fn flag_pos(&self, canonical: &str) -> HammerResult<Option<FlagMatch>> {
+        for (pos, token) in self.source.iter().enumerate() {
+            if token.kind != LongFlag { continue }
+
+            let (name, value) = self.split_token(token.raw.as_slice());
+
+            if same_flag(&*self.config, name, canonical) {
+                return Ok(Some(FlagMatch::new(pos, value)));
+            }
+        }
+
+        match self.short_pos() {
+            Some(found) => return Ok(Some(found)),
+            None => ()
+        }
+
+        self.abbreviated_pos(canonical)
+    }
+
+    /*
+        `-v` on its own, or inside a cluster of short aliases like `-vc`,
+        including what's left of a cluster once other flags were taken out.
+        Everything after a short alias that takes a value is that value, as
+        in `-n10`, so a cluster is only searched up to the first one. Like
+        long flags, short ones can be joined to their value with `=`.
+    */
+    fn short_pos(&self) -> Option<FlagMatch> {
+        let field = self.current_field.get_ref();
+        let syntax = self.config.accepted_syntax();
+
+        let shorts = self.shorts_for(field.as_slice());
+        if shorts.is_empty() { return None }
+
+        for (pos, token) in self.source.iter().enumerate() {
+            match token.kind {
+                ShortFlag | Cluster => (),
+                // `-5` is a value, unless this flag's short alias is a digit
+                Positional if is_negative_number(token.raw.as_slice()) && shorts.iter().any(|c| c.is_digit()) => (),
+                _ => continue
+            }
+
+            let remainder = self.clusters.contains(&token.index);
+            let token = token.raw.as_slice();
+
+            if token.len() == 2 {
+                if shorts.contains(&token.char_at(1)) {
+                    return Some(FlagMatch { pos: pos, value: None, clustered: remainder });
+                }
+
+                continue;
+            }
+
+            let first = token.char_at(1);
+
+            if syntax.equals && !remainder && shorts.contains(&first) && token.as_bytes()[2] == '=' as u8 {
+                return Some(FlagMatch::new(pos, Some(token.slice_from(3))));
+            }
+
+            if syntax.attached_values && !remainder && shorts.contains(&first) && self.layout.value_shorts.contains(&first) {
+                return Some(FlagMatch::new(pos, Some(token.slice_from(2))));
+            }
+
+            if !syntax.clustering { continue }
+
+            for short in token.slice_from(1).chars() {
+                if shorts.contains(&short) {
+                    return Some(FlagMatch { pos: pos, value: None, clustered: true });
+                }
+
+                if self.layout.value_shorts.contains(&short) { break }
+            }
+        }
+
+        None
+    }
+
+    // the flag and the value joined to it with `=`, when that's accepted
+    fn split_token<'a>(&self, token: &'a str) -> (&'a str, Option<&'a str>) {
+        if self.config.accepted_syntax().equals {
+            split_flag(token)
+        } else {
+            (token, None)
+        }
+    }
+
+    // `--co` (or `--co=red`) matches `--color` as long as no other flag starts
+    // with `--co`; if one does, the abbreviation is an error rather than a guess.
+    // With `strict_spelling`, every abbreviation is an error.
+    fn abbreviated_pos(&self, canonical: &str) -> HammerResult<Option<FlagMatch>> {
+        if !self.config.accepted_syntax().abbreviation {
+            return Ok(None);
+        }
+
+        for (pos, token) in self.source.iter().enumerate() {
+            if token.kind != LongFlag { continue }
+
+            let (name, value) = self.split_token(token.raw.as_slice());
+
+            if name.len() <= 2 || !abbreviates(&*self.config, name, canonical) { continue }
+
+            // an exact match for some other flag is never an abbreviation
+            if self.layout.flags.iter().any(|f| same_flag(&*self.config, name, f.as_slice())) { continue }
+
+            let candidates: Vec<&str> = self.layout.flags.iter()
+                .map(|f| f.as_slice())
+                .filter(|f| abbreviates(&*self.config, name, *f))
+                .collect();
+
+            if candidates.len() > 1 {
+                let err = HammerError::new(format!("{} is ambiguous; it could be {}", name, candidates.connect(", ")));
+                return err.map_err(|e| e.at_index(token.index));
+            }
+
+            if self.config.spells_strictly() {
+                let err = HammerError::new(format!("{} is an abbreviation; spell it out as {}", name, canonical));
+                return err.map_err(|e| e.at_index(token.index));
+            }
+
+            return Ok(Some(FlagMatch::new(pos, value)));
+        }
+
+        Ok(None)
+    }
+
+    fn take_value(&mut self, found: &FlagMatch) -> HammerResult<String> {
+        let index = self.source[found.pos].index;
+
+        if found.clustered {
+            let short = self.shorts_for(self.current_field.get_ref().as_slice())[0];
+            let err = HammerError::new(format!("-{} takes a value, so it can't be combined with other flags in {}", short, self.args[index]));
+            return err.map_err(|e| e.at_index(index));
+        }
+
+        match found.value {
+            Some(ref val) if val.is_empty() && self.source[found.pos].kind != LongFlag => {
+                let err = HammerError::new(format!("{} needs a value after {}", self.display_name(), self.source[found.pos].raw));
+                return err.map_err(|e| e.at_index(index));
+            },
+            Some(ref val) => {
+                self.value_index = Some(index);
+                self.remove_val_field(found);
+                return Ok(val.clone());
+            },
+            None => ()
+        }
+
+        // a flag at the very end has nothing following it
+        if found.pos + 1 >= self.source.len() {
+            self.value_index = Some(index);
+
+            if !self.config.allows_missing_value() {
+                return self.invalid(format!("{} is missing a following string", self.display_name()));
+            }
+
+            self.remove_token(found.pos);
+            return Ok(String::new());
+        }
+
+        let (at, val) = {
+            let value = self.source.get_mut(found.pos + 1);
+            value.kind = Value;
+            (value.index, value.raw.clone())
+        };
+
+        self.value_index = Some(at);
+        self.remove_val_field(found);
+
+        Ok(val)
+    }
+
+    // the value of a flag that wasn't supplied on the command line
+    fn missing_value(&mut self) -> HammerResult<String> {
+        match self.existing() {
+            Some(EncodedValue(value)) => return Ok(value),
+            _ => ()
+        }
+
+        let field = self.current_field.get_ref().to_string();
+
+        match self.resolve_value(field.as_slice()) {
+            Some(value) => Ok(value),
+            None => self.missing()
+        }
+    }
+
+    /*
+        Where a value comes from when its flag isn't on the command line: the
+        configuration's sources, in order, which by default are the field's
+        environment variable, the config file, then the field's default. A
+        field marked `required` must be supplied by the user somewhere, so its
+        default is never used.
+    */
+    fn resolve_value(&mut self, field: &str) -> Option<String> {
+        let flag = self.config.display_name(field);
+        let required = self.config.requires(field);
+        self.from_env = false;
+
+        match self.overrides.iter().rev().find(|&&(ref f, _, _)| f.as_slice() == field) {
+            Some(&(_, ref value, index)) => {
+                let origin = format!("from {} at arg {}", self.config.override_flag_name().unwrap(), index + 1);
+                self.trace.push(format!("{}: override", flag));
+                self.origins.insert(field.to_string(), origin);
+                self.value_index = Some(index);
+                return Some(value.clone());
+            },
+            None => ()
+        }
+
+        for source in self.config.sources().iter() {
+            let name = source.name();
+            if required && name.as_slice() == DEFAULT_SOURCE { continue }
+            if !source.covers(field) { continue }
+
+            self.trace.push(format!("{}: {}", flag, name));
+
+            match source.lookup(field) {
+                Some(value) => {
+                    let origin = match self.config.env_var_for(field) {
+                        _ if name.as_slice() == DEFAULT_SOURCE => name.clone(),
+                        Some(var) if name.as_slice() == ENV_SOURCE => format!("from env {}", var),
+                        _ => format!("from {}", name)
+                    };
+
+                    self.origins.insert(field.to_string(), origin);
+                    self.from_env = name.as_slice() == ENV_SOURCE;
+                    self.value_index = None;
+                    return Some(value);
+                },
+                None => ()
+            }
+        }
+
+        None
+    }
+
+    // every occurrence of the flag, joined into a single value
+    fn read_joined(&mut self, separator: &str) -> HammerResult<String> {
+        let values = try!(self.values(true));
+
+        if values.is_empty() {
+            return self.missing_value();
+        }
+
+        let values: Vec<String> = values.move_iter().map(|(value, _)| value).collect();
+        Ok(values.connect(separator))
+    }
+
+    fn duplicate_policy(&self, list: bool) -> DuplicatePolicy {
+        let field = self.current_field.get_ref();
+
+        // every occurrence of a counted flag counts
+        if self.is_counted(field.as_slice()) {
+            return Accumulate;
+        }
+
+        match self.config.duplicate_policy_for(field.as_slice()) {
+            Some(policy) => policy,
+            None if list => Accumulate,
+            None => KeepFirst
+        }
+    }
+
+    /*
+        Every occurrence of the current field that its duplicate policy lets
+        through, consumed with `take`. Occurrences that aren't let through by
+        `KeepFirst` stay in the source.
+    */
+    fn occurrences<T>(&mut self, list: bool, take: |&mut FlagDecoder, &FlagMatch| -> T) -> HammerResult<Vec<T>> {
+        let policy = self.duplicate_policy(list);
+        let mut found = vec!();
+        let mut positions = vec!();
+
+        loop {
+            let occurrence = match try!(self.probe()) {
+                Some(occurrence) => occurrence,
+                None => break
+            };
+
+            if policy == Error && found.len() == 1 {
+                let err = HammerError::new(format!("{} was given more than once", self.display_name()));
+                return err.map_err(|e| e.at_index(self.source[occurrence.pos].index));
+            }
+
+            positions.push(self.source[occurrence.pos].index);
+            found.push(take(self, &occurrence));
+
+            if policy == KeepFirst {
+                match self.field_pos() {
+                    Ok(Some(_)) => {
+                        let message = format!("{} was given more than once; only the first was used", self.display_name());
+                        self.warn(DuplicateIgnored, message);
+                    },
+                    _ => ()
+                }
+
+                break;
+            }
+        }
+
+        if policy == KeepLast && found.len() > 1 {
+            found = vec!(found.pop().unwrap());
+        }
+
+        if !found.is_empty() {
+            let field = self.current_field.get_ref().to_string();
+            let used = if policy == KeepLast { positions[positions.len() - 1] } else { positions[0] };
+
+            self.origins.insert(field.clone(), format!("from {} at arg {}", self.display_name(), used + 1));
+            self.given.insert(field);
+        }
+
+        Ok(found)
+    }
+
+    /*
+        The number of times a `counted` flag was given. Short aliases can be
+        repeated in a single argument, so `-vv` counts twice. When the flag
+        isn't given, its value comes from the sources like any integer, or
+        is 0.
+    */
+    fn read_count(&mut self) -> HammerResult<uint> {
+        let counts = try!(self.occurrences(true, |d, found| d.remove_counted(found)));
+
+        if !counts.is_empty() {
+            return Ok(counts.iter().fold(0, |total, &count| total + count));
+        }
+
+        match self.existing() {
+            Some(EncodedValue(value)) => return self.count_value(value),
+            _ => ()
+        }
+
+        let field = self.current_field.get_ref().to_string();
+
+        match self.resolve_value(field.as_slice()) {
+            Some(value) => self.count_value(value),
+            None => Ok(0)
+        }
+    }
+
+    fn count_value(&self, value: String) -> HammerResult<uint> {
+        match from_str(value.as_slice()) {
+            Some(count) => Ok(count),
+            None => self.unconverted(value.as_slice(), Unsigned)
+        }
+    }
+
+    // the values of the current field, along with the indices of their arguments
+    fn values(&mut self, list: bool) -> HammerResult<Vec<(String, uint)>> {
+        let taken = try!(self.occurrences(list, |d, found| {
+            d.take_value(found).map(|value| (value, d.value_index.unwrap()))
+        }));
+
+        let mut values = vec!();
+
+        for value in taken.move_iter() {
+            values.push(try!(value));
+        }
+
+        Ok(values)
+    }
+
+    // the string value of the current field, wherever it comes from
+    fn raw_value(&mut self) -> HammerResult<String> {
+        if self.placeholder {
+            return Ok("0".to_string());
+        }
+
+        match self.state {
+            ProcessingRest(i) => {
+                let (value, index) = self.rest_arg(i as uint);
+                self.value_index = Some(index);
+                return Ok(value)
+            },
+            ProcessingList(i) => {
+                let (value, index) = self.list[i as uint].clone();
+                self.value_index = index;
+                return Ok(value)
+            },
+            _ => ()
+        }
+
+        // `read_option` already found the value somewhere other than the command line
+        match self.resolved.take() {
+            Some(value) => return Ok(value),
+            None => ()
+        }
+
+        let field = self.current_field.get_ref().to_string();
+
+        match self.config.separator_for(field.as_slice()) {
+            Some(separator) => return self.read_joined(separator.as_slice()),
+            None => ()
+        }
+
+        match try!(self.values(false)).pop() {
+            Some((value, index)) => {
+                self.value_index = Some(index);
+                Ok(value)
+            },
+            None => self.missing_value()
+        }
+    }
+
+    // expands environment variables in the value of a field configured with `expand_env`
+    fn expand_value(&self, value: String) -> HammerResult<String> {
+        let field = self.current_field.get_ref();
+
+        let value = if self.config.expands_home(field.as_slice()) {
+            expand_home(value.as_slice())
+        } else {
+            value
+        };
+
+        if !self.config.expands_env(field.as_slice()) {
+            return Ok(value);
+        }
+
+        match expand_vars(value.as_slice(), self.config.expands_leniently(), |var| self.config.env_value(var)) {
+            Ok(expanded) => Ok(expanded),
+            Err(var) => self.invalid(format!("{}: ${} is not set", self.display_name(), var))
+        }
+    }
+
+    // a list field other than the rest field collects every occurrence of its
+    // flag, in order; each element is then converted by its own read_*
+    fn read_list<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> {
+        self.list = try!(self.values(true)).move_iter().map(|(value, index)| (value, Some(index))).collect();
+
+        let field = self.current_field.get_ref().to_string();
+        let lenient = self.config.is_lenient_list(field.as_slice());
+
+        if self.list.is_empty() {
+            self.list = self.overrides.iter()
+                .filter(|&&(ref f, _, _)| *f == field)
+                .map(|&(_, ref value, index)| (value.clone(), Some(index)))
+                .collect();
+        }
+
+        // every piece of a delimited value points at the value's argument
+        match self.config.delimiter_for(field.as_slice()) {
+            Some(delimiter) => {
+                for (value, index) in mem::replace(&mut self.list, vec!()).move_iter() {
+                    if value.is_empty() { continue }
+                    self.list.extend(value.as_slice().split(delimiter).map(|piece| (piece.to_string(), index)));
+                }
+            },
+            None => ()
+        }
+
+        match self.config.values_file_for(field.as_slice()) {
+            Some(flag) => try!(self.read_value_files(flag)),
+            None => ()
+        }
+
+        match self.config.list_default_for(field.as_slice()) {
+            Some(defaults) => self.add_defaults(field.as_slice(), defaults.move_iter().map(|value| (value, None)).collect()),
+            None => ()
+        }
+
+        if self.config.dedups(field.as_slice()) {
+            let mut seen = HashSet::new();
+            self.list.retain(|&(ref value, _)| seen.insert(value.clone()));
+        }
+
+        loop {
+            let len = self.list.len();
+            self.state = ProcessingList(-1);
+
+            match f(self, len) {
+                // a lenient list drops the element that failed and tries again
+                Err(ref err) if lenient => {
+                    let failed = match self.state {
+                        ProcessingList(i) if i >= 0 => i as uint,
+                        _ => return Err(err.clone())
+                    };
+
+                    let (value, _) = self.list.remove(failed).unwrap();
+                    // the error already starts with the flag's name
+                    let prefix = self.flag_prefix();
+                    let reason = err.message.as_slice();
+                    let reason = if reason.starts_with(prefix.as_slice()) { reason.slice_from(prefix.len()) } else { reason };
+
+                    let message = format!("{}skipped {} ({})", prefix, self.shown(value.as_slice()), reason);
+                    self.warn(SkippedListElement, message);
+                },
+                ret => {
+                    self.state = Processing;
+                    return ret;
+                }
+            }
+        }
+    }
+
+    // the default elements of a list or map go in front of the given ones
+    // with `default_append`, and otherwise only when none were given
+    fn add_defaults(&mut self, field: &str, defaults: Vec<(String, Option<uint>)>) {
+        if self.list.is_empty() && !self.given.contains_equiv(&field) {
+            self.origins.insert(field.to_string(), DEFAULT_SOURCE.to_string());
+        } else if !self.config.appends_default(field) {
+            return;
+        }
+
+        self.list = defaults.append(self.list.as_slice());
+    }
+
+    /*
+        The values in the files named by a list's `values_from_file` flag,
+        added to `list` after the ones given directly. The flag is looked up
+        like a field of its own, and each value points at the argument that
+        named its file.
+    */
+    fn read_value_files(&mut self, flag: String) -> HammerResult<()> {
+        let field = self.current_field.take();
+        self.current_field = Some(flag);
+        self.probe = None;
+
+        let paths = self.values(true);
+        let flag = self.display_name();
+        self.current_field = field;
+
+        for (path, index) in try!(paths).move_iter() {
+            match read_values(path.as_slice()) {
+                Ok(values) => self.list.extend(values.move_iter().map(|value| (value, Some(index)))),
+                Err(e) => {
+                    self.value_index = Some(index);
+                    return self.invalid(format!("{}: could not read {} ({})", flag, path, e.desc));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+        Takes every `override_flag` argument and its `field=value` out of the
+        source before any field is read, so they're never mistaken for
+        values or positionals. Field names may use dashes or underscores.
+    */
+    fn take_overrides(&mut self) -> HammerResult<()> {
+        let flag = match self.config.override_flag_name() {
+            Some(flag) => flag,
+            None => return Ok(())
+        };
+
+        let mut pos = 0;
+
+        while pos < self.source.len() {
+            let (name, joined) = match self.split_token(self.source[pos].raw.as_slice()) {
+                (name, joined) => (name.to_string(), joined.map(|j| j.to_string()))
+            };
+
+            if name != flag {
+                pos += 1;
+                continue;
+            }
+
+            let (assignment, index) = match joined {
+                Some(assignment) => (assignment, self.source[pos].index),
+                None if pos + 1 < self.source.len() => {
+                    let next = (self.source[pos + 1].raw.clone(), self.source[pos + 1].index);
+                    self.remove_token(pos);
+                    next
+                },
+                None => {
+                    let err = HammerError::new(format!("{} is missing a following string", flag));
+                    return err.map_err(|e| e.at_index(self.source[pos].index));
+                }
+            };
+
+            self.remove_token(pos);
+
+            let (name, value) = match assignment.as_slice().find('=') {
+                Some(i) => (assignment.as_slice().slice_to(i).to_string(), assignment.as_slice().slice_from(i + 1).to_string()),
+                None => {
+                    let err = HammerError::new(format!("{} expects field=value", flag));
+                    return err.map_err(|e| e.at_index(index));
+                }
+            };
+
+            // fields are named the way their flags are spelled
+            let long = format!("--{}", name);
+
+            let field = match self.config.field_for_flag(long.as_slice()) {
+                Some(field) if self.layout.flags.contains(&long) => field,
+                _ => {
+                    let err = HammerError::new(format!("{}: unknown field {}", flag, name));
+                    return err.map_err(|e| e.at_index(index));
+                }
+            };
+
+            self.overrides.push((field, value, index));
+        }
+
+        Ok(())
+    }
+
+    fn read_absent_option<T>(&mut self, f: |&mut FlagDecoder, bool| -> HammerResult<T>) -> HammerResult<T> {
+        let field = self.current_field.get_ref().to_string();
+
+        self.resolved = self.resolve_value(field.as_slice());
+
+        if self.resolved.is_some() {
+            f(self, true)
+        } else if self.field_required(true) {
+            self.missing()
+        } else {
+            f(self, false)
+        }
+    }
+
+    // the `confirm_with` flag, if it should have been given; nobody can be
+    // asked when stdin isn't a terminal, so then it never needs to be
+    fn unconfirmed(&self, tty: bool) -> Option<String> {
+        self.config.confirmation_field().and_then(|field| {
+            if tty && !self.given.contains(&field) {
+                Some(self.config.display_name(field.as_slice()))
+            } else {
+                None
+            }
+        })
+    }
+
+    // flags given on the command line that have no effect because of another one
+    fn warn_ignored(&mut self) {
+        for (field, other, reason) in self.config.ignore_rules().move_iter() {
+            if self.given.contains(&field) && self.given.contains(&other) {
+                let message = format!("{} is ignored because {} was given ({})",
+                    self.config.display_name(field.as_slice()), self.config.display_name(other.as_slice()), reason);
+                self.warnings.push(HammerWarning { kind: IgnoredFlag, message: message, field: Some(field) });
+            }
+        }
+    }
+
+    // a warning about the current field
+    fn warn(&mut self, kind: WarningKind, message: String) {
+        let field = self.current_field.clone();
+        self.warnings.push(HammerWarning { kind: kind, message: message, field: field });
+    }
+
+    // a value of the current field as it may appear in messages: redacted
+    // when it's sensitive, and cut short when it's long
+    fn shown(&self, value: &str) -> String {
+        if self.config.is_sensitive(self.current_field.get_ref().as_slice()) {
+            REDACTED.to_string()
+        } else {
+            shortened(value)
+        }
+    }
+
+    // the `range` and `validate` checks of the current field; a value that
+    // wasn't given on the command line says where it came from instead
+    fn check_value(&self, value: &str) -> HammerResult<()> {
+        let field = self.current_field.get_ref().as_slice();
+
+        match self.config.check_value(field, value) {
+            Ok(()) => Ok(()),
+            Err(problem) => {
+                self.invalid(format!("{}: {} {}{}", self.display_name(), self.shown(value), problem, self.origin_note()))
+            }
+        }
+    }
+
+    // where a value that wasn't given on the command line came from, as in
+    // ` (from env MYAPP_PORT)`; nothing for one that was
+    fn origin_note(&self) -> String {
+        let field = self.current_field.get_ref().as_slice();
+
+        match (self.value_index, self.origins.find_equiv(&field)) {
+            (None, Some(origin)) => format!(" ({})", origin),
+            _ => String::new()
+        }
+    }
+
+    // names the flag a bad value was given to, as in `--count: `; positionals
+    // have no flag to name
+    fn flag_prefix(&self) -> String {
+        let field = self.current_field.get_ref().to_string();
+
+        if field == self.config.rest_field_name() {
+            String::new()
+        } else {
+            format!("{}: ", self.display_name())
+        }
+    }
+
+    // a value that isn't of the field's type
+    fn unconverted<T>(&self, value: &str, kind: ValueKind) -> HammerResult<T> {
+        self.not_converted(value, kind, format!("could not convert {} to {}", self.shown(value), kind.description()))
+    }
+
+    // a default is the program's to get right, so one that can't be
+    // converted is a mistake in the configuration rather than the user's;
+    // `check_config` catches those before decoding, except for decoders
+    // made with `FlagDecoder::new`
+    fn not_converted<T>(&self, value: &str, kind: ValueKind, problem: String) -> HammerResult<T> {
+        let field = self.current_field.get_ref().as_slice();
+        let from_default = self.origins.find_equiv(&field).map(|o| o.as_slice() == DEFAULT_SOURCE).unwrap_or(false);
+
+        if self.value_index.is_none() && from_default {
+            let message = format!("the default {} of {} is not {}", self.shown(value), field, kind.description());
+            return Err(HammerError::early(ConfigError, message));
+        }
+
+        self.invalid(format!("{}{}{}", self.flag_prefix(), problem, self.origin_note()))
+    }
+
+    // an error about the value that was just read, pointing at its argument
+    fn invalid<T>(&self, message: String) -> HammerResult<T> {
+        match self.value_index {
+            Some(index) => HammerError::new(message).map_err(|e| e.at_index(index)),
+            None => HammerError::new(message)
+        }
+    }
+
+    // moves the argument at `pos` from the source to what the current field consumed
+    fn remove_token(&mut self, pos: uint) {
+        let mut token = self.source.remove(pos).unwrap();
+        token.consumed_by = self.current_field.clone();
+        self.consumed.push(token);
+    }
+
+    // removes the flag, returning the value joined to it with `=`, if any,
+    // and the index of its argument
+    fn remove_bool_field(&mut self, found: &FlagMatch) -> (Option<String>, uint) {
+        let index = self.source[found.pos].index;
+
+        if found.clustered {
+            self.remove_short(found.pos, false);
+            return (None, index);
+        }
+
+        self.remove_token(found.pos);
+        (found.value.clone(), index)
+    }
+
+    // removes an occurrence of a counted flag, returning how many times it
+    // was given in that argument
+    fn remove_counted(&mut self, found: &FlagMatch) -> uint {
+        if self.source[found.pos].kind == LongFlag {
+            self.remove_token(found.pos);
+            1
+        } else {
+            self.remove_short(found.pos, true)
+        }
+    }
+
+    /*
+        Takes the current field's short aliases out of the argument at `pos`,
+        once or every time they appear, and returns how many were taken. The
+        argument is dropped once only the `-` is left; otherwise the rest of
+        the cluster stays for the other fields.
+    */
+    fn remove_short(&mut self, pos: uint, every: bool) -> uint {
+        let shorts = self.shorts_for(self.current_field.get_ref().as_slice());
+        let mut taken = 0u;
+
+        let rest: String = self.source[pos].raw.as_slice().slice_from(1).chars().filter(|&c| {
+            let take = shorts.contains(&c) && (every || taken == 0);
+            if take { taken += 1; }
+            !take
+        }).collect();
+
+        if rest.is_empty() {
+            self.remove_token(pos);
+        } else {
+            self.clusters.insert(self.source[pos].index);
+            self.source.get_mut(pos).raw = format!("-{}", rest);
+        }
+
+        taken
+    }
+
+    // takes every `--no-flag` of the current switch, returning the index of
+    // the last one
+    fn take_negations(&mut self) -> Option<uint> {
+        if !self.config.accepted_syntax().negation {
+            return None;
+        }
+
+        // like `field_pos`, borrowed from the layout when it has the field
+        let layout = self.layout.clone();
+        let configured;
+
+        let flag = match layout.fields.find_equiv(&self.current_field.get_ref().as_slice()) {
+            Some(flags) => flags.negated.as_slice(),
+            None => {
+                configured = self.config.negated_name(self.current_field.get_ref().as_slice());
+                configured.as_slice()
+            }
+        };
+
+        let mut last = None;
+
+        loop {
+            let found = self.source.iter().position(|token| {
+                token.kind == LongFlag && same_flag(&*self.config, token.raw.as_slice(), flag)
+            });
+
+            match found {
+                Some(pos) => {
+                    last = Some(self.source[pos].index);
+                    self.remove_token(pos);
+                },
+                None => return last
+            }
+        }
+    }
+
+    // with `reject_unknown_flags`, anything left before `--` that looks like
+    // a flag is a mistake; what looks like one is up to the tokenizer, so
+    // this agrees with `classify`
+    fn check_unknown_flags(&self) -> HammerResult<()> {
+        if !self.config.rejects_unknown_flags() {
+            return Ok(());
+        }
+
+        match self.source.iter().find(|token| token.is_flag()) {
+            Some(token) => {
+                let (name, _) = split_flag(token.raw.as_slice());
+                let err = HammerError::new(format!("unknown flag: {}", shortened(name)));
+                err.map_err(|e| e.at_index(token.index))
+            },
+            None => Ok(())
+        }
+    }
+
+    // what's left of a cluster like `-vxc` once the known flags are taken
+    // out must be other flags given twice; anything else is an error
+    fn check_clusters(&self) -> HammerResult<()> {
+        for token in self.source.iter() {
+            let index = token.index;
+            if !self.clusters.contains(&index) { continue }
+
+            match token.raw.as_slice().slice_from(1).chars().find(|&c| !self.config.is_alias(c)) {
+                Some(c) => {
+                    let err = HammerError::new(format!("unknown flag -{} in {}", c, shortened(self.args[index].as_slice())));
+                    return err.map_err(|e| e.at_index(index));
+                },
+                None => ()
+            }
+        }
+
+        Ok(())
+    }
+
+    // `true`, `yes`, `on` or `1`, or their opposites, in any case
+    fn switch_value(&self, value: String) -> HammerResult<bool> {
+        match parse_switch(value.as_slice()) {
+            Some(b) => Ok(b),
+            None => {
+                let spellings: Vec<String> = SWITCH_SPELLINGS.iter().map(|&(on, off)| format!("{}/{}", on, off)).collect();
+                self.not_converted(value.as_slice(), Switch, format!("could not convert {} to a bool; use {}", self.shown(value.as_slice()), spellings.connect(", ")))
+            }
+        }
+    }
+
+    fn remove_val_field(&mut self, found: &FlagMatch) {
+        // removes the flag and, unless it was joined with `=`, the value it's set to
+        self.remove_token(found.pos);
+
+        if found.value.is_none() {
+            self.remove_token(found.pos);
+        }
+    }
+}
+
+pub type HammerResult<T> = Result<T, HammerError>;
+
+/// What a `HammerError` reports
+#[deriving(Clone, PartialEq, PartialOrd, Hash, Show)]
+pub enum ErrorKind {
+    /// The command line was wrong; the message says how
+    InvalidFlags,
+    /// `--help` was given; the message is the help text
+    HelpRequested,
+    /// `--version` was given; the message is the version
+    VersionRequested,
+    /// A flag configured with `exits_early` was given; the message is its field
+    ExitedEarly,
+    /// The `FlagConfiguration` itself is wrong, such as naming a field that
+    /// doesn't exist
+    ConfigError,
+    /// `--hammer-complete` was given; the message is the completions, one
+    /// per line
+    CompletionRequested,
+    /// The flag configured with `confirm_with` wasn't given and stdin is a
+    /// terminal, so the program should ask before going on
+    ConfirmationNeeded
+}
+
+#[deriving(Clone, PartialEq, PartialOrd, Hash, Show)]
+pub struct HammerError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub line: Option<uint>,
+    pub index: Option<uint>
+}
+
+impl HammerError {
+    fn new<T>(message: String) -> HammerResult<T> {
+        Err(HammerError{ kind: InvalidFlags, message: message, line: None, index: None })
+    }
+
+    fn early(kind: ErrorKind, message: String) -> HammerError {
+        HammerError { kind: kind, message: message, line: None, index: None }
+    }
+
+    fn at_line(self, line: uint) -> HammerError {
+        HammerError { line: Some(line), ..self }
+    }
+
+    fn at_index(self, index: uint) -> HammerError {
+        HammerError { index: Some(index), ..self }
+    }
+
+    // several errors as one, each on a line of its own
+    fn combine(errors: &[HammerError]) -> HammerError {
+        if errors.len() == 1 {
+            return errors[0].clone();
+        }
+
+        let mut message = format!("{} problems with the command line", errors.len());
+
+        for err in errors.iter() {
+            message.push_str(format!("\n  - {}", err.message).as_slice());
+        }
+
+        HammerError { kind: InvalidFlags, message: message, line: None, index: None }
+    }
+}
+
+/**
+The arguments of a decoded command line, sorted by what they turned out to
+be; see `FlagDecoder::classify`
+
+The arguments taken by the struct's own flags aren't listed. Every other
+argument is in exactly one of these, in the order it was given.
+*/
+#[deriving(Clone, PartialEq, Show)]
+pub struct ClassifiedArgs {
+    /// Arguments that aren't flags, before any `--`, including negative
+    /// numbers like `-5`; the rest field gets these along with the unknown
+    /// flags
+    pub positionals: Vec<String>,
+    /// The `NAME=VALUE` arguments taken by the `leading_assignments` field
+    pub assignments: Vec<(String, String)>,
+    /// The `field=value` arguments given with the `override_flag`
+    pub overrides: Vec<(String, String)>,
+    /// Arguments that look like flags, before any `--`, but aren't flags of
+    /// the struct
+    pub unknown_flags: Vec<String>,
+    /// The arguments after the first `--`, which is itself left out
+    pub passthrough: Vec<String>
+}
+
+/// What a `HammerWarning` reports
+#[deriving(Clone, PartialEq, PartialOrd, Hash, Show)]
+pub enum WarningKind {
+    /// A `lenient_list` element couldn't be converted and was left out
+    SkippedListElement,
+    /// A flag was given more than once and only the first was used; the
+    /// others are left in the remaining arguments
+    DuplicateIgnored,
+    /// A flag was given along with one configured to make it have no effect
+    /// with `ignored_when`
+    IgnoredFlag,
+    /// A `negated` switch was given in its positive form, which is already
+    /// its default
+    RedundantFlag
+}
+
+/// A problem that didn't stop decoding
+#[deriving(Clone, PartialEq, PartialOrd, Hash, Show)]
+pub struct HammerWarning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// The field the warning is about
+    pub field: Option<String>
+}
+
+// the error as printed by `decode_args_or_exit`; annotated errors are only
+// used on a terminal, so the caret and the `error:` label are colored too
+fn render_error(args: &[String], err: &HammerError, annotate: bool) -> String {
+    if !annotate {
+        return finish_block(format!("error: {}", err.message).as_slice());
+    }
+
+    let annotated = format_error_annotated(args, err);
+    let mut out = String::new();
+
+    for line in annotated.as_slice().lines() {
+        if line.starts_with("error:") {
+            out.push_str(format!("\x1b[1;31merror:\x1b[0m{}\n", line.slice_from(6)).as_slice());
+        } else if line.trim_left().starts_with("^") {
+            let caret = line.trim_left();
+            let indent = line.len() - caret.len();
+            out.push_str(format!("{}\x1b[1;31m{}\x1b[0m\n", line.slice_to(indent), caret).as_slice());
+        } else {
+            out.push_str(format!("{}\n", line).as_slice());
+        }
+    }
+
+    out
+}
+
+impl Decoder<HammerError> for FlagDecoder {
+    fn read_nil(&mut self) -> HammerResult<()> { unimplemented!() }
+
+    fn read_uint(&mut self) -> HammerResult<uint> {
+        if self.placeholder { return Ok(0) }
+
+        if self.is_counted(self.current_field.get_ref().as_slice()) {
+            return self.read_count();
+        }
+
+        match self.read_str() {
+            Ok(s) => {
+                match from_str(s.as_slice()) {
+                    Some(i) => Ok(i),
+                    None => self.unconverted(s.as_slice(), Unsigned)
+                }
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    // doesn't handle "too large to represent" problems. will just truncate.
+    fn read_u64(&mut self) -> HammerResult<u64> { self.read_uint().map(|v| v as u64) }
+    fn read_u32(&mut self) -> HammerResult<u32> { self.read_uint().map(|v| v as u32) }
+    fn read_u16(&mut self) -> HammerResult<u16> { self.read_uint().map(|v| v as u16) }
+    fn read_u8(&mut self) -> HammerResult<u8>   { self.read_uint().map(|v| v as u8)  }
+
+    // signed integers are parsed as such, so `--offset -5` works
+    fn read_int(&mut self) -> HammerResult<int> {
+        if self.placeholder || self.is_counted(self.current_field.get_ref().as_slice()) {
+            return self.read_uint().map(|v| v as int);
+        }
+
+        match self.read_str() {
+            Ok(s) => {
+                match from_str(s.as_slice()) {
+                    Some(i) => Ok(i),
+                    None => self.unconverted(s.as_slice(), Signed)
+                }
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    fn read_i64(&mut self) -> HammerResult<i64> { self.read_int().map(|v| v as i64) }
+    fn read_i32(&mut self) -> HammerResult<i32> { self.read_int().map(|v| v as i32) }
+    fn read_i16(&mut self) -> HammerResult<i16> { self.read_int().map(|v| v as i16) }
+    fn read_i8(&mut self) -> HammerResult<i8>   { self.read_int().map(|v| v as i8)  }
+
+    /*
+        A switch is turned on by `--flag` and off by `--no-flag`; when both
+        are given, the last one wins, except for a `negated` switch, where
+        `--flag` only makes sense as a mistake.
+    */
+    fn read_bool(&mut self) -> HammerResult<bool> {
+        if self.placeholder { return Ok(false) }
+
+        let negated = self.take_negations();
+        let mut found = try!(self.occurrences(false, |d, found| d.remove_bool_field(found)));
+        let given = found.pop();
+
+        match (negated, &given) {
+            (Some(negated), &Some((_, index))) if self.config.is_negated(self.current_field.get_ref().as_slice()) => {
+                let field = self.current_field.get_ref().to_string();
+                let err = HammerError::new(format!("{} and {} can't both be given",
+                    self.display_name(), self.negated_name(field.as_slice())));
+                return err.map_err(|e| e.at_index(cmp::max(negated, index)));
+            },
+            _ => ()
+        }
+
+        match negated {
+            Some(negated) if given.as_ref().map(|&(_, index)| negated > index).unwrap_or(true) => {
+                let field = self.current_field.get_ref().to_string();
+                let flag = self.negated_name(field.as_slice());
+
+                self.origins.insert(field.clone(), format!("from {} at arg {}", flag, negated + 1));
+                self.given.insert(field);
+                return Ok(false);
+            },
+            _ => ()
+        }
+
+        match given {
+            Some((Some(value), index)) => {
+                self.value_index = Some(index);
+                return self.switch_value(value);
+            },
+            Some((None, _)) => {
+                let field = self.current_field.get_ref().to_string();
+
+                if self.config.is_negated(field.as_slice()) {
+                    let message = format!("{} changes nothing; it's on unless {} is given",
+                        self.display_name(), self.negated_name(field.as_slice()));
+                    self.warn(RedundantFlag, message);
+                }
+
+                return Ok(true);
+            },
+            None => ()
+        }
+
+        match self.existing() {
+            Some(EncodedSwitch(value)) => return Ok(value),
+            _ => ()
+        }
+
+        let field = self.current_field.get_ref().to_string();
+        self.value_index = None;
+
+        match self.resolve_value(field.as_slice()) {
+            // an environment variable is on when it's set to anything but
+            // empty or a spelling of off, like `MYAPP_COLOR=1` or `=always`
+            Some(value) if self.from_env => Ok(parse_switch(value.as_slice()).unwrap_or(!value.is_empty())),
+            Some(value) => self.switch_value(value),
+            None if self.field_required(true) => self.missing(),
+            None => Ok(false)
+        }
+    }
+
+    fn read_f64(&mut self) -> HammerResult<f64> {
+        match self.read_str() {
+            Ok(s) => {
+                match from_str(s.as_slice()) {
+                    Some(f) => Ok(f),
+                    None => self.unconverted(s.as_slice(), Float)
+                }
+            },
+            Err(e) => Err(e)
+        }
+    }
+    fn read_f32(&mut self) -> HammerResult<f32> { self.read_f64().map(|v| v as f32) }
+    fn read_char(&mut self) -> HammerResult<char> {
+        match self.read_str() {
+            Ok(s) => {
+                if s.as_slice().char_len() == 1 {
+                    Ok(s.as_slice().char_at(0))
+                } else {
+                    self.not_converted(s.as_slice(), Character, format!("{} is not a single character", self.shown(s.as_slice())))
+                }
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    fn read_str(&mut self) -> HammerResult<String> {
+        let value = try!(self.raw_value());
+        let value = try!(self.expand_value(value));
+
+        if !self.placeholder {
+            try!(self.check_value(value.as_slice()));
+        }
+
+        Ok(value)
+    }
+
+    // a struct inside the flag struct is only decoded when its field was
+    // declared with `flatten_field`; its fields are then read like any other
+    #[allow(unused_variable)]
+    fn read_struct<T>(&mut self, s_name: &str, len: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        if self.depth > 0 {
+            if !self.config.is_flattened(self.current_field.get_ref().as_slice()) {
+                let message = format!("{} is a struct; declare it with flatten_field", self.display_name());
+                return Err(HammerError::early(ConfigError, message));
+            }
+
+            self.depth += 1;
+            let ret = f(self);
+            self.depth -= 1;
+            return ret;
+        }
+
+        // the error that stopped decoding is kept for `errored`
+        let ret = self.read_flags(f);
+        self.error = match ret {
+            Err(ref err) => Some(err.message.clone()),
+            Ok(_) => None
+        };
+        ret
+    }
+
+    // the flag struct itself, with the checks of the whole command line
+    // once its fields are read
+    fn read_flags<T>(&mut self, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        match self.broken.clone() {
+            Some(err) => return Err(err),
+            None => ()
+        }
+
+        try!(self.take_overrides());
+
+        self.depth += 1;
+        let ret = f(self);
+        self.depth -= 1;
+
+        if ret.is_ok() {
+            match self.check_clusters().and(self.check_unknown_flags()) {
+                Err(err) if self.config.collects_errors() => self.errors.push(err),
+                Err(err) => return Err(err),
+                Ok(()) => ()
+            }
+
+            self.warn_ignored();
+
+            if !self.errors.is_empty() {
+                return Err(HammerError::combine(self.errors.as_slice()));
+            }
+        }
+
+        ret
+    }
+
+    /*
+        With `collect_errors`, a field that's wrong is recorded and read
+        again as a stand-in value (zero, empty or absent) so decoding can go
+        on to the other fields.
+    */
+    #[allow(unused_variable)]
+    fn read_struct_field<T>(&mut self, f_name: &str, f_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        assert!(!self.done, "Flag struct must not contain any fields after {}", self.config.rest_field_name());
+
+        self.current_field = Some(f_name.to_string());
+        self.probe = None;
+        self.resolved = None;
+
+        match f(self) {
+            Err(ref err) if err.kind == InvalidFlags && self.config.collects_errors() => {
+                self.errors.push(err.clone());
+                self.placeholder = true;
+                let ret = f(self);
+                self.placeholder = false;
+                ret
+            },
+            ret => ret
+        }
+    }
+
+    fn read_option<T>(&mut self, f: |&mut FlagDecoder, bool| -> HammerResult<T>) -> HammerResult<T> {
+        if self.placeholder { return f(self, false) }
+
+        let found = try!(self.probe());
+        let present = found.is_some();
+        self.probe = Some(found);
+
+        if present {
+            return f(self, true);
+        }
+
+        match self.existing() {
+            Some(EncodedValue(_)) => f(self, true),
+            Some(EncodedNone) => f(self, false),
+            _ => self.read_absent_option(f)
+        }
+    }
+
+    #[allow(unused_variable)]
+    fn read_enum<T>(&mut self, name: &str, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        f(self)
+    }
+
+    // an enum field is a choice: its value names one of the variants, in
+    // any case, so `--level warn` is `Warn`
+    fn read_enum_variant<T>(&mut self, names: &[&str], f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> {
+        if self.placeholder { return f(self, 0) }
+
+        let value = try!(self.read_str());
+
+        match names.iter().position(|name| name.eq_ignore_ascii_case(value.as_slice())) {
+            Some(idx) => f(self, idx),
+            None => {
+                let choices: Vec<String> = names.iter().map(|name| name.to_ascii_lower()).collect();
+                self.invalid(format!("{} is not one of {}", self.shown(value.as_slice()), choices.connect(", ")))
+            }
+        }
+    }
+
+    // only the names of variants can be given on the command line
+    #[allow(unused_variable)]
+    fn read_enum_variant_arg<T>(&mut self, a_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        let message = format!("{} is an enum with fields; only enums of plain variants can be flags", self.display_name());
+        Err(HammerError::early(ConfigError, message))
+    }
+
+    // the rest of these are pretty weird or hard to implement.
+
+    #[allow(unused_variable)]
+    fn read_enum_struct_variant<T>(&mut self, names: &[&str], f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
+    #[allow(unused_variable)]
+    fn read_enum_struct_variant_field<T>(&mut self, f_name: &str, f_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
+
+    #[allow(unused_variable)]
+    fn read_tuple<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
+    #[allow(unused_variable)]
+    fn read_tuple_arg<T>(&mut self, a_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
+    #[allow(unused_variable)]
+    fn read_tuple_struct<T>(&mut self, s_name: &str, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
+    #[allow(unused_variable)]
+    fn read_tuple_struct_arg<T>(&mut self, a_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
+
+    #[allow(unused_variable)]
+    fn read_seq<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> {
+        if self.placeholder { return f(self, 0) }
+
+        let current_field = self.current_field.as_ref().unwrap().to_string();
+
+        if current_field != self.config.rest_field_name() {
+            return self.read_list(f);
+        }
+
+        self.done = true;
+
+        // when decoding in place, the rest field is only replaced by new
+        // positionals; the old ones aren't arguments, so they're read like
+        // a list rather than put back into the source
+        match self.existing() {
+            Some(EncodedList(values)) if self.source.is_empty() && self.passthrough().is_empty() => {
+                self.list = values.move_iter().map(|value| (value, None)).collect();
+
+                let len = self.list.len();
+                self.state = ProcessingList(-1);
+                let ret = f(self, len);
+                self.state = Processing;
+                return ret;
+            },
+            _ => ()
+        }
+
+        let len = self.source.len() + self.passthrough().len();
+
+        if len > 0 {
+            self.origins.insert(current_field, "from the remaining arguments".to_string());
+        }
+
+        self.state = ProcessingRest(-1);
+        f(self, len)
+    }
+
+    #[allow(unused_variable)]
+    fn read_seq_elt<T>(&mut self, idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        self.state = match self.state {
+            ProcessingRest(i) => ProcessingRest(i + 1),
+            ProcessingList(i) => ProcessingList(i + 1),
+            _ => unimplemented!()
+        };
+
+        f(self)
+    }
+
+    /*
+        The only map is the `leading_assignments` field: the `NAME=VALUE`
+        arguments that nothing has been taken from before. Names and values
+        take turns in `list`, both pointing at their argument.
+    */
+    fn read_map<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> {
+        if self.placeholder { return f(self, 0) }
+
+        let field = self.current_field.get_ref().to_string();
+
+        if self.config.assignments_field() != Some(field.clone()) {
+            let message = format!("{} is a map; only a leading_assignments field can be", self.display_name());
+            return Err(HammerError::early(ConfigError, message));
+        }
+
+        self.list = vec!();
+        let mut index = 0u;
+
+        while !self.source.is_empty() && self.source[0].index == index {
+            match split_assignment(self.source[0].raw.as_slice()) {
+                Some((name, value)) => {
+                    self.assignments.push((name.to_string(), value.to_string()));
+                    self.list.push((name.to_string(), Some(index)));
+                    self.list.push((value.to_string(), Some(index)));
+                },
+                None => break
+            }
+
+            self.remove_token(0);
+            index += 1;
+        }
+
+        if index > 0 {
+            self.origins.insert(field.clone(), "from the leading assignments".to_string());
+            self.given.insert(field.clone());
+        }
+
+        match self.config.map_default_for(field.as_slice()) {
+            Some(defaults) => {
+                let defaults = defaults.move_iter().flat_map(|(name, value)| vec!((name, None), (value, None)).move_iter()).collect();
+                self.add_defaults(field.as_slice(), defaults);
+            },
+            None => ()
+        }
+
+        let len = self.list.len() / 2;
+        let ret = f(self, len);
+        self.state = Processing;
+        ret
+    }
+
+    #[allow(unused_variable)]
+    fn read_map_elt_key<T>(&mut self, idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        self.state = ProcessingList((idx * 2) as int);
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn read_map_elt_val<T>(&mut self, idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
+        self.state = ProcessingList((idx * 2 + 1) as int);
+        f(self)
+    }
+}
+
+/**
+Convert arguments into struct T
+
+hammer_config! must be called on T beforehand.
+*/
+pub fn decode_args<T: Flags>(args: &[String]) -> HammerResult<T> {
+    decode(args).and_then(|decoded| decoded.confirmed())
+}
+
+/**
+A decoded struct, along with what decoding left behind
+
+Everything besides the struct is read through methods, so more can be kept
+here without breaking programs. `Decoded` derefs to the struct, so its
+fields can be read directly: `decoded.verbose`.
+*/
+pub struct Decoded<T> {
+    value: T,
+    remaining: Vec<String>,
+    warnings: Vec<HammerWarning>,
+    confirm: Option<String>,
+    origins: HashMap<String, String>,
+    config: Rc<FlagConfiguration>
+}
+
+impl<T> Deref<T> for Decoded<T> {
+    fn deref<'a>(&'a self) -> &'a T {
+        &self.value
+    }
+}
+
+impl<T> Decoded<T> {
+    // nothing left over, no warnings and nothing to confirm; the `with_`
+    // methods fill in the rest. The configuration it was decoded with is
+    // kept for `report`
+    fn new(value: T, config: Rc<FlagConfiguration>) -> Decoded<T> {
+        Decoded { value: value, remaining: vec!(), warnings: vec!(), confirm: None, origins: HashMap::new(), config: config }
+    }
+
+    fn with_origins(mut self, origins: HashMap<String, String>) -> Decoded<T> {
+        self.origins = origins;
+        self
+    }
+
+    fn with_remaining(mut self, remaining: Vec<String>) -> Decoded<T> {
+        self.remaining = remaining;
+        self
+    }
+
+    fn with_warnings(mut self, warnings: Vec<HammerWarning>) -> Decoded<T> {
+        self.warnings = warnings;
+        self
+    }
+
+    fn with_confirmation(mut self, confirm: Option<String>) -> Decoded<T> {
+        self.confirm = confirm;
+        self
+    }
+
+    pub fn value<'a>(&'a self) -> &'a T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// The arguments that weren't decoded into any field
+    pub fn remaining<'a>(&'a self) -> &'a [String] {
+        self.remaining.as_slice()
+    }
+
+    /// Problems that didn't stop decoding, such as skipped list elements
+    pub fn warnings<'a>(&'a self) -> &'a [HammerWarning] {
+        self.warnings.as_slice()
+    }
+
+    /// Whether the program should ask the user before going on; see
+    /// `FlagConfiguration::confirm_with`
+    pub fn needs_confirmation(&self) -> bool {
+        self.confirm.is_some()
+    }
+
+    // the struct, unless confirmation is needed
+    fn confirmed(self) -> HammerResult<T> {
+        match self.confirm {
+            Some(flag) => Err(HammerError::early(ConfirmationNeeded, format!("confirmation needed; pass {} to go ahead", flag))),
+            None => Ok(self.value)
+        }
+    }
+}
+
+impl<T: FlagConfig + Encodable<FlagEncoder, HammerError>> Decoded<T> {
+    /**
+    Every field's value and where it came from, one field per line, for
+    finding out why a program used the value it did:
+
+    ```text
+    count = 3 (from --count at arg 2)
+    color = true (default)
+    token = ***** (from env MYAPP_TOKEN)
+    ```
+
+    Arguments are numbered from 1. The values of `sensitive` fields are
+    replaced by `*****`, and fields that weren't given anywhere are marked
+    `(not given)`.
+    */
+    pub fn report(&self) -> String {
+        let config = &*self.config;
+        let mut encoder = FlagEncoder::new();
+
+        match self.value.encode(&mut encoder) {
+            Ok(()) => (),
+            Err(err) => return format!("error: {}\n", err.message)
+        }
+
+        let mut out = String::new();
+
+        for (field, value) in encoder.fields().move_iter() {
+            let shown = if config.is_sensitive(field.as_slice()) {
+                REDACTED.to_string()
+            } else {
+                match value {
+                    EncodedSwitch(value) => value.to_string(),
+                    EncodedValue(value) => value,
+                    EncodedList(values) => format!("[{}]", values.connect(", ")),
+                    EncodedNone => "none".to_string()
+                }
+            };
+
+            let origin = match self.origins.find(&field) {
+                Some(origin) => origin.as_slice(),
+                None => "not given"
+            };
+
+            let name = config.display_name(field.as_slice());
+            out.push_str(format!("{} = {} ({})\n", name.as_slice().trim_left_chars('-'), shown, origin).as_slice());
+        }
+
+        finish_block(out.as_slice())
+    }
+}
+
+/**
+Convert arguments into struct T, keeping the leftover arguments and any
+warnings
+
+hammer_config! must be called on T beforehand.
+*/
+pub fn decode<T: Flags>(args: &[String]) -> HammerResult<Decoded<T>> {
+    try!(ParsePlan::<T>::new()).decode(args)
+}
+
+/**
+The configuration and flag names of `T`, worked out once and checked, for
+decoding many command lines
+
+`decode` builds and checks the configuration of `T`, walks `T` for its
+flag names and works out each field's flags every time it's called. A
+program decoding lots of short command lines against the same struct, such
+as a server reading commands, can do that once and keep the plan; its
+decodes share it and only match and convert the arguments. The results are
+the same as `decode` and `decode_args`.
+
+```
+let plan = try!(ParsePlan::<ServerCommand>::new());
+
+for line in lines.iter() {
+    let command = try!(plan.decode_args(shell_split(line.as_slice()).as_slice()));
+}
+```
+*/
+pub struct ParsePlan<T> {
+    config: Rc<FlagConfiguration>,
+    layout: Rc<Layout>
+}
+
+impl<T: Flags> ParsePlan<T> {
+    /// Fails the way `decode` would when the configuration of `T` is broken
+    pub fn new() -> HammerResult<ParsePlan<T>> {
+        let config = try!(checked_config::<T>());
+        let layout = Layout::of::<T>(&config);
+        try!(check_config(&config, &layout));
+
+        Ok(ParsePlan { config: Rc::new(config), layout: Rc::new(layout) })
+    }
+
+    // every decoder shares the plan's configuration and layout
+    fn decoder(&self, args: &[String]) -> FlagDecoder {
+        FlagDecoder::with_config(args, self.config.clone(), self.layout.clone())
+    }
+
+    pub fn decode(&self, args: &[String]) -> HammerResult<Decoded<T>> {
+        self.decode_all(args).val1().map_err(|errors| HammerError::combine(errors.as_slice()))
+    }
+
+    // `decode`, failing with each of the errors `collect_errors` gathered
+    // rather than one listing them all, along with the arguments as expanded
+    // from argument files, which the errors point into
+    fn decode_all(&self, args: &[String]) -> (Vec<String>, Result<Decoded<T>, Vec<HammerError>>) {
+        let args = if self.config.allows_argfiles() {
+            match expand_argfiles(args, self.config.accepted_syntax().passthrough) {
+                Ok(expanded) => expanded,
+                Err(err) => return (Vec::from_slice(args), Err(vec!(err)))
+            }
+        } else {
+            Vec::from_slice(args)
+        };
+
+        let decoded = self.decode_expanded(args.as_slice());
+        (args, decoded)
+    }
+
+    fn decode_expanded(&self, args: &[String]) -> Result<Decoded<T>, Vec<HammerError>> {
+        match early_exit::<T>(args, &*self.config, self.layout.flags.as_slice()) {
+            Some(err) => return Err(vec!(err)),
+            None => ()
+        }
+
+        let mut decoder = self.decoder(args);
+
+        let value = match FlagParse::decode_flags(&mut decoder) {
+            Ok(value) => value,
+            Err(_) if decoder.errors.len() > 1 => return Err(decoder.errors.clone()),
+            Err(err) => return Err(vec!(err))
+        };
+
+        let confirm = decoder.unconfirmed(stdio::stdin_raw().isatty());
+
+        Ok(Decoded::new(value, self.config.clone())
+            .with_remaining(decoder.remaining())
+            .with_warnings(decoder.warnings())
+            .with_confirmation(confirm)
+            .with_origins(decoder.origins.clone()))
+    }
+
+    pub fn decode_args(&self, args: &[String]) -> HammerResult<T> {
+        self.decode(args).and_then(|decoded| decoded.confirmed())
+    }
+
+    /// `redacted_args` with the plan's configuration
+    pub fn redacted_args(&self, args: &[String]) -> Vec<String> {
+        redact(args, &*self.config, &*self.layout)
+    }
+}
+
+// how many argument files deep `@file` arguments may go
+static ARGFILE_DEPTH: uint = 8;
+
+/*
+    With `allow_argfiles`, every `@file` argument before `--` (anywhere,
+    without the `passthrough` syntax) is replaced by the arguments in the
+    file, and the `@file`s among those are expanded in turn. Errors point at
+    the argument on the command line that led to the file.
+*/
+fn expand_argfiles(args: &[String], passthrough: bool) -> HammerResult<Vec<String>> {
+    let mut out = vec!();
+
+    for (i, arg) in args.iter().enumerate() {
+        if passthrough && arg.as_slice() == "--" {
+            out.push_all(args.slice_from(i));
+            break;
+        }
+
+        try!(expand_argfile(arg.as_slice(), 0, &mut out).map_err(|e| e.at_index(i)));
+    }
+
+    Ok(out)
+}
+
+fn expand_argfile(arg: &str, depth: uint, out: &mut Vec<String>) -> HammerResult<()> {
+    if arg.starts_with("@@") {
+        out.push(arg.slice_from(1).to_string());
+        return Ok(());
+    }
+
+    if arg.len() < 2 || !arg.starts_with("@") {
+        out.push(arg.to_string());
+        return Ok(());
+    }
+
+    let path = arg.slice_from(1);
+
+    if depth == ARGFILE_DEPTH {
+        return HammerError::new(format!("argument files are nested more than {} deep at {}", ARGFILE_DEPTH, path));
+    }
+
+    let args = match read_args(path) {
+        Ok(args) => args,
+        Err(e) => return HammerError::new(format!("could not read argument file {} ({})", path, e.desc))
+    };
+
+    for arg in args.iter() {
+        try!(expand_argfile(arg.as_slice(), depth + 1, out));
+    }
+
+    Ok(())
+}
+
+/**
+Decodes like `decode`, then checks the decoded struct as a whole
+
+`FlagConfiguration` doesn't know the type it configures, so checks that
+need the struct, such as one flag requiring another, are registered here.
+Each validator returns a message when the struct is invalid, which becomes
+the `HammerError`.
+
+```
+fn check_tls(flags: &ServeFlags) -> Result<(), String> {
+    if flags.tls && flags.cert.is_none() { Err("--tls requires --cert".to_string()) } else { Ok(()) }
+}
+
+let flags = Parser::<ServeFlags>::new().validate_all(check_tls).decode_args(args);
+```
+*/
+pub struct Parser<T> {
+    validators: Vec<fn(&T) -> Result<(), String>>
+}
+
+impl<T: Flags> Parser<T> {
+    pub fn new() -> Parser<T> {
+        Parser { validators: vec!() }
+    }
+
+    /// Check the whole struct after every successful decode
+    pub fn validate_all(mut self, validator: fn(&T) -> Result<(), String>) -> Parser<T> {
+        self.validators.push(validator);
+        self
+    }
+
+    pub fn decode(&self, args: &[String]) -> HammerResult<Decoded<T>> {
+        let decoded = try!(decode::<T>(args));
+
+        for validator in self.validators.iter() {
+            match (*validator)(decoded.value()) {
+                Ok(()) => (),
+                Err(message) => return HammerError::new(message)
+            }
+        }
+
+        Ok(decoded)
+    }
+
+    pub fn decode_args(&self, args: &[String]) -> HammerResult<T> {
+        self.decode(args).and_then(|decoded| decoded.confirmed())
+    }
+}
+
+/*
+    The configuration of `T`. A `hammer_config!` closure is the program's
+    own code and can fail, so it's run in a separate task first, where a
+    failure becomes a `ConfigError` naming the type instead of taking the
+    caller's task down with it. Configurations hold `Rc`s and boxed sources,
+    validators and completers, none of which can be sent between tasks, so
+    once that succeeds the configuration is built again here. `ParsePlan`
+    does this once, and everything decoded through it shares the result.
+*/
+#[doc(hidden)]
+pub fn checked_config<T: FlagConfig>() -> HammerResult<FlagConfiguration> {
+    let attempt = task::try(proc() {
+        let _ = FlagConfig::config(None::<T>, FlagConfiguration::new());
+    });
+
+    let cause = match attempt {
+        Ok(()) => return Ok(FlagConfig::config(None::<T>, FlagConfiguration::new())),
+        Err(cause) => cause
+    };
+
+    let reason = match cause.as_ref::<&'static str>() {
+        Some(reason) => reason.to_string(),
+        None => cause.as_ref::<String>().map(|reason| reason.clone()).unwrap_or("unknown failure".to_string())
+    };
+
+    let name = unsafe { (*get_tydesc::<T>()).name };
+    Err(HammerError::early(ConfigError, format!("the configuration of {} failed: {}", name, reason)))
+}
+
+// makes sure the configuration only refers to fields that exist, gives
+// each flag and short alias to one field and has defaults its fields can
+// read, reporting every problem in a single error; the layout's paths are
+// where the fields are, for telling a flattened field apart from one of
+// the same name
+fn check_config(config: &FlagConfiguration, layout: &Layout) -> HammerResult<()> {
+    let (fields, paths) = (layout.names.as_slice(), layout.paths.as_slice());
+
+    let mut problems = config.problems();
+
+    problems.extend(config.field_references().move_iter()
+        .filter(|&(_, ref field)| !fields.contains(field))
+        .map(|(setting, field)| format!("{} refers to unknown field {}", setting, field)));
+
+    // flattened structs and the rest field aren't flags themselves
+    let rest = config.rest_field_name();
+    let flags: Vec<(String, &String)> = fields.iter().zip(paths.iter())
+        .filter(|&(field, _)| !config.is_flattened(field.as_slice()) && *field != rest)
+        .map(|(field, path)| (config.display_name(field.as_slice()), path))
+        .collect();
+
+    for (i, &(ref flag, field)) in flags.iter().enumerate() {
+        for &(_, other) in flags.slice_from(i + 1).iter().filter(|&&(ref f, _)| f == flag) {
+            problems.push(format!("{} is the flag of both {} and {}", flag, field, other));
+        }
+    }
+
+    // a default is only used when the flag isn't given, so it's checked now
+    // rather than whenever that happens: first that the field can read it,
+    // then against its range and validator
+    for field in fields.iter() {
+        let value = match config.default_for(field.as_slice()) {
+            Some(value) => value,
+            None => continue
+        };
+
+        // a default with variables in it is only known once they're expanded
+        let kind = if config.expands_env(field.as_slice()) {
+            Text
+        } else {
+            layout.fields.find(field).map(|f| f.kind).unwrap_or(Text)
+        };
+
+        let checked = if kind.converts(value.as_slice()) {
+            config.check_value(field.as_slice(), value.as_slice())
+        } else {
+            Err(format!("is not {}", kind.description()))
+        };
+
+        let shown = if config.is_sensitive(field.as_slice()) { REDACTED.to_string() } else { shortened(value.as_slice()) };
+
+        match checked {
+            Err(problem) => problems.push(format!("the default {} of {} {}", shown, field, problem)),
+            Ok(()) => ()
+        }
+    }
+
+    let aliases = config.short_aliases();
+
+    for (i, &(ref field, c)) in aliases.iter().enumerate() {
+        for &(ref other, _) in aliases.slice_from(i + 1).iter().filter(|&&(_, o)| o == c) {
+            problems.push(format!("-{} is the short form of both {} and {}", c, field, other));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(HammerError::early(ConfigError, problems.connect("; ")))
+    }
+}
+
+/*
+    `--help`, `--version`, the `help` command and flags configured with
+    `exits_early` win over everything else on the command line, including
+    missing required flags, so they're looked for before decoding. `--help`
+    and `--version` are left alone if the struct has fields by those names.
+    `-h` only lists `Common` flags, and `--help --verbose` every flag.
+    With `shell_completion`, `--hammer-complete` is only recognized as the
+    first argument, since everything after it is the command line being
+    completed. None of these count after `--`, and they ignore case with
+    `case_insensitive` like every other flag.
+*/
+fn early_exit<T: Flags>(args: &[String], config: &FlagConfiguration, flags: &[String]) -> Option<HammerError> {
+    let passthrough = config.accepted_syntax().passthrough;
+    let given = |flag: &str| args.iter().take_while(|a| !passthrough || a.as_slice() != "--").any(|a| same_flag(config, a.as_slice(), flag));
+    let declared = |flag: &str| flags.iter().any(|f| same_flag(config, f.as_slice(), flag));
+
+    if config.offers_completion() && args.get(0).map(|a| a.as_slice() == "--hammer-complete").unwrap_or(false) {
+        return Some(HammerError::early(CompletionRequested, completions_with::<T>(config.clone(), args.tail()).connect("\n")));
+    }
+
+    let help_command = config.has_help_command() && args.get(0).map(|a| a.as_slice() == "help").unwrap_or(false);
+
+    if (given("--help") && !declared("--help")) || help_command {
+        let tier = if given("--verbose") { Expert } else { Full };
+        return Some(HammerError::early(HelpRequested, help_text::<T>(config, tier)));
+    }
+
+    if given("-h") && !config.is_alias('h') {
+        return Some(HammerError::early(HelpRequested, help_text::<T>(config, Common)));
+    }
+
+    match config.program_version() {
+        Some(version) => if given("--version") && !declared("--version") {
+            return Some(HammerError::early(VersionRequested, version));
+        },
+        None => ()
+    }
+
+    for field in config.early_exit_fields().iter() {
+        let shorts = config.shorts_for(field.as_slice());
+
+        if given(config.display_name(field.as_slice()).as_slice()) || shorts.iter().any(|c| given(format!("-{}", c).as_slice())) {
+            return Some(HammerError::early(ExitedEarly, field.clone()));
+        }
+    }
+
+    None
+}
+
+// whether a flag on the command line is `flag`; long flags ignore case
+// with `case_insensitive`, short ones never do
+fn same_flag(config: &FlagConfiguration, name: &str, flag: &str) -> bool {
+    if config.ignores_case() && flag.starts_with("--") {
+        name.eq_ignore_ascii_case(flag)
+    } else {
+        name == flag
+    }
+}
+
+// whether `name` is the start of `flag`, as an abbreviation of it
+fn abbreviates(config: &FlagConfiguration, name: &str, flag: &str) -> bool {
+    name.len() <= flag.len() && flag.is_char_boundary(name.len()) && same_flag(config, name, flag.slice_to(name.len()))
+}
+
+// the description followed by the list of flags
+fn help_text<T: Flags>(config: &FlagConfiguration, tier: HelpTier) -> String {
+    match usage_with::<T>(config.clone(), tier, false) {
+        (Some(desc), options) => finish_block(format!("{}\n\n{}", desc, options).as_slice()),
+        (None, options) => options
+    }
+}
+
+/**
+Convert arguments into struct T, or print the error and exit
+
+Help and version requests and completions are printed to stdout, exiting
+successfully; long help goes through a pager, as `should_page` decides.
+Other errors are printed to stderr, exiting with status 1; when stderr is
+a terminal, they're rendered with `format_error_annotated`. The errors
+gathered with `collect_errors` are listed one by one with `render_errors`.
+
+Confirmation never makes it exit, and only the struct is returned, so a
+program using `confirm_with` should call `decode_or_exit` and check
+`needs_confirmation` itself.
+*/
+pub fn decode_args_or_exit<T: Flags>(args: &[String]) -> T {
+    decode_or_exit(args).into_inner()
+}
+
+/**
+Like `decode_args_or_exit`, but keeping the leftover arguments, warnings
+and whether confirmation is needed, as `decode` does
+
+```
+let decoded = decode_or_exit::<DeleteFlags>(args.as_slice());
+
+if decoded.needs_confirmation() && !ask("Delete everything?") {
+    return;
+}
+```
+*/
+pub fn decode_or_exit<T: Flags>(args: &[String]) -> Decoded<T> {
+    let plan = match ParsePlan::<T>::new() {
+        Ok(plan) => plan,
+        // a `ConfigError` doesn't echo the arguments
+        Err(err) => exit_with(args, &err)
+    };
+
+    let (args, decoded) = plan.decode_all(args);
+
+    let errors = match decoded {
+        Ok(decoded) => return decoded,
+        Err(errors) => errors
+    };
+
+    // errors point into the arguments as expanded
+    let args = plan.redacted_args(args.as_slice());
+
+    match errors.as_slice().head() {
+        Some(err) if errors.len() == 1 => exit_with(args.as_slice(), err),
+        _ => {
+            let _ = stdio::stderr().write_str(list_errors(args.as_slice(), errors.as_slice()).as_slice());
+            unsafe { libc::exit(1) }
+        }
+    }
+}
+
+// what `decode_or_exit` does about a single error, echoing `args`,
+// which are already redacted
+fn exit_with(args: &[String], err: &HammerError) -> ! {
+    match err.kind {
+        HelpRequested => {
+            print_paged(err.message.as_slice().trim_right());
+            unsafe { libc::exit(0) }
+        },
+        VersionRequested | CompletionRequested => {
+            println!("{}", err.message.as_slice().trim_right());
+            unsafe { libc::exit(0) }
+        },
+        _ => {
+            let annotate = stdio::stderr_raw().isatty();
+            let _ = stdio::stderr().write_str(render_error(args, err, annotate).as_slice());
+            unsafe { libc::exit(1) }
+        }
+    }
+}
+
+// help longer than a terminal of the traditional 24 lines is paged
+static PAGED_LINES: uint = 24;
+
+/**
+The pager to show `line_count` lines of help with, if any
+
+Help is only paged on a terminal, when it doesn't fit on a screen, and when
+`pager_env` names a pager; `decode_args_or_exit` passes `HAMMER_PAGER`, or
+`PAGER` when that isn't set, so an empty `HAMMER_PAGER` turns paging off.
+*/
+pub fn should_page(line_count: uint, is_tty: bool, pager_env: Option<String>) -> Option<String> {
+    if !is_tty || line_count <= PAGED_LINES {
+        return None;
+    }
+
+    pager_env.and_then(|pager| {
+        let pager = pager.as_slice().trim();
+        if pager.is_empty() { None } else { Some(pager.to_string()) }
+    })
+}
+
+/**
+Print the complete usage message, as `full_usage` renders it, through the
+pager when `should_page` says so
+*/
+pub fn print_help_paged<T: Flags>(program: &str) {
+    print_paged(full_usage::<T>(Some(program)).as_slice().trim_right());
+}
+
+// prints `text` through the pager, or plainly when there's none or it can't
+// be started; the pager is run by the shell, so `PAGER="less -R"` works
+fn print_paged(text: &str) {
+    let pager = should_page(text.lines().count(), stdio::stdout_raw().isatty(),
+        os::getenv("HAMMER_PAGER").or_else(|| os::getenv("PAGER")));
+
+    let pager = match pager {
+        Some(pager) => pager,
+        None => {
+            println!("{}", text);
+            return;
+        }
+    };
+
+    let spawned = Command::new("sh").arg("-c").arg(pager.as_slice())
+        .stdout(InheritFd(1)).stderr(InheritFd(2)).spawn();
+
+    let mut process = match spawned {
+        Ok(process) => process,
+        Err(_) => {
+            println!("{}", text);
+            return;
+        }
+    };
+
+    // the pager may be quit before reading everything
+    let _ = process.stdin.take().unwrap().write_str(format!("{}\n", text).as_slice());
+    let _ = process.wait();
+}
+
+/**
+Several errors from one command line, such as the ones collected by
+`FlagDecoder::errors` with `collect_errors`, as one message
+
+The errors are listed under a count, each with where it happened, followed by
+a single hint to look at `--help`. Arguments are echoed with the values of
+`sensitive` flags redacted.
+*/
+pub fn render_errors<T: Flags>(args: &[String], errors: &[HammerError]) -> String {
+    list_errors(redacted_args::<T>(args).as_slice(), errors)
+}
+
+// `render_errors` for arguments that are already redacted
+fn list_errors(args: &[String], errors: &[HammerError]) -> String {
+    let problems = if errors.len() == 1 { "problem" } else { "problems" };
+    let mut out = format!("error: {} {} with the command line\n", errors.len(), problems);
+
+    for err in errors.iter() {
+        let place = match (err.kind.clone(), err.index, err.line) {
+            (ConfigError, _, _) => " (in the program's flag configuration)".to_string(),
+            (_, Some(index), _) if index < args.len() => format!(" (at arg {}, {})", index + 1, args[index]),
+            (_, _, Some(line)) => format!(" (at line {})", line),
+            _ => String::new()
+        };
+
+        out.push_str(format!("  - {}{}\n", err.message, place).as_slice());
+    }
+
+    out.push_str("run with --help to see the available flags");
+    finish_block(out.as_slice())
+}
+
+/**
+The arguments with the values of `sensitive` flags replaced by `*****`, for
+echoing the command line back with `format_error_annotated`
+
+When the configuration of `T` is broken, nothing is known to be sensitive
+and the arguments come back as they are; decoding them fails with a
+`ConfigError`, which doesn't echo the command line.
+*/
+pub fn redacted_args<T: Flags>(args: &[String]) -> Vec<String> {
+    match checked_config::<T>() {
+        Ok(config) => redact(args, &config, &Layout::of::<T>(&config)),
+        Err(_) => Vec::from_slice(args)
+    }
+}
+
+/*
+    Redacts by the rules the decoder reads arguments with: arguments are
+    told apart the way `tokenize` does, long flags match like `same_flag`
+    and abbreviations do, and a short alias that takes a value takes the
+    rest of its cluster, `-p1234` or `-vp1234`, or else the next argument.
+    `override_flag` assignments to a sensitive field are redacted as well.
+    An abbreviation is treated as sensitive even if it's ambiguous.
+*/
+fn redact(args: &[String], config: &FlagConfiguration, layout: &Layout) -> Vec<String> {
+    let sensitive = config.sensitive_fields();
+    let override_flag = config.override_flag_name();
+
+    let sensitive_long = |name: &str| name.len() > 2 && sensitive.iter().any(|field| {
+        abbreviates(config, name, config.display_name(field.as_slice()).as_slice())
+    });
+    let sensitive_short = |c: char| sensitive.iter().any(|field| config.shorts_for(field.as_slice()).contains(&c));
+
+    let assignment = |arg: &str| match arg.find('=') {
+        Some(i) if config.field_for_flag(format!("--{}", arg.slice_to(i)).as_slice())
+                .map(|field| config.is_sensitive(field.as_slice())).unwrap_or(false) => {
+            format!("{}={}", arg.slice_to(i), REDACTED)
+        },
+        _ => arg.to_string()
+    };
+
+    let mut out = vec!();
+    let mut mask_next = false;
+    let mut assign_next = false;
+
+    for token in tokenize(args, config.accepted_syntax().passthrough).iter() {
+        let raw = token.raw.as_slice();
+
+        if mask_next || assign_next {
+            out.push(if mask_next { REDACTED.to_string() } else { assignment(raw) });
+            mask_next = false;
+            assign_next = false;
+            continue;
+        }
+
+        let (name, value) = match token.kind {
+            LongFlag => split_flag(raw),
+            ShortFlag if raw.len() > 2 => (raw.slice_to(2), Some(raw.slice_from(3))),
+            _ => (raw, None)
+        };
+
+        let is_override = token.is_flag() && override_flag.as_ref().map(|flag| flag.as_slice() == name).unwrap_or(false);
+
+        let shown = match (token.kind.clone(), value) {
+            (_, Some(value)) if is_override => format!("{}={}", name, assignment(value)),
+            (_, None) if is_override => {
+                assign_next = true;
+                raw.to_string()
+            },
+            (LongFlag, Some(_)) if sensitive_long(name) => format!("{}={}", name, REDACTED),
+            (LongFlag, None) => {
+                mask_next = sensitive_long(name);
+                raw.to_string()
+            },
+            (ShortFlag, Some(_)) if sensitive_short(name.char_at(1)) => format!("{}={}", name, REDACTED),
+            (ShortFlag, None) | (Cluster, _) => {
+                let mut shown = "-".to_string();
+
+                for (i, c) in raw.slice_from(1).char_indices() {
+                    shown.push_char(c);
+                    if !layout.value_shorts.contains(&c) { continue }
+
+                    let attached = raw.slice_from(1 + i + c.len_utf8_bytes());
+
+                    if attached.is_empty() {
+                        mask_next = sensitive_short(c);
+                    } else {
+                        shown.push_str(if sensitive_short(c) { REDACTED } else { attached });
+                    }
+
+                    break;
+                }
+
+                shown
+            },
+            _ => raw.to_string()
+        };
+
+        out.push(shown);
+    }
+
+    out
+}
+
+/**
+Decode arguments into an existing struct, in place
+
+Only the fields whose flags are present are overwritten; every other field
+keeps its current value, so required flags and defaults don't apply in this
+mode and a missing flag is never an error. The rest field is only replaced
+when there are positional arguments. A broken configuration is a
+`ConfigError`, as with `decode`, and leaves the target alone.
+*/
+pub fn decode_into<T: Flags + Encodable<FlagEncoder, HammerError>>(target: &mut T, args: &[String]) -> HammerResult<()> {
+    let plan = try!(ParsePlan::<T>::new());
+
+    let mut encoder = FlagEncoder::new();
+    try!(target.encode(&mut encoder));
+
+    let mut decoder = plan.decoder(args);
+    decoder.existing = encoder.field_map();
+
+    *target = try!(FlagParse::decode_flags(&mut decoder));
+    Ok(())
+}
+
+/**
+Decode every line read from `reader` as a separate command line.
+
+Each line is split into arguments the way a shell would split it. Blank
+lines and lines starting with `#` are skipped; every other line produces a
+result, paired with its line number (starting at 1). Errors also carry the
+line number in `HammerError::line`. The configuration of `T` is checked
+as `decode` checks it; when it's broken, every line's result is that
+`ConfigError`.
+*/
+pub fn decode_lines<T: Flags>(reader: &mut Buffer) -> Vec<(uint, HammerResult<T>)> {
+    let plan = ParsePlan::<T>::new();
+    let mut results = vec!();
+    let mut number = 0u;
+
+    loop {
+        let line = match reader.read_line() {
+            Err(ref e) if e.kind == EndOfFile => break,
+            line => line
+        };
+
+        number += 1;
+
+        let result = match line {
+            Err(e) => HammerError::new(format!("could not read line: {}", e)),
+            Ok(line) => {
+                let line = match content_line(line.as_slice(), number == 1) {
+                    Some(line) => line,
+                    None => continue
+                };
+
+                match (shell_split(line), &plan) {
+                    (_, &Err(ref err)) => Err(err.clone()),
+                    (Err(message), _) => HammerError::new(message),
+                    (Ok(args), &Ok(ref plan)) => FlagParse::decode_flags(&mut plan.decoder(args.as_slice()))
+                }
+            }
+        };
+
+        results.push((number, result.map_err(|e| e.at_line(number))));
+    }
+
+    results
+}
+
+/*
+    Whatever a test decodes, the arguments left over must come from the
+    command line, whole or as what's left of a cluster, in their original
+    order. The decode tests go through these instead of the functions they
+    wrap, so a change that drops, repeats or reorders arguments fails every
+    one of them rather than only the tests about `remaining`.
+*/
+#[cfg(test)]
+pub mod harness {
+    use super::{HammerResult, HammerError, Decoded, ParsePlan};
+    use Flags;
+
+    // whether `left` is `arg`, or what's left of it once some of the short
+    // flags clustered in it were taken out
+    fn left_of(left: &str, arg: &str) -> bool {
+        if left == arg { return true }
+        if !left.starts_with("-") || left.starts_with("--") || !arg.starts_with("-") { return false }
+
+        let mut shorts = arg.slice_from(1).chars();
+        left.slice_from(1).chars().all(|c| shorts.any(|s| s == c))
+    }
+
+    pub fn check_remaining(args: &[String], remaining: &[String]) {
+        let mut rest = args.iter();
+
+        for left in remaining.iter() {
+            assert!(rest.any(|arg| left_of(left.as_slice(), arg.as_slice())),
+                "{} is left over from {}, but not in order or not there at all: {}", left, args, remaining);
+        }
+    }
+
+    pub fn decode<T: Flags>(args: &[String]) -> HammerResult<Decoded<T>> {
+        // leftovers come from the files that `@file` arguments name
+        let (args, decoded) = try!(ParsePlan::<T>::new()).decode_all(args);
+        let decoded = try!(decoded.map_err(|errors| HammerError::combine(errors.as_slice())));
+
+        check_remaining(args.as_slice(), decoded.remaining());
+        Ok(decoded)
+    }
+
+    pub fn decode_args<T: Flags>(args: &[String]) -> HammerResult<T> {
+        decode(args).and_then(|decoded| decoded.confirmed())
+    }
+
+    pub fn owned(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    // `decode_args` for a command line written out as string literals
+    pub fn decode_strs<T: Flags>(args: &[&str]) -> HammerResult<T> {
+        decode_args(owned(args).as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlagDecoder, HammerResult, HammerError, Parser, ParsePlan, Layout, Decoded, redacted_args, decode_lines, decode_into, render_error, render_errors, should_page};
+    use super::harness::{decode, decode_args, decode_strs, owned, check_remaining};
+    use super::{InvalidFlags, HelpRequested, VersionRequested, ExitedEarly, ConfigError, ConfirmationNeeded, CompletionRequested};
+    use super::{HammerWarning, WarningKind, SkippedListElement, DuplicateIgnored, IgnoredFlag, RedundantFlag, ClassifiedArgs};
+    use config::{KeepLast, Accumulate, Error, Common, Full, Expert};
+    use config::{FlagConfig, FlagConfiguration, ValueSource, Syntax, EnvLookup};
+    use usage::{FlagSpec, describe, usage, synopsis};
+    use text::format_error_annotated;
+    use token::{TokenKind, LongFlag, ShortFlag, Value};
+    use std::os;
+    use std::io::File;
+    use std::rc::Rc;
+    use {Flags, FlagParse};
+    use std::collections::hashmap::HashMap;
+    use serialize::{Decoder,Decodable};
+    use std::io::BufReader;
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct CompileFlags {
+        color: bool,
+        count: uint,
+        maybe: Option<uint>,
+        some_some: bool
+    }
+
+    hammer_config!(CompileFlags |c| {
+        c.short("color", 'c')
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct GlobalFlags {
+        color: bool,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(GlobalFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct AliasedRest {
+        color: bool,
+        verbose: bool,
+        remaining: Vec<String>
+    }
+
+    hammer_config!(AliasedRest |c| {
+        c.short("verbose", 'v').rest_field("remaining")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct PaintFlags {
+        color: Option<String>,
+        verbose: bool
+    }
+
+    hammer_config!(PaintFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct StrictPaintFlags {
+        color: Option<String>,
+        verbose: bool
+    }
+
+    hammer_config!(StrictPaintFlags |c| { c.strict_spelling() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct CompletingPaintFlags {
+        color: Option<String>,
+        verbose: bool
+    }
+
+    hammer_config!(CompletingPaintFlags |c| { c.shell_completion() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ScriptedPaintFlags {
+        color: Option<String>,
+        verbose: bool
+    }
+
+    hammer_config!(ScriptedPaintFlags |c| { c.short("verbose", 'v').strict_spelling_from_env("HAMMER_TEST_STRICT_FLAGS") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct AmbiguousFlags {
+        color: Option<String>,
+        count: Option<uint>
+    }
+
+    hammer_config!(AmbiguousFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct DefaultedFlags {
+        count: uint,
+        maybe: Option<uint>,
+        plain: uint,
+        verbose: bool
+    }
+
+    hammer_config!(DefaultedFlags |c| {
+        c.default("count", "10").required("maybe")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct MistypedDefaultFlags {
+        count: uint
+    }
+
+    hammer_config!(MistypedDefaultFlags |c| { c.default("count", "ten") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TypedDefaultFlags {
+        jobs: uint,
+        ratio: f64,
+        name: String
+    }
+
+    hammer_config!(TypedDefaultFlags |c| { c.default("jobs", "4").default("ratio", "0.5").default("name", "anon") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct MistypedRatioFlags {
+        ratio: f64
+    }
+
+    hammer_config!(MistypedRatioFlags |c| { c.default("ratio", "half") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct MistypedLaterFlags {
+        count: uint,
+        ratio: f64
+    }
+
+    hammer_config!(MistypedLaterFlags |c| { c.default("ratio", "half") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct CollectedFlags {
+        name: String,
+        count: uint,
+        level: Option<uint>,
+        verbose: bool
+    }
+
+    hammer_config!(CollectedFlags |c| { c.collect_errors() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ListenFlags {
+        port: uint
+    }
+
+    hammer_config!(ListenFlags |c| { c.default("port", "8080").env("port", "HAMMER_TEST_PORT").range("port", 1, 65535) })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct FeatureFlags {
+        features: Vec<String>,
+        sizes: Vec<uint>
+    }
+
+    hammer_config!(FeatureFlags |c| { c.delimited("features", ',').delimited("sizes", ',') })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct OffsetFlags {
+        offset: int,
+        scale: f64,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(OffsetFlags |c| { c.short("verbose", 'v').short("offset", 'o').default("scale", "1") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ConvertedFlags {
+        count: uint,
+        offset: i32,
+        ratio: f64,
+        separator: char
+    }
+
+    hammer_config!(ConvertedFlags |c| {
+        c.default("count", "1").default("offset", "0").default("ratio", "1").default("separator", ",").short("count", 'c')
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct CaseFlags {
+        count: uint,
+        name: Option<String>,
+        color: bool,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(CaseFlags |c| { c.case_insensitive().short("verbose", 'v').negated("color") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ArgfileFlags {
+        count: uint,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(ArgfileFlags |c| { c.short("verbose", 'v').allow_argfiles() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct MergedFlags {
+        verbose: bool,
+        jobs: uint
+    }
+
+    hammer_config!(MergedFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct DigitFlags {
+        single_column: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(DigitFlags |c| { c.short("single_column", '1') })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct HomeFlags {
+        config: String,
+        raw_path: Option<String>
+    }
+
+    hammer_config!(HomeFlags |c| { c.path("config").path("raw_path").no_home_expansion("raw_path") })
+
+    #[deriving(Decodable, Encodable, Show, PartialEq)]
+    struct OutOfRangeFlags {
+        port: uint
+    }
+
+    hammer_config!(OutOfRangeFlags |c| { c.default("port", "99999").range("port", 1, 65535) })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct SecretDefaultFlags {
+        pin: uint
+    }
+
+    hammer_config!(SecretDefaultFlags |c| { c.default("pin", "12ab").sensitive("pin") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ScientificPortFlags {
+        port: uint
+    }
+
+    hammer_config!(ScientificPortFlags |c| { c.default("port", "1e3").range("port", 1, 65535) })
+
+    fn even(value: &str) -> Result<(), String> {
+        match from_str::<uint>(value) {
+            Some(n) if n % 2 == 0 => Ok(()),
+            _ => Err("must be even".to_string())
+        }
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct WorkerFlags {
+        workers: uint
+    }
+
+    hammer_config!(WorkerFlags |c| { c.default("workers", "3").validate("workers", even) })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct MessageFlags {
+        line: String,
+        verbose: bool
+    }
+
+    hammer_config!(MessageFlags |c| {
+        c.join_lines("line", " / ")
+    })
+
+    #[deriving(Decodable, Encodable, Show, PartialEq)]
+    struct Settings {
+        name: String,
+        count: uint,
+        color: Option<String>,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(Settings)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TokenFlags {
+        token: String
+    }
+
+    fn token_config_path() -> Path {
+        os::tmpdir().join("hammer-test-token.conf")
+    }
+
+    hammer_config!(TokenFlags |c| {
+        c.env("token", "HAMMER_TEST_TOKEN")
+         .config_file(token_config_path().as_str().unwrap())
+         .default("token", "from-default")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct Samples {
+        weights: Vec<f64>,
+        rest: Vec<char>
+    }
+
+    hammer_config!(Samples)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ProjectFlags {
+        project: String,
+        list_formats: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(ProjectFlags "Manages projects", |c| {
+        c.version("1.0").exits_early("list_formats").short("list_formats", 'l').help_command()
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct PortFlags {
+        ports: Vec<uint>
+    }
+
+    hammer_config!(PortFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LenientPortFlags {
+        ports: Vec<uint>
+    }
+
+    hammer_config!(LenientPortFlags |c| { c.lenient_list("ports") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct OutputFlags {
+        output_dir: String,
+        raw_dir: Option<String>
+    }
+
+    hammer_config!(OutputFlags |c| { c.expand_env("output_dir") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LenientOutputFlags {
+        output_dir: String
+    }
+
+    hammer_config!(LenientOutputFlags |c| { c.expand_env("output_dir").lenient_expansion() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct BuildFlags {
+        verbose: bool,
+        output: Option<String>,
+        include: Vec<String>,
+        jobs: uint
+    }
+
+    hammer_config!(BuildFlags |c| {
+        c.duplicates(KeepLast)
+         .duplicates_for("verbose", Accumulate)
+         .duplicates_for("output", Error)
+         .duplicates_for("include", Accumulate)
+         .short("verbose", 'v')
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct MisconfiguredFlags {
+        output: Option<String>
+    }
+
+    hammer_config!(MisconfiguredFlags |c| { c.duplicates_for("ouptut", Error) })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct WrappedFlags {
+        jobs: uint,
+        rest: Vec<String>
+    }
+
+    hammer_config!(WrappedFlags |c| { c.duplicates_for("jobs", KeepLast) })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct RenamedFlags {
+        tls_cert: Option<String>,
+        verbose: bool
+    }
+
+    hammer_config!(RenamedFlags |c| {
+        c.required("cert_file")
+         .short("verbos", 'v')
+         .env("tls_key", "HAMMER_TEST_TLS_KEY")
+         .sensitive("tls_cert")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LogFlags {
+        log_level: Option<String>,
+        quiet: bool
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct CommonFlags {
+        verbose: bool,
+        log: LogFlags
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ServeFlags {
+        port: uint,
+        common: CommonFlags,
+        rest: Vec<String>
+    }
+
+    hammer_config!(ServeFlags |c| {
+        c.flatten_field("common").flatten_field("log")
+         .short("verbose", 'v')
+         .short("quiet", 'q')
+         .default("log_level", "info")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TwinFlags {
+        verbose: bool,
+        common: CommonFlags
+    }
+
+    hammer_config!(TwinFlags |c| { c.flatten_field("common").flatten_field("log") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct DoubledFlags {
+        dry_run: bool,
+        dry__run: bool
+    }
+
+    hammer_config!(DoubledFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct RenamedTwinFlags {
+        output: Option<String>,
+        out_file: Option<String>
+    }
+
+    hammer_config!(RenamedTwinFlags |c| { c.flag_name("out_file", "output") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct NestedFlags {
+        port: uint,
+        common: CommonFlags
+    }
+
+    hammer_config!(NestedFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TieredFlags {
+        verbose: bool,
+        jobs: Option<uint>,
+        debug_gc: bool
+    }
+
+    hammer_config!(TieredFlags |c| { c.help_tier("jobs", Full).help_tier("debug_gc", Expert) })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LazyFlags {
+        region: Option<String>,
+        zone: Option<String>,
+        count: uint,
+        verbose: bool
+    }
+
+    hammer_config!(LazyFlags |c| {
+        c.env("region", "HAMMER_TEST_UNSET_REGION")
+         .default("region", "us")
+         .env("zone", "HAMMER_TEST_UNSET_ZONE")
+         .default("count", "1")
+    })
+
+    fn fake_env(var: &str) -> Option<String> {
+        match var {
+            "MYAPP_COLOR" => Some("always".to_string()),
+            "MYAPP_PORT" => Some("8080".to_string()),
+            "MYAPP_QUIET" => Some("no".to_string()),
+            _ => None
+        }
+    }
+
+    // the variables the tests read, so none of them has to set any in the
+    // environment the other tests share
+    fn test_env(var: &str) -> Option<String> {
+        match var {
+            "HAMMER_TEST_STRICT_FLAGS" => Some("1".to_string()),
+            "HAMMER_TEST_PORT" => Some("0".to_string()),
+            "HAMMER_TEST_TOKEN" => Some("from-env".to_string()),
+            "HAMMER_TEST_REPORT_TOKEN" => Some("secret".to_string()),
+            "HAMMER_TEST_PROJECT" => Some("hammer".to_string()),
+            _ => None
+        }
+    }
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    // `decode_strs` for `T` with its variables read from `lookup`
+    fn decode_with_env<T: Flags>(args: &[&str], lookup: EnvLookup) -> HammerResult<T> {
+        let config = FlagConfig::config(None::<T>, FlagConfiguration::new()).env_lookup(lookup);
+        let mut decoder = FlagDecoder::configured::<T>(owned(args).as_slice(), config);
+        FlagParse::decode_flags(&mut decoder)
+    }
+
+    fn broken_env(var: &str) -> Option<String> {
+        match var {
+            "MYAPP_COLOR" => Some("".to_string()),
+            "MYAPP_PORT" => Some("http".to_string()),
+            _ => None
+        }
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LookupEnvFlags {
+        color: bool,
+        quiet: bool,
+        port: uint
+    }
+
+    hammer_config!(LookupEnvFlags |c| {
+        c.env("color", "MYAPP_COLOR").env("quiet", "MYAPP_QUIET").env("port", "MYAPP_PORT").env_lookup(fake_env)
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct BrokenEnvFlags {
+        color: bool,
+        port: uint
+    }
+
+    hammer_config!(BrokenEnvFlags |c| {
+        c.env_lookup(broken_env).env("color", "MYAPP_COLOR").env("port", "MYAPP_PORT")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct SwitchFlags {
+        color: bool,
+        verbose: bool
+    }
+
+    hammer_config!(SwitchFlags |c| { c.default_true("color") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TagFlags {
+        tag: Vec<String>,
+        level: Vec<uint>
+    }
+
+    hammer_config!(TagFlags |c| { c.dedup("tag") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TrailingFlags {
+        count: Option<uint>,
+        color: Option<String>
+    }
+
+    hammer_config!(TrailingFlags |c| { c.lenient_missing_value() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct SyncFlags {
+        exclude: Vec<String>,
+        rest: Vec<String>
+    }
+
+    hammer_config!(SyncFlags |c| { c.values_from_file("exclude") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct DeleteFlags {
+        yes: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(DeleteFlags |c| { c.short("yes", 'y').confirm_with("yes") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct EnvFlags {
+        env: HashMap<String, String>,
+        rest: Vec<String>
+    }
+
+    hammer_config!(EnvFlags |c| { c.leading_assignments("env") })
+
+    #[deriving(Decodable, Encodable, Show, PartialEq)]
+    struct SpelledFlags {
+        output_file: Option<String>,
+        max_count: uint,
+        dry_run: bool
+    }
+
+    hammer_config!(SpelledFlags |c| {
+        c.flag_name("output_file", "out")
+         .flag_name("max_count", "limit")
+         .flag_name("dry_run", "simulate")
+         .duplicates_for("output_file", Error)
+         .ignored_when("output_file", "dry_run", "nothing is written")
+         .help("max_count", "stop after this many")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TunedFlags {
+        count: uint,
+        line_width: Option<uint>,
+        tag: Vec<String>,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(TunedFlags |c| { c.override_flag("o").default("count", "1") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct OverriddenFlags {
+        max_count: uint
+    }
+
+    hammer_config!(OverriddenFlags |c| { c.flag_name("max_count", "limit").override_flag("o") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ClusterFlags {
+        verbose: bool,
+        color: bool,
+        name: Option<String>,
+        rest: Vec<String>
+    }
+
+    hammer_config!(ClusterFlags |c| { c.short("verbose", 'v').short("color", 'c').short("name", 'n') })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    enum Level {
+        Debug,
+        Info,
+        Warn
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LevelFlags {
+        level: Level,
+        fallback: Option<Level>
+    }
+
+    hammer_config!(LevelFlags |c| { c.default("level", "info") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ShortCountFlags {
+        count: uint,
+        verbose: bool
+    }
+
+    hammer_config!(ShortCountFlags |c| { c.short("count", 'c').short("verbose", 'v') })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct NegatedFlags {
+        color: bool,
+        verbose: bool
+    }
+
+    hammer_config!(NegatedFlags |c| { c.negated("color") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct CheckedFlags {
+        color: Option<String>,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(CheckedFlags |c| { c.short("verbose", 'v').reject_unknown_flags() })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct JoinedFlags {
+        name: Option<String>,
+        some_some: Option<uint>,
+        rest: Vec<String>
+    }
+
+    hammer_config!(JoinedFlags)
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct SyntaxFlags {
+        verbose: uint,
+        count: uint,
+        color: Option<String>
+    }
+
+    hammer_config!(SyntaxFlags |c| { c.short("verbose", 'v').counted("verbose").default("count", "0") })
+
+    #[deriving(Decodable, Encodable, Show, PartialEq)]
+    struct ReportFlags {
+        count: uint,
+        color: bool,
+        token: String,
+        output_file: Option<String>,
+        level: Option<uint>,
+        rest: Vec<String>
+    }
+
+    hammer_config!(ReportFlags |c| {
+        c.short("count", 'c')
+         .default("color", "true")
+         .env("token", "HAMMER_TEST_REPORT_TOKEN").sensitive("token")
+         .flag_name("output_file", "out")
+         .env_lookup(test_env)
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct PlannedFlags {
+        name: Option<String>,
+        count: uint,
+        rest: Vec<String>
+    }
+
+    hammer_config!(PlannedFlags |c| {
+        c.short("count", 'c').default("count", "1").flag_name("name", "label")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LauncherFlags {
+        env: HashMap<String, String>,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(LauncherFlags |c| { c.leading_assignments("env").override_flag("o") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct IncludeFlags {
+        env: HashMap<String, String>,
+        includes: Vec<String>,
+        libs: Vec<String>,
+        rest: Vec<String>
+    }
+
+    hammer_config!(IncludeFlags |c| {
+        c.default_list("includes", &["src", "tests"]).delimited("includes", ',')
+         .default_list("libs", &["core"]).default_append("libs")
+         .leading_assignments("env").default_map("env", &[("LANG", "C")]).default_append("env")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct BrokenFlags {
+        count: uint
+    }
+
+    impl FlagConfig for BrokenFlags {
+        fn config(_: Option<BrokenFlags>, c: FlagConfiguration) -> FlagConfiguration {
+            let count: Option<uint> = from_str("ten");
+            c.default("count", count.expect("the default count is not a number").to_string().as_slice())
+        }
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct LoginFlags {
+        user: String,
+        backup_pins: Vec<uint>,
+        pin: uint
+    }
+
+    hammer_config!(LoginFlags |c| {
+        c.sensitive("pin").sensitive("backup_pins").short("pin", 'p').lenient_list("backup_pins")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct VaultFlags {
+        verbose: bool,
+        pin: uint,
+        rest: Vec<String>
+    }
+
+    hammer_config!(VaultFlags |c| {
+        c.sensitive("pin").short("pin", 'p').short("verbose", 'v').case_insensitive().override_flag("o")
+    })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct TlsFlags {
+        tls: bool,
+        cert: Option<String>
+    }
+
+    hammer_config!(TlsFlags)
+
+    fn check_tls(flags: &TlsFlags) -> Result<(), String> {
+        if flags.tls && flags.cert.is_none() {
+            Err("--tls requires --cert".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ExportFlags {
+        output: Option<String>,
+        dry_run: bool
+    }
+
+    hammer_config!(ExportFlags |c| {
+        c.ignored_when("output", "dry_run", "output is not written in dry-run mode")
+    })
+
+    struct FixedSource {
+        name: &'static str,
+        value: &'static str
+    }
+
+    impl ValueSource for FixedSource {
+        fn name(&self) -> String { self.name.to_string() }
+
+        fn lookup(&self, field: &str) -> Option<String> {
+            if field == "token" { Some(self.value.to_string()) } else { None }
+        }
+    }
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct KeyringFlags {
+        token: String,
+        user: Option<String>
+    }
+
+    hammer_config!(KeyringFlags |c| {
+        c.default("token", "from-default")
+         .source_before("default", box FixedSource { name: "keyring", value: "from-keyring" })
+         .source(box FixedSource { name: "fallback", value: "from-fallback" })
+    })
+
+    #[test]
+    fn test_example() {
+        let args = vec!("--count".to_string(), "1".to_string(), "foo".to_string(), "-c".to_string());
+        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
+        let flags: CompileFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(decoder.remaining(), vec!("foo".to_string()));
+        assert_eq!(flags, CompileFlags{ color: true, count: 1u, maybe: None, some_some: false });
+        assert!(!decoder.errored());
+        assert_eq!(decoder.error_message(), None);
+    }
+
+    #[test]
+    fn test_err() {
+        let mut decoder = FlagDecoder::new::<CompileFlags>(vec!().as_slice());
+        let flags: HammerResult<CompileFlags> = Decodable::decode(&mut decoder);
+
+        assert_eq!(flags, HammerError::new("--count is required".to_string()));
+
+        assert!(decoder.errored(), "The decoder has an error");
+        assert_eq!(decoder.error_message(), Some("--count is required"));
+    }
+
+    #[test]
+    fn test_rest() {
+        let args = vec!("--verbose".to_string(), "hello".to_string(), "goodbye".to_string());
+
+        let mut decoder = FlagDecoder::new::<GlobalFlags>(args.as_slice());
+        let flags: GlobalFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, GlobalFlags { color: false, verbose: true, rest: vec!("hello".to_string(), "goodbye".to_string()) });
+    }
+
+    #[test]
+    fn test_aliased_rest() {
+        let args = vec!("-v".to_string(), "hello".to_string(), "goodbye".to_string());
+
+        let mut decoder = FlagDecoder::new::<AliasedRest>(args.as_slice());
+        let flags: AliasedRest = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, AliasedRest { color: false, verbose: true, remaining: vec!("hello".to_string(), "goodbye".to_string()) });
+    }
+
+    #[test]
+    fn test_abbreviated_equals() {
+        let args = vec!("--co=red".to_string(), "--verb".to_string(), "foo".to_string());
+
+        let mut decoder = FlagDecoder::new::<PaintFlags>(args.as_slice());
+        let flags: PaintFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, PaintFlags { color: Some("red".to_string()), verbose: true });
+        assert_eq!(decoder.remaining(), vec!("foo".to_string()));
+    }
+
+    #[test]
+    fn test_short_clusters() {
+        let both = ClusterFlags { verbose: true, color: true, name: None, rest: vec!("a".to_string()) };
+        assert_eq!(decode_strs::<ClusterFlags>(&["-vc", "a"]), Ok(both));
+        assert_eq!(decode_strs::<ClusterFlags>(&["a", "-cv"]).unwrap().rest, vec!("a".to_string()));
+        assert_eq!(decode_strs::<ClusterFlags>(&["-c", "-n", "x"]).unwrap().name, Some("x".to_string()));
+
+        // arguments without any known alias aren't clusters
+        assert_eq!(decode_strs::<ClusterFlags>(&["-xyz"]).unwrap().rest, vec!("-xyz".to_string()));
+
+        let err = decode_strs::<ClusterFlags>(&["a", "-vnc", "x"]).unwrap_err();
+        assert_eq!(err.message, "-n takes a value, so it can't be combined with other flags in -vnc".to_string());
+        assert_eq!(err.index, Some(1));
+
+        let err = decode_strs::<ClusterFlags>(&["-vxc"]).unwrap_err();
+        assert_eq!(err.message, "unknown flag -x in -vxc".to_string());
+        assert_eq!(err.index, Some(0));
+    }
+
+    #[test]
+    fn test_attached_short_values() {
+        let attached = decode_strs::<ClusterFlags>(&["-n10", "a"]).unwrap();
+        assert_eq!(attached, ClusterFlags { verbose: false, color: false, name: Some("10".to_string()), rest: vec!("a".to_string()) });
+        assert_eq!(decode_strs::<ClusterFlags>(&["-n", "10", "a"]), Ok(attached));
+
+        // everything after the alias is the value, even known aliases
+        let flags = decode_strs::<ClusterFlags>(&["-nv", "-c"]).unwrap();
+        assert_eq!((flags.name, flags.verbose, flags.color), (Some("v".to_string()), false, true));
+
+        let err = decode_strs::<ClusterFlags>(&["-v", "-n"]).unwrap_err();
+        assert_eq!(err.message, "--name is missing a following string".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_short_equals() {
+        assert_eq!(decode_strs::<ShortCountFlags>(&["-c=5"]), Ok(ShortCountFlags { count: 5, verbose: false }));
+        assert_eq!(decode_strs::<ShortCountFlags>(&["-c=5"]), decode_strs::<ShortCountFlags>(&["--count=5"]));
+        assert_eq!(decode_strs::<ShortCountFlags>(&["-c", "5", "-v=true"]), Ok(ShortCountFlags { count: 5, verbose: true }));
+
+        let err = decode_strs::<ShortCountFlags>(&["-v", "-c="]).unwrap_err();
+        assert_eq!(err.message, "--count needs a value after -c=".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_unused_arguments_keep_order() {
+        let args = owned(&["foo", "--count", "1", "bar", "-c", "baz"]);
+        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
+        let flags: CompileFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, CompileFlags { color: true, count: 1, maybe: None, some_some: false });
+        assert_eq!(decoder.remaining(), owned(&["foo", "bar", "baz"]));
+
+        // a repeated flag that isn't used stays where it was
+        let args = owned(&["foo", "--count", "1", "bar", "--count", "2", "baz", "--maybe", "3"]);
+        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
+        let _: CompileFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(decoder.remaining(), owned(&["foo", "bar", "--count", "2", "baz"]));
+    }
+
+    #[test]
+    fn test_harness_allows_cluster_remnants() {
+        check_remaining(&["x".to_string(), "-vqc".to_string(), "y".to_string()], &["-q".to_string(), "y".to_string()]);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_harness_catches_reordering() {
+        check_remaining(&["a".to_string(), "b".to_string()], &["b".to_string(), "a".to_string()]);
+    }
+
+    #[test]
+    fn test_consumed_tokens() {
+        let args = owned(&["foo", "--count", "1", "-c", "--", "bar"]);
+
+        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
+        let _: CompileFlags = Decodable::decode(&mut decoder).unwrap();
+
+        let consumed: Vec<(String, uint, TokenKind, Option<String>)> = decoder.consumed.iter()
+            .map(|t| (t.raw.clone(), t.index, t.kind, t.consumed_by.clone())).collect();
+
+        assert_eq!(consumed, vec!(
+            ("-c".to_string(), 3, ShortFlag, Some("color".to_string())),
+            ("--count".to_string(), 1, LongFlag, Some("count".to_string())),
+            ("1".to_string(), 2, Value, Some("count".to_string()))
+        ));
+        assert_eq!(decoder.remaining(), vec!("foo".to_string(), "bar".to_string()));
+    }
+
+    #[test]
+    fn test_clusters_leave_nothing_behind() {
+        let args = owned(&["hello", "-vc", "goodbye"]);
+
+        let mut decoder = FlagDecoder::new::<ClusterFlags>(args.as_slice());
+        let flags: ClusterFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, ClusterFlags { verbose: true, color: true, name: None, rest: vec!("hello".to_string(), "goodbye".to_string()) });
+        assert_eq!(decoder.remaining(), vec!("hello".to_string(), "goodbye".to_string()));
+
+        let args = owned(&["-vqc"]);
+        assert_eq!(decode_args::<ClusterFlags>(args.as_slice()).unwrap_err().message, "unknown flag -q in -vqc".to_string());
+    }
+
+    #[test]
+    fn test_choices() {
+        assert_eq!(decode_strs::<LevelFlags>(&["--level", "warn"]), Ok(LevelFlags { level: Warn, fallback: None }));
+        assert_eq!(decode_strs::<LevelFlags>(&["--fallback=DEBUG"]), Ok(LevelFlags { level: Info, fallback: Some(Debug) }));
+
+        let err = decode_strs::<LevelFlags>(&["--fallback", "warn", "--level", "loud"]).unwrap_err();
+        assert_eq!(err.message, "loud is not one of debug, info, warn".to_string());
+        assert_eq!(err.index, Some(3));
+    }
+
+    #[test]
+    fn test_negated_switch() {
+        let negated = |args: &[&str]| decode::<NegatedFlags>(owned(args).as_slice()).unwrap();
+
+        assert_eq!(negated(&[]).into_inner(), NegatedFlags { color: true, verbose: false });
+        assert_eq!(negated(&["--no-color", "--verbose"]).into_inner(), NegatedFlags { color: false, verbose: true });
+
+        let decoded = negated(&["--color"]);
+        assert_eq!(decoded.color, true);
+        assert_eq!(decoded.warnings(), vec!(HammerWarning {
+            kind: RedundantFlag,
+            message: "--color changes nothing; it's on unless --no-color is given".to_string(),
+            field: Some("color".to_string())
+        }).as_slice());
+
+        let args = owned(&["--no-color", "--verbose", "--color"]);
+        let err = decode::<NegatedFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--color and --no-color can't both be given".to_string());
+        assert_eq!(err.index, Some(2));
+
+        assert_eq!(usage::<NegatedFlags>(false), (None, "[--no-color]\n[--verbose]\n".to_string()));
+        assert_eq!(synopsis::<NegatedFlags>("prog"), "prog [--no-color] [--verbose]".to_string());
+    }
+
+    #[test]
+    fn test_switch_negation() {
+        let global = |args: &[&str]| decode::<GlobalFlags>(owned(args).as_slice()).unwrap();
+
+        let decoded = global(&["--no-color", "hello"]);
+        assert_eq!(decoded.color, false);
+        assert_eq!(decoded.rest, vec!("hello".to_string()));
+        assert_eq!(decoded.remaining(), vec!("hello".to_string()).as_slice());
+
+        // the last one wins
+        assert_eq!(global(&["--color", "--verbose", "--no-color"]).color, false);
+        assert_eq!(global(&["--no-color", "--color"]).color, true);
+        assert_eq!(global(&["--color", "--no-color", "--color", "--no-color"]).color, false);
+    }
+
+    #[test]
+    fn test_unknown_flags() {
+        let err = decode_strs::<CheckedFlags>(&["build", "--colr=red"]).unwrap_err();
+        assert_eq!(err.message, "unknown flag: --colr".to_string());
+        assert_eq!(err.index, Some(1));
+
+        let err = decode_strs::<CheckedFlags>(&["-v", "-x", "build"]).unwrap_err();
+        assert_eq!(err.message, "unknown flag: -x".to_string());
+        assert_eq!(err.index, Some(1));
+
+        let rest = owned(&["build", "-", "-5", "--raw"]);
+        assert_eq!(decode_strs::<CheckedFlags>(&["build", "-", "-v", "-5", "--", "--raw"]), Ok(CheckedFlags { color: None, verbose: true, rest: rest }));
+
+        // only plain negative numbers are values, the same as for classify
+        let err = decode_strs::<CheckedFlags>(&["build", "-1e5"]).unwrap_err();
+        assert_eq!(err.message, "unknown flag: -1e5".to_string());
+        assert_eq!(decode_strs::<CheckedFlags>(&["-inf"]).unwrap_err().message, "unknown flag: -inf".to_string());
+
+        // without reject_unknown_flags, they're left over
+        let args = owned(&["--colr", "red"]);
+        assert_eq!(decode_args::<GlobalFlags>(args.as_slice()).unwrap().rest, args);
+    }
+
+    #[test]
+    fn test_end_of_options() {
+        let args = owned(&["--verbose", "x", "--", "--color", "--verbose", "--", "--help"]);
+
+        let mut decoder = FlagDecoder::new::<GlobalFlags>(args.as_slice());
+        let flags: GlobalFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, GlobalFlags { color: false, verbose: true, rest: owned(&["x", "--color", "--verbose", "--", "--help"]) });
+        assert_eq!(decoder.remaining(), owned(&["x", "--color", "--verbose", "--", "--help"]));
+
+        let args = owned(&["--", "--count", "5"]);
+        assert_eq!(decode_args::<CompileFlags>(args.as_slice()), HammerError::new("--count is required".to_string()));
+
+        let flags = decode_args::<ClusterFlags>(owned(&["-v", "--", "--not-a-flag"]).as_slice()).unwrap();
+        assert!(flags.verbose);
+        assert_eq!(flags.rest, owned(&["--not-a-flag"]));
+
+        assert_eq!(decode_args::<ClusterFlags>(owned(&["-v", "--"]).as_slice()).unwrap().rest, vec!());
+    }
+
+    #[test]
+    fn test_equals_form() {
+        let joined = decode_strs::<JoinedFlags>(&["--some-some=1", "a", "--name=x"]).unwrap();
+        assert_eq!(joined, JoinedFlags { name: Some("x".to_string()), some_some: Some(1), rest: vec!("a".to_string()) });
+        assert_eq!(decode_strs::<JoinedFlags>(&["--some-some", "1", "a", "--name", "x"]).unwrap(), joined);
+
+        assert_eq!(decode_strs::<JoinedFlags>(&["--name=", "a"]).unwrap(), JoinedFlags { name: Some("".to_string()), some_some: None, rest: vec!("a".to_string()) });
+        assert_eq!(decode_strs::<JoinedFlags>(&["--name=a=b"]).unwrap().name, Some("a=b".to_string()));
+    }
+
+    #[test]
+    fn test_syntax() {
+        let args = owned(&["-vv", "--count=2", "--col", "red", "--", "-v"]);
+
+        let decode_with = |syntax: Syntax| {
+            let config = FlagConfig::config(None::<SyntaxFlags>, FlagConfiguration::new()).syntax(syntax);
+            let mut decoder = FlagDecoder::configured::<SyntaxFlags>(args.as_slice(), config);
+            let flags: SyntaxFlags = Decodable::decode(&mut decoder).unwrap();
+
+            (flags, decoder.remaining())
+        };
+
+        assert_eq!(decode_with(Syntax::standard()),
+            (SyntaxFlags { verbose: 2, count: 2, color: Some("red".to_string()) }, owned(&["-v"])));
+
+        assert_eq!(decode_with(Syntax { equals: false, ..Syntax::standard() }),
+            (SyntaxFlags { verbose: 2, count: 0, color: Some("red".to_string()) }, owned(&["--count=2", "-v"])));
+
+        assert_eq!(decode_with(Syntax { clustering: false, ..Syntax::standard() }),
+            (SyntaxFlags { verbose: 0, count: 2, color: Some("red".to_string()) }, owned(&["-vv", "-v"])));
+
+        assert_eq!(decode_with(Syntax { abbreviation: false, ..Syntax::standard() }),
+            (SyntaxFlags { verbose: 2, count: 2, color: None }, owned(&["--col", "red", "-v"])));
+
+        assert_eq!(decode_with(Syntax { passthrough: false, ..Syntax::standard() }),
+            (SyntaxFlags { verbose: 3, count: 2, color: Some("red".to_string()) }, owned(&["--"])));
+    }
+
+    #[test]
+    fn test_strict_spelling() {
+        let args = owned(&["--co=red", "--verb"]);
+
+        let flags = decode_args::<PaintFlags>(args.as_slice()).unwrap();
+        assert_eq!(flags, PaintFlags { color: Some("red".to_string()), verbose: true });
+
+        let err = decode_args::<StrictPaintFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--co is an abbreviation; spell it out as --color".to_string());
+        assert_eq!(err.index, Some(0));
+
+        let args = owned(&["--color", "red", "--verbose"]);
+        let flags = decode_args::<StrictPaintFlags>(args.as_slice()).unwrap();
+        assert_eq!(flags, StrictPaintFlags { color: Some("red".to_string()), verbose: true });
+
+        assert_eq!(usage::<StrictPaintFlags>(false), usage::<PaintFlags>(false));
+    }
+
+    #[test]
+    fn test_strict_spelling_from_env() {
+        let args = ["-v", "--col", "red"];
+
+        let flags = decode_with_env::<ScriptedPaintFlags>(args.as_slice(), no_env).unwrap();
+        assert_eq!(flags, ScriptedPaintFlags { color: Some("red".to_string()), verbose: true });
+
+        let err = decode_with_env::<ScriptedPaintFlags>(args.as_slice(), test_env).unwrap_err();
+        assert_eq!(err.message, "--col is an abbreviation; spell it out as --color".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_long_tokens() {
+        // a token is compared with each flag once, so a huge one is just
+        // another unknown argument
+        let long = format!("--co{}", String::from_char(10240, 'l'));
+        let args = vec!(long.clone(), "--verb".to_string(), "--color".to_string(), String::from_char(10240, '*'));
+
+        let mut decoder = FlagDecoder::new::<PaintFlags>(args.as_slice());
+        let flags: HammerResult<PaintFlags> = Decodable::decode(&mut decoder);
+
+        assert_eq!(flags, Ok(PaintFlags { color: Some(String::from_char(10240, '*')), verbose: true }));
+        assert_eq!(decoder.remaining(), vec!(long));
+
+        // an error about a huge value quotes only the start of it, and the
+        // command line echoes one window of it, with the caret under what fits
+        let huge = String::from_char(10240, 'x');
+        let args = vec!("-c".to_string(), "--count".to_string(), huge.clone());
+
+        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
+        let flags: HammerResult<CompileFlags> = Decodable::decode(&mut decoder);
+        let err = flags.unwrap_err();
+
+        assert_eq!(err.index, Some(2));
+        assert_eq!(format_error_annotated(args.as_slice(), &err),
+            format!("...{}...\n   {}\nerror: --count: could not convert {}... to an integer\n",
+                String::from_char(72, 'x'), String::from_char(72, '^'), String::from_char(64, 'x')));
+    }
+
+    #[test]
+    fn test_ambiguous_abbreviation() {
+        let args = vec!("--co=red".to_string());
+
+        let mut decoder = FlagDecoder::new::<AmbiguousFlags>(args.as_slice());
+        let flags: HammerResult<AmbiguousFlags> = Decodable::decode(&mut decoder);
+
+        let err = flags.unwrap_err();
+        assert_eq!(err.message, "--co is ambiguous; it could be --color, --count".to_string());
+        assert_eq!(err.index, Some(0));
+    }
+
+    #[test]
+    fn test_decode_lines() {
+        let input = "--count 1 -c\n\n# a comment\n--color\n  --count 'two'\n--count 3 --maybe 4\n";
+        let mut reader = BufReader::new(input.as_bytes());
+
+        let results = decode_lines::<CompileFlags>(&mut reader);
+
+        assert_eq!(results, vec!(
+            (1, Ok(CompileFlags { color: true, count: 1, maybe: None, some_some: false })),
+            (4, Err(HammerError { kind: InvalidFlags, message: "--count is required".to_string(), line: Some(4), index: None })),
+            (5, Err(HammerError { kind: InvalidFlags, message: "--count: could not convert two to an integer".to_string(), line: Some(5), index: Some(1) })),
+            (6, Ok(CompileFlags { color: false, count: 3, maybe: Some(4), some_some: false }))
+        ));
+
+        let mut reader = BufReader::new(b"\xef\xbb\xbf--count 1\r\n\r\n--count 2 -c");
+        let results = decode_lines::<CompileFlags>(&mut reader);
+
+        assert_eq!(results, vec!(
+            (1, Ok(CompileFlags { color: false, count: 1, maybe: None, some_some: false })),
+            (3, Ok(CompileFlags { color: true, count: 2, maybe: None, some_some: false }))
+        ));
+
+        // a configuration that's checked and found wrong fails every line
+        let mut reader = BufReader::new(b"--count 1\n\n--count 2\n");
+        let message = "the default ten of count is not an integer".to_string();
+
+        assert_eq!(decode_lines::<MistypedDefaultFlags>(&mut reader), vec!(
+            (1, Err(HammerError { kind: ConfigError, message: message.clone(), line: Some(1), index: None })),
+            (3, Err(HammerError { kind: ConfigError, message: message, line: Some(3), index: None }))
+        ));
+    }
+
+    fn spec(field: &str, required: bool, default: Option<&str>) -> FlagSpec {
+        FlagSpec {
+            field: field.to_string(),
+            flag: format!("--{}", field),
+            short: None,
+            shorts: vec!(),
+            takes_value: field != "verbose",
+            negatable: field == "verbose",
+            counted: false,
+            required: required,
+            default: default.map(|d| d.to_string()),
+            tier: Common,
+            hidden: false,
+            help: None
+        }
+    }
+
+    #[test]
+    fn test_required_agrees_everywhere() {
+        assert_eq!(usage::<DefaultedFlags>(false), (None, "--maybe <INT>\n--plain <INT>\n[--count <INT>]\n[--verbose]\n".to_string()));
+
+        assert_eq!(describe::<DefaultedFlags>(), vec!(
+            spec("count", false, Some("10")),
+            spec("maybe", true, None),
+            spec("plain", true, None),
+            spec("verbose", false, None)
+        ));
+
+        let args = vec!("--maybe".to_string(), "1".to_string(), "--plain".to_string(), "2".to_string());
+        let flags: DefaultedFlags = decode_args(args.as_slice()).unwrap();
+        assert_eq!(flags, DefaultedFlags { count: 10, maybe: Some(1), plain: 2, verbose: false });
+
+        let args = vec!("--count".to_string(), "3".to_string(), "--maybe".to_string(), "1".to_string(), "--plain".to_string(), "2".to_string());
+        let flags: DefaultedFlags = decode_args(args.as_slice()).unwrap();
+        assert_eq!(flags.count, 3);
+
+        let args = vec!("--plain".to_string(), "2".to_string());
+        let flags: HammerResult<DefaultedFlags> = decode_args(args.as_slice());
+        assert_eq!(flags, HammerError::new("--maybe is required".to_string()));
+
+        let args = vec!("--maybe".to_string(), "1".to_string());
+        let flags: HammerResult<DefaultedFlags> = decode_args(args.as_slice());
+        assert_eq!(flags, HammerError::new("--plain is required".to_string()));
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let err = decode_args::<CollectedFlags>(&[]).unwrap_err();
+        assert_eq!(err.message, "2 problems with the command line\n  - --name is required\n  - --count is required".to_string());
+        assert_eq!(err.index, None);
+
+        let args = owned(&["--count", "x", "--level", "y", "--verbose"]);
+        let mut decoder = FlagDecoder::new::<CollectedFlags>(args.as_slice());
+        let result: HammerResult<CollectedFlags> = Decodable::decode(&mut decoder);
+
+        assert_eq!(result.unwrap_err().message.as_slice().lines().count(), 4);
+        assert_eq!(decoder.errors(), vec!(
+            HammerError { kind: InvalidFlags, message: "--name is required".to_string(), line: None, index: None },
+            HammerError { kind: InvalidFlags, message: "--count: could not convert x to an integer".to_string(), line: None, index: Some(1) },
+            HammerError { kind: InvalidFlags, message: "--level: could not convert y to an integer".to_string(), line: None, index: Some(3) }
+        ));
+        assert!(decoder.remaining().is_empty());
+
+        // the plan keeps them apart, for `decode_args_or_exit` to render
+        let plan = ParsePlan::<CollectedFlags>::new().unwrap();
+        assert_eq!(plan.decode_all(args.as_slice()).val1().err(), Some(decoder.errors()));
+
+        // a single problem is reported as it is
+        let args = owned(&["--name", "a", "--count", "x"]);
+        let err = decode_args::<CollectedFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--count: could not convert x to an integer".to_string());
+        assert_eq!(err.index, Some(3));
+    }
+
+    #[test]
+    fn test_delimited_lists() {
+        let features = |args: &[&str]| decode_strs::<FeatureFlags>(args);
+
+        assert_eq!(features(&["--features", "a,b,c"]).unwrap().features, owned(&["a", "b", "c"]));
+        assert_eq!(features(&["--features", "a,,b,", "--features", "c"]).unwrap().features, owned(&["a", "", "b", "", "c"]));
+        assert_eq!(features(&["--sizes", "1,2", "--sizes=3"]).unwrap().sizes, vec!(1u, 2, 3));
+
+        let err = features(&["--features", "a", "--sizes", "1,x,3"]).unwrap_err();
+        assert_eq!(err.message, "--sizes: could not convert x to an integer".to_string());
+        assert_eq!(err.index, Some(3));
+    }
+
+    #[test]
+    fn test_path_flags() {
+        let home = os::homedir().unwrap().as_str().unwrap().to_string();
+        let args = owned(&["--config", "~/file", "--raw-path", "~/file"]);
+
+        assert_eq!(decode_args::<HomeFlags>(args.as_slice()), Ok(HomeFlags {
+            config: format!("{}/file", home),
+            raw_path: Some("~/file".to_string())
+        }));
+
+        assert_eq!(usage::<HomeFlags>(false), (None, "--config <PATH>\n[--raw-path <PATH>]\n".to_string()));
+    }
+
+    #[test]
+    fn test_value_checks() {
+        let port = |args: &[&str]| decode_strs::<ListenFlags>(args);
+
+        assert_eq!(port(&[]), Ok(ListenFlags { port: 8080 }));
+        assert_eq!(port(&["--port", "443"]), Ok(ListenFlags { port: 443 }));
+
+        let err = port(&["--port", "99999"]).unwrap_err();
+        assert_eq!(err.message, "--port: 99999 must be between 1 and 65535".to_string());
+        assert_eq!(err.index, Some(1));
+
+        // the environment can only be checked when it's read
+        let err = decode_with_env::<ListenFlags>(&[], test_env).unwrap_err();
+
+        assert_eq!(err.message, "--port: 0 must be between 1 and 65535 (from env HAMMER_TEST_PORT)".to_string());
+        assert_eq!(err.index, None);
+    }
+
+    #[test]
+    fn test_defaults_checked_with_the_config() {
+        let err = decode_args::<OutOfRangeFlags>(&[]).unwrap_err();
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "the default 99999 of port must be between 1 and 65535".to_string());
+
+        // even when the flag is given
+        let args = vec!("--workers".to_string(), "4".to_string());
+        let err = decode_args::<WorkerFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "the default 3 of workers must be even".to_string());
+
+        // within the range as a float, but not an integer
+        let err = decode_args::<ScientificPortFlags>(&[]).unwrap_err();
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "the default 1e3 of port is not an integer".to_string());
+
+        // a sensitive default isn't given away
+        let err = decode_args::<SecretDefaultFlags>(&[]).unwrap_err();
+        assert_eq!(err.message, "the default ***** of pin is not an integer".to_string());
+    }
+
+    #[test]
+    fn test_default_converted_like_a_value() {
+        let flags: HammerResult<MistypedDefaultFlags> = decode_args(&[]);
+        assert_eq!(flags, Err(HammerError::early(ConfigError, "the default ten of count is not an integer".to_string())));
+
+        // even when the flag is given
+        let args = vec!("--count".to_string(), "4".to_string());
+        let err = decode_args::<MistypedDefaultFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "the default ten of count is not an integer".to_string());
+
+        let err = decode_args::<MistypedRatioFlags>(&[]).unwrap_err();
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "the default half of ratio is not a float".to_string());
+
+        // a decoder made directly only finds out when it reads the default
+        let mut decoder = FlagDecoder::new::<MistypedDefaultFlags>(&[]);
+        let flags: HammerResult<MistypedDefaultFlags> = Decodable::decode(&mut decoder);
+        assert_eq!(flags, Err(HammerError::early(ConfigError, "the default ten of count is not an integer".to_string())));
+    }
+
+    #[test]
+    fn test_typed_defaults() {
+        assert_eq!(decode_args::<TypedDefaultFlags>(&[]), Ok(TypedDefaultFlags { jobs: 4, ratio: 0.5, name: "anon".to_string() }));
+
+        let args = owned(&["--ratio", "2", "--name", "me"]);
+        assert_eq!(decode_args::<TypedDefaultFlags>(args.as_slice()), Ok(TypedDefaultFlags { jobs: 4, ratio: 2.0, name: "me".to_string() }));
+
+        // a default read after a flag that was given is still blamed on the configuration
+        let args = vec!("--count".to_string(), "4".to_string());
+        let err = decode_args::<MistypedLaterFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "the default half of ratio is not a float".to_string());
+    }
+
+    #[test]
+    fn test_joined_lines() {
+        let args = vec!("--line".to_string(), "a".to_string(), "-v".to_string(), "--verbose".to_string(),
+                        "--line=b".to_string(), "--line".to_string(), "c".to_string());
+
+        let mut decoder = FlagDecoder::new::<MessageFlags>(args.as_slice());
+        let flags: MessageFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, MessageFlags { line: "a / b / c".to_string(), verbose: true });
+        assert_eq!(decoder.remaining(), vec!("-v".to_string()));
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let mut settings = Settings {
+            name: "old".to_string(),
+            count: 1,
+            color: Some("red".to_string()),
+            verbose: false,
+            rest: vec!("file".to_string())
+        };
+
+        let args = vec!("--count".to_string(), "5".to_string(), "--verbose".to_string());
+        decode_into(&mut settings, args.as_slice()).unwrap();
+
+        assert_eq!(settings, Settings {
+            name: "old".to_string(),
+            count: 5,
+            color: Some("red".to_string()),
+            verbose: true,
+            rest: vec!("file".to_string())
+        });
+
+        // the configuration is checked first, even when its broken default isn't used
+        let mut flags = OutOfRangeFlags { port: 80 };
+        let args = owned(&["--port", "8080"]);
+        let err = decode_into(&mut flags, args.as_slice()).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "the default 99999 of port must be between 1 and 65535".to_string());
+        assert_eq!(flags, OutOfRangeFlags { port: 80 });
+    }
+
+    #[test]
+    fn test_format_error_annotated() {
+        let args = vec!("-c".to_string(), "--count".to_string(), "two".to_string());
+        let err = decode_args::<CompileFlags>(args.as_slice()).unwrap_err();
+
+        assert_eq!(format_error_annotated(args.as_slice(), &err),
+            "-c --count two\n           ^^^\nerror: --count: could not convert two to an integer\n".to_string());
+    }
+
+    #[test]
+    fn test_format_error_annotated_quoting() {
+        let args = vec!("--line".to_string(), "a b".to_string(), "--count".to_string(), "x y".to_string());
+        let err = decode_args::<CompileFlags>(args.as_slice()).unwrap_err();
+
+        assert_eq!(format_error_annotated(args.as_slice(), &err),
+            "--line 'a b' --count 'x y'\n                     ^^^^^\nerror: --count: could not convert x y to an integer\n".to_string());
+    }
+
+    #[test]
+    fn test_format_error_annotated_window() {
+        let mut args: Vec<String> = range(0u, 40).map(|i| format!("file{}", i)).collect();
+        args.push("--count".to_string());
+        args.push("oops".to_string());
+
+        let err = decode_args::<CompileFlags>(args.as_slice()).unwrap_err();
+        let rendered = format_error_annotated(args.as_slice(), &err);
+        let lines: Vec<&str> = rendered.as_slice().lines().collect();
+
+        assert!(lines[0].starts_with("..."));
+        assert!(lines[0].ends_with("--count oops"));
+        assert_eq!(lines[1].find('^'), lines[0].find_str("oops"));
+    }
+
+    #[test]
+    fn test_render_error_plain() {
+        let args = vec!();
+        let err = decode_args::<CompileFlags>(args.as_slice()).unwrap_err();
+
+        assert_eq!(render_error(args.as_slice(), &err, false), "error: --count is required\n".to_string());
+        assert_eq!(render_error(args.as_slice(), &err, true), "\x1b[1;31merror:\x1b[0m --count is required\n".to_string());
+    }
+
+    #[test]
+    fn test_render_error_annotated() {
+        let args = vec!("--count".to_string(), "x".to_string());
+        let err = decode_args::<CompileFlags>(args.as_slice()).unwrap_err();
+
+        assert_eq!(render_error(args.as_slice(), &err, false), "error: --count: could not convert x to an integer\n".to_string());
+        assert_eq!(render_error(args.as_slice(), &err, true),
+            "--count x\n        \x1b[1;31m^\x1b[0m\n\x1b[1;31merror:\x1b[0m --count: could not convert x to an integer\n".to_string());
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let args = owned(&["--user", "me", "-p", "12ab"]);
+        let invalid = decode_args::<LoginFlags>(args.as_slice()).unwrap_err();
+
+        let errors = vec!(
+            invalid,
+            HammerError { kind: InvalidFlags, message: "--user needs a value".to_string(), line: Some(3), index: None },
+            HammerError { kind: ConfigError, message: "pin has two short forms".to_string(), line: None, index: None }
+        );
+
+        assert_eq!(render_errors::<LoginFlags>(args.as_slice(), errors.as_slice()), concat!(
+            "error: 3 problems with the command line\n",
+            "  - --pin: could not convert ***** to an integer (at arg 4, *****)\n",
+            "  - --user needs a value (at line 3)\n",
+            "  - pin has two short forms (in the program's flag configuration)\n",
+            "run with --help to see the available flags\n").to_string());
+    }
+
+    #[test]
+    fn test_value_precedence() {
+        let config = FlagConfig::config(None::<TokenFlags>, FlagConfiguration::new());
+        assert_eq!(config.source_names(), vec!("env".to_string(), "file".to_string(), "default".to_string()));
+
+        let path = token_config_path();
+        let cli = vec!("--token".to_string(), "from-cli".to_string());
+        let token = |args: &[String]| decode_args::<TokenFlags>(args).unwrap().token;
+
+        File::create(&path).write_str("# tokens\ntoken = from-file\n").unwrap();
+        let token_from_env = |args: &[&str]| decode_with_env::<TokenFlags>(args, test_env).unwrap().token;
+
+        assert_eq!(token_from_env(&["--token", "from-cli"]), "from-cli".to_string());
+        assert_eq!(token_from_env(&[]), "from-env".to_string());
+
+        assert_eq!(token(cli.as_slice()), "from-cli".to_string());
+        assert_eq!(token(&[]), "from-file".to_string());
+
+        File::create(&path).write_str("other = 1\n").unwrap();
+        assert_eq!(token(&[]), "from-default".to_string());
+    }
+
+    #[test]
+    fn test_confirmation() {
+        let flags = decode_args::<DeleteFlags>(&["-y".to_string(), "a".to_string()]);
+        assert_eq!(flags, Ok(DeleteFlags { yes: true, rest: vec!("a".to_string()) }));
+
+        let mut decoder = FlagDecoder::new::<DeleteFlags>(&["-y".to_string()]);
+        let _: DeleteFlags = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(decoder.unconfirmed(true), None);
+
+        let mut decoder = FlagDecoder::new::<DeleteFlags>(&["a".to_string()]);
+        let flags: DeleteFlags = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(decoder.unconfirmed(false), None);
+        assert_eq!(decoder.unconfirmed(true), Some("--yes".to_string()));
+
+        let decoded = Decoded::new(flags, Rc::new(FlagConfiguration::new())).with_confirmation(Some("--yes".to_string()));
+        assert!(decoded.needs_confirmation());
+
+        let err = decoded.confirmed().unwrap_err();
+        assert_eq!(err.kind, ConfirmationNeeded);
+        assert_eq!(err.message, "confirmation needed; pass --yes to go ahead".to_string());
+    }
+
+    #[test]
+    fn test_decoded_accessors() {
+        let args = owned(&["x", "--color", "red", "--verbose", "--color", "blue"]);
+
+        let decoded = decode::<PaintFlags>(args.as_slice()).unwrap();
+
+        assert_eq!(decoded.color, Some("red".to_string()));
+        assert!(decoded.verbose);
+        assert_eq!(decoded.remaining(), vec!("x".to_string(), "--color".to_string(), "blue".to_string()).as_slice());
+        assert_eq!(decoded.warnings().iter().map(|w| w.kind.clone()).collect::<Vec<WarningKind>>(), vec!(DuplicateIgnored));
+        assert!(!decoded.needs_confirmation());
+        assert_eq!(decoded.value(), &*decoded);
+
+        assert_eq!(decoded.into_inner(), PaintFlags { color: Some("red".to_string()), verbose: true });
+    }
+
+    #[test]
+    fn test_report() {
+        let args = owned(&["build", "-c", "3", "--out", "a.txt"]);
+
+        let decoded = decode::<ReportFlags>(args.as_slice()).unwrap();
+
+        assert_eq!(decoded.report(), vec!(
+            "count = 3 (from --count at arg 2)",
+            "color = true (default)",
+            "token = ***** (from env HAMMER_TEST_REPORT_TOKEN)",
+            "out = a.txt (from --out at arg 4)",
+            "level = none (not given)",
+            "rest = [build] (from the remaining arguments)",
+            ""
+        ).connect("\n"));
+    }
+
+    #[test]
+    fn test_completion_request() {
+        let args = owned(&["--hammer-complete", "--verbose", "--co"]);
+        let err = decode_args::<CompletingPaintFlags>(args.as_slice()).unwrap_err();
+
+        assert_eq!(err, HammerError::early(CompletionRequested, "--color".to_string()));
+
+        // a program that doesn't ask for completion decodes it like anything else
+        match decode_args::<PaintFlags>(args.as_slice()) {
+            Err(ref err) if err.kind == CompletionRequested => fail!("completed without shell_completion"),
+            _ => ()
+        }
+    }
+
+    #[test]
+    fn test_leading_assignments() {
+        let args = owned(&["FOO=1", "BAR=2", "build", "-v", "BAZ=3"]);
+        let flags = decode_args::<EnvFlags>(args.as_slice()).unwrap();
+
+        let mut env = HashMap::new();
+        env.insert("FOO".to_string(), "1".to_string());
+        env.insert("BAR".to_string(), "2".to_string());
+
+        assert_eq!(flags.env, env);
+        assert_eq!(flags.rest, vec!("build".to_string(), "-v".to_string(), "BAZ=3".to_string()));
+
+        let args = owned(&["build", "FOO=1"]);
+        let flags = decode_args::<EnvFlags>(args.as_slice()).unwrap();
+
+        assert!(flags.env.is_empty());
+        assert_eq!(flags.rest, args);
+        assert_eq!(synopsis::<EnvFlags>("env"), "env [NAME=VALUE...] [rest...]".to_string());
+    }
+
+    #[test]
+    fn test_collection_defaults() {
+        let flags = decode_strs::<IncludeFlags>(&[]).unwrap();
+        assert_eq!(flags.includes, owned(&["src", "tests"]));
+        assert_eq!(flags.libs, owned(&["core"]));
+        assert_eq!(flags.env.find_equiv(&"LANG"), Some(&"C".to_string()));
+
+        // given values replace the defaults, unless they're appended
+        let flags = decode_strs::<IncludeFlags>(&["LANG=en", "FOO=1", "--includes", "lib", "--libs", "io", "--libs", "net"]).unwrap();
+        assert_eq!(flags.includes, owned(&["lib"]));
+        assert_eq!(flags.libs, owned(&["core", "io", "net"]));
+        assert_eq!(flags.env.len(), 2);
+        assert_eq!(flags.env.find_equiv(&"LANG"), Some(&"en".to_string()));
+        assert_eq!(flags.env.find_equiv(&"FOO"), Some(&"1".to_string()));
+
+        // an empty delimited value is an empty list, not the default
+        assert_eq!(decode_strs::<IncludeFlags>(&["--includes="]).unwrap().includes, vec!());
+    }
+
+    #[test]
+    fn test_flag_names_everywhere() {
+        use usage::{full_usage, completions};
+        use text::quoted_command;
+
+        let mut shown = vec!();
+
+        let failing: Vec<Vec<&str>> = vec!(vec!(), vec!("--limit"), vec!("--limit", "x"), vec!("--limit", "1", "--out", "a", "--out", "b"));
+
+        for line in failing.iter() {
+            shown.push(decode_args::<SpelledFlags>(owned(line.as_slice()).as_slice()).unwrap_err().message);
+        }
+
+        let mut decoder = FlagDecoder::new::<SpelledFlags>(owned(&["--limit", "1", "--out", "a", "--simulate"]).as_slice());
+        let flags: SpelledFlags = Decodable::decode(&mut decoder).unwrap();
+        shown.extend(decoder.warnings().move_iter().map(|w| w.message));
+        shown.push_all(decoder.trace().as_slice());
+
+        shown.push(usage::<SpelledFlags>(false).val1());
+        shown.push(full_usage::<SpelledFlags>(Some("prog")));
+        shown.extend(describe::<SpelledFlags>().move_iter().map(|spec| spec.flag));
+        shown.push_all(completions::<SpelledFlags>(owned(&["-"]).as_slice()).as_slice());
+        shown.push(quoted_command("prog", &flags));
+
+        for text in shown.iter() {
+            for raw in ["output-file", "output_file", "max-count", "max_count", "dry-run", "dry_run"].iter() {
+                assert!(!text.as_slice().contains(*raw), "{} appears in {}", raw, text);
+            }
+        }
+
+        assert_eq!(shown[0], "--limit is required".to_string());
+        assert_eq!(quoted_command("prog", &flags), "prog --out a --limit 1 --simulate".to_string());
+
+        // the old spelling is no longer a flag
+        let flags = decode_args::<SpelledFlags>(owned(&["--output-file", "a", "--limit", "1"]).as_slice()).unwrap();
+        assert_eq!(flags.output_file, None);
+    }
+
+    #[test]
+    fn test_override_flag() {
+        assert_eq!(decode_strs::<TunedFlags>(&["a", "-o", "count=5", "-o", "line-width=80", "b", "-o", "verbose=true"]), Ok(TunedFlags {
+            count: 5,
+            line_width: Some(80),
+            tag: vec!(),
+            verbose: true,
+            rest: vec!("a".to_string(), "b".to_string())
+        }));
+
+        assert_eq!(decode_strs::<TunedFlags>(&["--count", "3", "-o", "count=5"]).unwrap().count, 3);
+        assert_eq!(decode_strs::<TunedFlags>(&["-o", "count=5", "-o", "count=6"]).unwrap().count, 6);
+        assert_eq!(decode_strs::<TunedFlags>(&[]).unwrap().count, 1);
+        assert_eq!(decode_strs::<TunedFlags>(&["-o", "tag=a", "-o", "tag=b"]).unwrap().tag, vec!("a".to_string(), "b".to_string()));
+        assert_eq!(decode_strs::<TunedFlags>(&["--tag", "c", "-o", "tag=a"]).unwrap().tag, vec!("c".to_string()));
+
+        let err = decode_strs::<TunedFlags>(&["-o", "count=x"]).unwrap_err();
+        assert_eq!((err.message, err.index), ("--count: could not convert x to an integer".to_string(), Some(1)));
+
+        let err = decode_strs::<TunedFlags>(&["-o", "size=1"]).unwrap_err();
+        assert_eq!((err.message, err.index), ("-o: unknown field size".to_string(), Some(1)));
+
+        let err = decode_strs::<TunedFlags>(&["-v", "-o", "count"]).unwrap_err();
+        assert_eq!((err.message, err.index), ("-o expects field=value".to_string(), Some(2)));
+
+        let err = decode_strs::<TunedFlags>(&["-o"]).unwrap_err();
+        assert_eq!((err.message, err.index), ("-o is missing a following string".to_string(), Some(0)));
+
+        // a renamed field goes by the name of its flag
+        let args = vec!("-o".to_string(), "limit=3".to_string());
+        assert_eq!(decode_args::<OverriddenFlags>(args.as_slice()), Ok(OverriddenFlags { max_count: 3 }));
+
+        let args = vec!("-o".to_string(), "max-count=3".to_string());
+        assert_eq!(decode_args::<OverriddenFlags>(args.as_slice()).unwrap_err().message, "-o: unknown field max-count".to_string());
+    }
+
+    #[test]
+    fn test_classify() {
+        let args = owned(&["FOO=1", "-o", "verbose=true", "build", "--unknown", "x", "--", "-x", "y"]);
+
+        let mut decoder = FlagDecoder::new::<LauncherFlags>(args.as_slice());
+        let flags: LauncherFlags = Decodable::decode(&mut decoder).unwrap();
+        assert!(flags.verbose);
+
+        assert_eq!(decoder.classify(), ClassifiedArgs {
+            positionals: owned(&["build", "x"]),
+            assignments: vec!(("FOO".to_string(), "1".to_string())),
+            overrides: vec!(("verbose".to_string(), "true".to_string())),
+            unknown_flags: owned(&["--unknown"]),
+            passthrough: owned(&["-x", "y"])
+        });
+
+        let args = owned(&["-5", "-1e5", "-inf"]);
+        let mut decoder = FlagDecoder::new::<LauncherFlags>(args.as_slice());
+        let _: LauncherFlags = Decodable::decode(&mut decoder).unwrap();
+
+        let classified = decoder.classify();
+        assert_eq!((classified.positionals, classified.unknown_flags), (owned(&["-5"]), owned(&["-1e5", "-inf"])));
+    }
+
+    #[test]
+    fn test_parse_plan() {
+        let corpus = vec!(
+            owned(&[]),
+            owned(&["--count", "1", "foo", "-c"]),
+            owned(&["--count=2", "--color"]),
+            owned(&["--cou", "3", "--some-some", "--maybe", "x"]),
+            owned(&["--count", "four"]),
+            owned(&["--count"]),
+            owned(&["--help"]),
+            owned(&["-c", "--unknown", "bar"])
+        );
+
+        let plan = ParsePlan::<CompileFlags>::new().unwrap();
+
+        for args in corpus.iter() {
+            assert_eq!(plan.decode_args(args.as_slice()), decode_args::<CompileFlags>(args.as_slice()));
+
+            match (plan.decode(args.as_slice()), decode::<CompileFlags>(args.as_slice())) {
+                (Ok(planned), Ok(decoded)) => {
+                    assert_eq!(planned.remaining(), decoded.remaining());
+                    assert_eq!(planned.warnings(), decoded.warnings());
+                },
+                (planned, decoded) => assert_eq!(planned.err(), decoded.err())
+            }
+        }
+
+        assert_eq!(ParsePlan::<BrokenFlags>::new().err(), decode_args::<BrokenFlags>(&[]).err());
+    }
+
+    #[test]
+    fn test_parse_plan_shares_its_layout() {
+        let plan = ParsePlan::<PlannedFlags>::new().unwrap();
+        let args = vec!("-c".to_string(), "2".to_string(), "--label".to_string(), "x".to_string());
+
+        let first = plan.decoder(args.as_slice());
+        let second = plan.decoder(args.as_slice());
+
+        assert!(&*first.config as *const FlagConfiguration == &*second.config as *const FlagConfiguration);
+        assert!(&*first.layout as *const Layout == &*second.layout as *const Layout);
+
+        let count = first.layout.fields.find_equiv(&"count").unwrap();
+        assert_eq!(count.flag, "--count".to_string());
+        assert_eq!(count.shorts, vec!('c'));
+        assert_eq!(first.layout.fields.find_equiv(&"name").unwrap().flag, "--label".to_string());
+
+        let flags = plan.decode_args(args.as_slice()).unwrap();
+        assert_eq!(flags, PlannedFlags { name: Some("x".to_string()), count: 2, rest: vec!() });
+    }
+
+    #[test]
+    fn test_failing_config() {
+        let err = decode_args::<BrokenFlags>(&[]).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert!(err.message.as_slice().contains("BrokenFlags"));
+        assert!(err.message.as_slice().ends_with("failed: the default count is not a number"));
+
+        assert_eq!(usage::<BrokenFlags>(false), (None, "--count <INT>\n".to_string()));
+    }
+
+    #[test]
+    fn test_failing_config_everywhere() {
+        use usage::completions;
+
+        let args = vec!("--count".to_string(), "1".to_string());
+
+        let mut decoder = FlagDecoder::new::<BrokenFlags>(args.as_slice());
+        let decoded: HammerResult<BrokenFlags> = Decodable::decode(&mut decoder);
+        assert_eq!(decoded.unwrap_err().kind, ConfigError);
+
+        let mut reader = BufReader::new(b"--count 1\n");
+        let (line, result) = decode_lines::<BrokenFlags>(&mut reader).move_iter().next().unwrap();
+        assert_eq!(line, 1);
+        assert_eq!(result.unwrap_err().kind, ConfigError);
+
+        assert_eq!(redacted_args::<BrokenFlags>(args.as_slice()), args);
+        assert_eq!(completions::<BrokenFlags>(&["--c".to_string()]), vec!("--count".to_string()));
+    }
+
+    #[test]
+    fn test_custom_source() {
+        let config = FlagConfig::config(None::<KeyringFlags>, FlagConfiguration::new());
+        assert_eq!(config.source_names(), owned(&["env", "file", "keyring", "default", "fallback"]));
+
+        let mut decoder = FlagDecoder::new::<KeyringFlags>(&[]);
+        let flags: KeyringFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, KeyringFlags { token: "from-keyring".to_string(), user: None });
+        assert_eq!(decoder.trace().as_slice().slice_to(2), &[
+            "--token: cli".to_string(),
+            "--token: keyring".to_string()
+        ]);
+
+        let args = vec!("--token".to_string(), "from-cli".to_string());
+        assert_eq!(decode_args::<KeyringFlags>(args.as_slice()).unwrap().token, "from-cli".to_string());
+    }
+
+    fn warning_messages(decoder: &FlagDecoder, kind: WarningKind) -> Vec<String> {
+        decoder.warnings().move_iter().filter(|w| w.kind == kind).map(|w| w.message).collect()
+    }
+
+    #[test]
+    fn test_ignored_when() {
+        let warnings = |args: &[&str]| {
+            let args = owned(args);
+            let mut decoder = FlagDecoder::new::<ExportFlags>(args.as_slice());
+            let _: ExportFlags = Decodable::decode(&mut decoder).unwrap();
+            decoder.warnings()
+        };
+
+        assert_eq!(warnings(&["--output", "out.txt", "--dry-run"]), vec!(HammerWarning {
+            kind: IgnoredFlag,
+            message: "--output is ignored because --dry-run was given (output is not written in dry-run mode)".to_string(),
+            field: Some("output".to_string())
+        }));
+        assert_eq!(warnings(&["--output", "out.txt"]), vec!());
+        assert_eq!(warnings(&["--dry-run"]), vec!());
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let parser = Parser::<TlsFlags>::new().validate_all(check_tls);
+
+        let err = parser.decode_args(owned(&["--tls"]).as_slice()).unwrap_err();
+        assert_eq!(err.kind, InvalidFlags);
+        assert_eq!(err.message, "--tls requires --cert".to_string());
+
+        assert_eq!(parser.decode_args(owned(&["--tls", "--cert", "a.pem"]).as_slice()),
+            Ok(TlsFlags { tls: true, cert: Some("a.pem".to_string()) }));
+        assert_eq!(parser.decode_args(owned(&[]).as_slice()), Ok(TlsFlags { tls: false, cert: None }));
+    }
+
+    #[test]
+    fn test_remaining_keeps_order() {
+        let args = owned(&["a", "--tag=x", "b", "--level", "2", "c", "--tag", "x", "d"]);
+
+        let mut decoder = FlagDecoder::new::<TagFlags>(args.as_slice());
+        let _: TagFlags = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(decoder.remaining(), vec!("a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()));
+
+        // the second --color is left alone by the default duplicate policy
+        let args = owned(&["x", "--color", "red", "y", "--color", "blue", "z"]);
+
+        let mut decoder = FlagDecoder::new::<PaintFlags>(args.as_slice());
+        let _: PaintFlags = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(decoder.remaining(), owned(&["x", "y", "--color", "blue", "z"]));
+        assert_eq!(warning_messages(&decoder, DuplicateIgnored), vec!("--color was given more than once; only the first was used".to_string()));
+    }
+
+    #[test]
+    fn test_sensitive_values() {
+        let args = owned(&["--user", "me", "--backup-pins", "12cd", "-p", "12ab"]);
+
+        let mut decoder = FlagDecoder::new::<LoginFlags>(args.as_slice());
+        let result: HammerResult<LoginFlags> = Decodable::decode(&mut decoder);
+        let err = result.unwrap_err();
+
+        assert_eq!(err.message, "--pin: could not convert ***** to an integer".to_string());
+        assert_eq!(decoder.warnings()[0].message, "--backup-pins: skipped ***** (could not convert ***** to an integer)".to_string());
+
+        let annotated = format_error_annotated(redacted_args::<LoginFlags>(args.as_slice()).as_slice(), &err);
+        assert_eq!(annotated, "--user me --backup-pins ***** -p *****\n                                 ^^^^^\nerror: --pin: could not convert ***** to an integer\n".to_string());
+
+        let output = format!("{} {} {}", err, decoder.warnings(), decoder.trace());
+        assert!(!output.as_slice().contains("12ab") && !output.as_slice().contains("12cd"), "leaked a value: {}", output);
+
+        let args = vec!("--pin=12ab".to_string());
+        assert_eq!(redacted_args::<LoginFlags>(args.as_slice()), vec!("--pin=*****".to_string()));
+    }
+
+    #[test]
+    fn test_redacted_like_decoded() {
+        let redacted = |args: &[&str]| redacted_args::<VaultFlags>(owned(args).as_slice());
+
+        assert_eq!(redacted(&["-p12ab"]), owned(&["-p*****"]));
+        assert_eq!(redacted(&["-p=12ab"]), owned(&["-p=*****"]));
+        assert_eq!(redacted(&["-vp", "12ab"]), owned(&["-vp", "*****"]));
+        assert_eq!(redacted(&["-vp12ab"]), owned(&["-vp*****"]));
+        assert_eq!(redacted(&["--PIN", "1234"]), owned(&["--PIN", "*****"]));
+        assert_eq!(redacted(&["--Pi=1234"]), owned(&["--Pi=*****"]));
+        assert_eq!(redacted(&["-o", "pin=1234"]), owned(&["-o", "pin=*****"]));
+        assert_eq!(redacted(&["-o=pin=1234"]), owned(&["-o=pin=*****"]));
+
+        // nothing else is touched
+        assert_eq!(redacted(&["-o", "verbose=true", "-v", "12ab"]), owned(&["-o", "verbose=true", "-v", "12ab"]));
+        assert_eq!(redacted(&["--", "-p", "12ab"]), owned(&["--", "-p", "12ab"]));
+
+        // the decoder reads each of them as the pin
+        assert_eq!(decode_strs::<VaultFlags>(&["-p12ab"]).unwrap_err().message, "--pin: could not convert ***** to an integer".to_string());
+        assert_eq!(decode_strs::<VaultFlags>(&["--PIN", "1234"]).unwrap().pin, 1234);
+        assert_eq!(decode_strs::<VaultFlags>(&["-o", "pin=1234"]).unwrap().pin, 1234);
+    }
+
+    #[test]
+    fn test_trailing_flag_without_value() {
+        let err = decode_args::<CompileFlags>(&["--count".to_string()]).unwrap_err();
+        assert_eq!(err.message, "--count is missing a following string".to_string());
+        assert_eq!(err.index, Some(0));
+
+        let err = decode_args::<PaintFlags>(&["--verbose".to_string(), "--color".to_string()]).unwrap_err();
+        assert_eq!(err.message, "--color is missing a following string".to_string());
+        assert_eq!(err.index, Some(1));
+
+        let flags = decode_args::<TrailingFlags>(&["--color".to_string()]);
+        assert_eq!(flags, Ok(TrailingFlags { count: None, color: Some("".to_string()) }));
+
+        let err = decode_args::<TrailingFlags>(&["--count".to_string()]).unwrap_err();
+        assert_eq!(err.message, "--count: could not convert  to an integer".to_string());
+        assert_eq!(err.index, Some(0));
+    }
+
+    #[test]
+    fn test_repeated_flags_beside_rest() {
+        let sync = |args: &[&str]| decode_strs::<SyncFlags>(args).unwrap();
+
+        let flags = sync(&["src", "--exclude", "b", "docs", "--exclude=a"]);
+        assert_eq!(flags.exclude, vec!("b".to_string(), "a".to_string()));
+        assert_eq!(flags.rest, vec!("src".to_string(), "docs".to_string()));
+
+        assert_eq!(sync(&["src"]), SyncFlags { exclude: vec!(), rest: vec!("src".to_string()) });
+    }
+
+    #[test]
+    fn test_values_from_file() {
+        let path = os::tmpdir().join("hammer-test-exclude.txt");
+        File::create(&path).write_str("# build output\ntarget\n\n*.o\n").unwrap();
+
+        let path = path.as_str().unwrap().to_string();
+        let args = owned(&["--exclude", "a", "--exclude-from", path.as_slice(), "--exclude", "b", "src"]);
+
+        let flags = decode_args::<SyncFlags>(args.as_slice()).unwrap();
+        assert_eq!(flags.exclude, vec!("a".to_string(), "b".to_string(), "target".to_string(), "*.o".to_string()));
+        assert_eq!(flags.rest, vec!("src".to_string()));
+
+        let args = vec!("--exclude-from".to_string(), "/nonexistent/patterns.txt".to_string());
+        let err = decode_args::<SyncFlags>(args.as_slice()).unwrap_err();
+        assert!(err.message.as_slice().starts_with("--exclude-from: could not read /nonexistent/patterns.txt"));
+        assert_eq!(err.index, Some(1));
+
+        assert_eq!(usage::<SyncFlags>(false), (None,
+            "[--exclude <exclude>]\n[--exclude-from <exclude-from>]\n    note: reads --exclude values from a file, one per line\n".to_string()));
+    }
+
+    #[test]
+    fn test_argfiles() {
+        let inner = os::tmpdir().join("hammer-test-inner.args");
+        let outer = os::tmpdir().join("hammer-test-outer.args");
+        let looped = os::tmpdir().join("hammer-test-looped.args");
+
+        let inner_arg = format!("@{}", inner.as_str().unwrap());
+        let outer_arg = format!("@{}", outer.as_str().unwrap());
+        let looped_arg = format!("@{}", looped.as_str().unwrap());
+
+        File::create(&inner).write_str("-v\n# not a comment\n  spaced \n@@literal\n").unwrap();
+        File::create(&outer).write_str(format!("\ufeff--count\r\n3\r\n{}\r\n", inner_arg).as_slice()).unwrap();
+        File::create(&looped).write_str(looped_arg.as_slice()).unwrap();
+
+        assert_eq!(decode_strs::<ArgfileFlags>(&["a", outer_arg.as_slice(), "@@b", "--", inner_arg.as_slice()]), Ok(ArgfileFlags {
+            count: 3,
+            verbose: true,
+            rest: vec!("a".to_string(), "# not a comment".to_string(), "  spaced ".to_string(), "@literal".to_string(), "@b".to_string(), inner_arg.clone())
+        }));
+
+        // the plan hands back the arguments it decoded, for errors to point into
+        let plan = ParsePlan::<ArgfileFlags>::new().unwrap();
+        let (expanded, _) = plan.decode_all(owned(&[outer_arg.as_slice(), "x"]).as_slice());
+        assert_eq!(expanded, owned(&["--count", "3", "-v", "# not a comment", "  spaced ", "@literal", "x"]));
+
+        let err = decode_strs::<ArgfileFlags>(&["--count", "1", "@/nonexistent/flags.args"]).unwrap_err();
+        assert!(err.message.as_slice().starts_with("could not read argument file /nonexistent/flags.args"));
+        assert_eq!(err.index, Some(2));
+
+        let err = decode_strs::<ArgfileFlags>(&[looped_arg.as_slice()]).unwrap_err();
+        assert_eq!(err.message, format!("argument files are nested more than 8 deep at {}", looped.as_str().unwrap()));
+
+        // without allow_argfiles, @file is an ordinary argument
+        let args = vec!(outer_arg.clone());
+        assert_eq!(decode_args::<DigitFlags>(args.as_slice()).unwrap().rest, args);
+    }
+
+    #[test]
+    fn test_merge_configs() {
+        let core = FlagConfiguration::new().short("verbose", 'v').desc("Builds things");
+        let feature = FlagConfiguration::new().short("jobs", 'j').default("jobs", "4").desc("Runs jobs");
+
+        let merged = core.clone().merge(feature).unwrap();
+        assert_eq!(merged.short_for("verbose"), Some('v'));
+        assert_eq!(merged.short_for("jobs"), Some('j'));
+        assert_eq!(merged.description(), Some("Builds things".to_string()));
+
+        let args = vec!("-v".to_string());
+        let mut decoder = FlagDecoder::configured::<MergedFlags>(args.as_slice(), merged);
+        let flags: MergedFlags = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(flags, MergedFlags { verbose: true, jobs: 4 });
+
+        let clashing = FlagConfiguration::new().short("verbose", 'x');
+        assert_eq!(core.clone().merge(clashing).unwrap_err(), "short is configured twice for verbose: -v and -x".to_string());
+
+        // settings of the whole program clash too, unless one was left alone
+        let args = FlagConfiguration::new().rest_field("args");
+        assert_eq!(args.clone().merge(FlagConfiguration::new().rest_field("files")).unwrap_err(),
+            "rest_field is configured twice: args and files".to_string());
+        assert_eq!(FlagConfiguration::new().merge(args).unwrap().rest_field_name(), "args".to_string());
+        assert_eq!(core.syntax(Syntax { clustering: false, ..Syntax::standard() }).merge(FlagConfiguration::new().sort_usage(ShortFirst))
+            .unwrap().accepted_syntax().clustering, false);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let args = owned(&["--tag", "b", "--level", "1", "--tag", "a", "--tag", "b", "--level", "1", "--tag", "a"]);
+
+        let flags: TagFlags = decode_args(args.as_slice()).unwrap();
+
+        assert_eq!(flags, TagFlags { tag: vec!("b".to_string(), "a".to_string()), level: vec!(1, 1) });
+    }
+
+    #[test]
+    fn test_typed_lists() {
+        let args = vec!("a".to_string(), "--weights".to_string(), "1.5".to_string(), "b".to_string(), "--weights=2".to_string());
+        let samples: Samples = decode_args(args.as_slice()).unwrap();
+
+        assert_eq!(samples, Samples { weights: vec!(1.5, 2.0), rest: vec!('a', 'b') });
+
+        let samples: Samples = decode_args(&[]).unwrap();
+        assert_eq!(samples, Samples { weights: vec!(), rest: vec!() });
+    }
+
+    #[test]
+    fn test_typed_list_errors() {
+        let args = vec!("--weights".to_string(), "1".to_string(), "--weights".to_string(), "heavy".to_string());
+        let err = decode_args::<Samples>(args.as_slice()).unwrap_err();
+
+        assert_eq!(err.message, "--weights: could not convert heavy to a float".to_string());
+        assert_eq!(err.index, Some(3));
+
+        let args = vec!("a".to_string(), "bc".to_string());
+        let err = decode_args::<Samples>(args.as_slice()).unwrap_err();
+
+        assert_eq!(err.message, "bc is not a single character".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_conversion_errors_name_the_flag() {
+        let error = |args: &[&str]| decode_strs::<ConvertedFlags>(args).unwrap_err();
+
+        assert_eq!(error(&["--count", "abc"]).message, "--count: could not convert abc to an integer".to_string());
+        assert_eq!(error(&["-c", "-2"]).message, "--count: could not convert -2 to an integer".to_string());
+        assert_eq!(error(&["--offset=1.5"]).message, "--offset: could not convert 1.5 to an integer".to_string());
+        assert_eq!(error(&["--ratio", "half"]).message, "--ratio: could not convert half to a float".to_string());
+        assert_eq!(error(&["--separator", "::"]).message, "--separator: :: is not a single character".to_string());
+
+        let err = error(&["--ratio", "2", "--offset", "x"]);
+        assert_eq!((err.message, err.index), ("--offset: could not convert x to an integer".to_string(), Some(3)));
+    }
+
+    fn early_exit_of(args: &[&str]) -> HammerError {
+        decode_strs::<ProjectFlags>(args).unwrap_err()
+    }
+
+    #[test]
+    fn test_early_exits_beat_required_flags() {
+        let help = HammerError::early(HelpRequested, "Manages projects\n\n    --project <project>\n-l, [--list-formats]\n".to_string());
+
+        assert_eq!(early_exit_of(&["build", "--help"]), help);
+        assert_eq!(early_exit_of(&["-h"]), help);
+        assert_eq!(early_exit_of(&["help", "build"]), help);
+        assert_eq!(early_exit_of(&["--project", "--bogus", "-h"]), help);
+        assert_eq!(early_exit_of(&["--version"]), HammerError::early(VersionRequested, "1.0".to_string()));
+        assert_eq!(early_exit_of(&["--list-formats"]), HammerError::early(ExitedEarly, "list_formats".to_string()));
+        assert_eq!(early_exit_of(&["-l", "build"]), HammerError::early(ExitedEarly, "list_formats".to_string()));
+
+        assert_eq!(early_exit_of(&["build", "help"]).kind, InvalidFlags);
+        assert_eq!(early_exit_of(&["build"]).message, "--project is required".to_string());
+
+        // nor does a flag that would be rejected
+        let args = vec!("--bogus".to_string(), "--help".to_string());
+        let err = decode_args::<CheckedFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.kind, HelpRequested);
+    }
+
+    #[test]
+    fn test_should_page() {
+        let less = || Some("less -R".to_string());
+
+        assert_eq!(should_page(100, true, less()), Some("less -R".to_string()));
+        assert_eq!(should_page(100, false, less()), None);
+        assert_eq!(should_page(24, true, less()), None);
+        assert_eq!(should_page(25, true, None), None);
+        assert_eq!(should_page(25, true, Some("  ".to_string())), None);
+    }
+
+    #[test]
+    fn test_version() {
+        let config = FlagConfig::config(None::<ProjectFlags>, FlagConfiguration::new());
+        assert_eq!(config.program_version(), Some("1.0".to_string()));
+        assert_eq!(early_exit_of(&["build", "--version"]), HammerError::early(VersionRequested, "1.0".to_string()));
+
+        // without a version, --version is just another flag
+        let config = FlagConfig::config(None::<CheckedFlags>, FlagConfiguration::new());
+        assert_eq!(config.program_version(), None);
+
+        let args = vec!("--version".to_string());
+        assert_eq!(decode_args::<CheckedFlags>(args.as_slice()).unwrap_err().message, "unknown flag: --version".to_string());
+    }
+
+    #[test]
+    fn test_env_lookup() {
+        let flags = decode_args::<LookupEnvFlags>(&[]);
+        assert_eq!(flags, Ok(LookupEnvFlags { color: true, quiet: false, port: 8080 }));
+
+        let args = owned(&["--port", "443", "--no-color"]);
+        assert_eq!(decode_args::<LookupEnvFlags>(args.as_slice()), Ok(LookupEnvFlags { color: false, quiet: false, port: 443 }));
+
+        let err = decode_args::<BrokenEnvFlags>(&[]).unwrap_err();
+        assert_eq!(err.message, "--port: could not convert http to an integer (from env MYAPP_PORT)".to_string());
+        assert_eq!(err.index, None);
+
+        let args = vec!("--port".to_string(), "80".to_string());
+        assert_eq!(decode_args::<BrokenEnvFlags>(args.as_slice()), Ok(BrokenEnvFlags { color: false, port: 80 }));
+    }
+
+    #[test]
+    fn test_sources_consulted_once() {
+        let args = vec!("--zone".to_string(), "b".to_string());
+        let mut decoder = FlagDecoder::new::<LazyFlags>(args.as_slice());
+        let flags: LazyFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, LazyFlags { region: Some("us".to_string()), zone: Some("b".to_string()), count: 1, verbose: false });
+        assert_eq!(decoder.trace(), vec!(
+            "--region: cli".to_string(),
+            "--region: env".to_string(),
+            "--region: default".to_string(),
+            "--zone: cli".to_string(),
+            "--count: cli".to_string(),
+            "--count: default".to_string(),
+            "--verbose: cli".to_string()
+        ));
+
+        let args = vec!("--count".to_string(), "1".to_string(), "-c".to_string());
+        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
+        let _: CompileFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(decoder.trace(), vec!(
+            "--color: cli".to_string(),
+            "--count: cli".to_string(),
+            "--maybe: cli".to_string(),
+            "--some-some: cli".to_string()
+        ));
+    }
+
+    #[test]
+    fn test_default_true_switch() {
+        let switches = |args: &[&str]| decode_strs::<SwitchFlags>(args);
+
+        assert_eq!(switches(&[]), Ok(SwitchFlags { color: true, verbose: false }));
+        assert_eq!(switches(&["--color"]), Ok(SwitchFlags { color: true, verbose: false }));
+        assert_eq!(switches(&["--color=false"]), Ok(SwitchFlags { color: false, verbose: false }));
+        assert_eq!(switches(&["--verbose=true", "--color=true"]), Ok(SwitchFlags { color: true, verbose: true }));
+        assert_eq!(switches(&["--verbose=false"]), Ok(SwitchFlags { color: true, verbose: false }));
+        assert_eq!(switches(&["--verbose=Yes", "--color=off"]), Ok(SwitchFlags { color: false, verbose: true }));
+        assert_eq!(switches(&["--verbose=1", "--color=NO"]), Ok(SwitchFlags { color: false, verbose: true }));
+        assert_eq!(switches(&["--verbose=ON", "--color=0"]), Ok(SwitchFlags { color: false, verbose: true }));
+
+        let err = switches(&["--verbose", "--color=maybe"]).unwrap_err();
+        assert_eq!(err.message, "--color: could not convert maybe to a bool; use true/false, yes/no, on/off, 1/0".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_help_tiers() {
+        let help = |args: &[&str]| decode_strs::<TieredFlags>(args).unwrap_err().message;
+
+        assert_eq!(help(&["-h"]), "[--verbose]\n".to_string());
+        assert_eq!(help(&["--help"]), "[--verbose]\n[--jobs <INT>]\n".to_string());
+        assert_eq!(help(&["--help", "--verbose"]), "[--verbose]\n[--jobs <INT>]\n[--debug-gc]\n".to_string());
+    }
+
+    #[test]
+    fn test_negative_numbers() {
+        let offset = |args: &[&str]| decode_strs::<OffsetFlags>(args).unwrap();
+
+        let flags = offset(&["--offset", "-5", "--scale", "-0.5", "-v"]);
+        assert_eq!(flags, OffsetFlags { offset: -5, scale: -0.5, verbose: true, rest: vec!() });
+
+        let flags = offset(&["-v", "--scale=-2.25", "--offset=-10"]);
+        assert_eq!(flags, OffsetFlags { offset: -10, scale: -2.25, verbose: true, rest: vec!() });
+
+        let flags = offset(&["-5", "-o", "-3", "-1.5"]);
+        assert_eq!(flags, OffsetFlags { offset: -3, scale: 1.0, verbose: false, rest: vec!("-5".to_string(), "-1.5".to_string()) });
+
+        let args = owned(&["-o", "1", "-2", "-x"]);
+        let mut decoder = FlagDecoder::new::<OffsetFlags>(args.as_slice());
+        let _: OffsetFlags = Decodable::decode(&mut decoder).unwrap();
+        let classified = decoder.classify();
+        assert_eq!(classified.positionals, vec!("-2".to_string()));
+        assert_eq!(classified.unknown_flags, vec!("-x".to_string()));
+
+        // unless a short alias is a digit
+        let args = vec!("-1".to_string(), "-2".to_string());
+        assert_eq!(decode_args::<DigitFlags>(args.as_slice()), Ok(DigitFlags { single_column: true, rest: vec!("-2".to_string()) }));
+    }
+
+    #[test]
+    fn test_flags_among_positionals() {
+        let decode = |args: &[&str]| {
+            let args = owned(args);
+            let mut decoder = FlagDecoder::new::<OffsetFlags>(args.as_slice());
+            let flags: OffsetFlags = Decodable::decode(&mut decoder).unwrap();
+            (flags, decoder.classify().positionals)
+        };
+
+        let (flags, positionals) = decode(&["file1", "file2", "--verbose"]);
+        assert!(flags.verbose);
+        assert_eq!(flags.rest, owned(&["file1", "file2"]));
+        assert_eq!(positionals, owned(&["file1", "file2"]));
+
+        let (flags, _) = decode(&["file1", "--offset", "3", "file2", "-v", "file3"]);
+        assert_eq!(flags, OffsetFlags { offset: 3, scale: 1.0, verbose: true, rest: owned(&["file1", "file2", "file3"]) });
+
+        // positionals spelled like the value are left alone
+        let (flags, positionals) = decode(&["3", "--offset", "3", "3", "--scale", "3", "3"]);
+        assert_eq!(flags, OffsetFlags { offset: 3, scale: 3.0, verbose: false, rest: owned(&["3", "3", "3"]) });
+        assert_eq!(positionals, owned(&["3", "3", "3"]));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let args = owned(&["--COUNT", "3", "--Name=MixedCase", "-V", "--No-Color", "--VERBOSE"]);
+
+        assert_eq!(decode_args::<CaseFlags>(args.as_slice()), Ok(CaseFlags {
+            count: 3,
+            name: Some("MixedCase".to_string()),
+            color: false,
+            verbose: true,
+            rest: vec!("-V".to_string())
+        }));
+
+        // abbreviations and the flags looked for before decoding too
+        assert_eq!(decode_strs::<CaseFlags>(&["--COU", "3"]).unwrap().count, 3);
+        assert_eq!(decode_strs::<CaseFlags>(&["--HELP"]).unwrap_err().kind, HelpRequested);
+        assert_eq!(decode_strs::<CaseFlags>(&["--count", "3", "-H"]).unwrap().rest, vec!("-H".to_string()));
+
+        // without it, case matters
+        let args = vec!("--OFFSET".to_string(), "3".to_string());
+        assert_eq!(decode_args::<OffsetFlags>(args.as_slice()).unwrap_err().message, "--offset is required".to_string());
+    }
+
+    #[test]
+    fn test_case_insensitive_is_opt_in() {
+        let args = owned(&["--Color", "red"]);
+
+        let decode_with = |config: FlagConfiguration| {
+            let config = FlagConfig::config(None::<CheckedFlags>, config);
+            let mut decoder = FlagDecoder::configured::<CheckedFlags>(args.as_slice(), config);
+            let flags: HammerResult<CheckedFlags> = Decodable::decode(&mut decoder);
+            flags
+        };
+
+        assert_eq!(decode_with(FlagConfiguration::new().case_insensitive()).unwrap().color, Some("red".to_string()));
+        assert_eq!(decode_with(FlagConfiguration::new()).unwrap_err().message, "unknown flag: --Color".to_string());
+    }
+
+    #[test]
+    fn test_dash_is_a_value() {
+        let checked = |args: &[&str]| decode_strs::<CheckedFlags>(args).unwrap();
+
+        let flags = checked(&["--color", "-", "extra.txt", "-v"]);
+        assert_eq!(flags.color, Some("-".to_string()));
+        assert_eq!(flags.rest, vec!("extra.txt".to_string()));
+
+        let flags = checked(&["-v", "-", "--color=-"]);
+        assert_eq!(flags.color, Some("-".to_string()));
+        assert_eq!(flags.rest, vec!("-".to_string()));
+
+        let flags = checked(&["-", "-v"]);
+        assert_eq!(flags.color, None);
+        assert!(flags.verbose);
+        assert_eq!(flags.rest, vec!("-".to_string()));
+    }
+
+    #[test]
+    fn test_repeated_integer_flags() {
+        let ports = |args: &[&str]| decode_strs::<PortFlags>(args);
+
+        assert_eq!(ports(&["--ports", "80", "--ports=443", "--ports", "8080"]), Ok(PortFlags { ports: vec!(80, 443, 8080) }));
+        assert_eq!(ports(&[]), Ok(PortFlags { ports: vec!() }));
+
+        let err = ports(&["--ports", "80", "--ports", "-1"]).unwrap_err();
+        assert_eq!(err.message, "--ports: could not convert -1 to an integer".to_string());
+        assert_eq!(err.index, Some(3));
+    }
+
+    #[test]
+    fn test_lenient_lists() {
+        let args = owned(&["--ports", "80", "--ports", "http", "--ports", "443", "--ports", "-1"]);
+
+        let strict: HammerResult<PortFlags> = decode_args(args.as_slice());
+        assert_eq!(strict.unwrap_err().message, "--ports: could not convert http to an integer".to_string());
+
+        let mut decoder = FlagDecoder::new::<LenientPortFlags>(args.as_slice());
+        let lenient: LenientPortFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(lenient, LenientPortFlags { ports: vec!(80, 443) });
+        assert_eq!(warning_messages(&decoder, SkippedListElement), vec!(
+            "--ports: skipped http (could not convert http to an integer)".to_string(),
+            "--ports: skipped -1 (could not convert -1 to an integer)".to_string()
+        ));
+        assert!(decoder.warnings().iter().all(|w| w.field == Some("ports".to_string())));
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let args = ["--output-dir", "${HAMMER_TEST_PROJECT}/build/$$1", "--raw-dir", "$HAMMER_TEST_PROJECT"];
+        let flags: OutputFlags = decode_with_env(args.as_slice(), test_env).unwrap();
+
+        assert_eq!(flags, OutputFlags {
+            output_dir: "hammer/build/$1".to_string(),
+            raw_dir: Some("$HAMMER_TEST_PROJECT".to_string())
+        });
+
+        let args = vec!("--output-dir".to_string(), "$HAMMER_TEST_UNSET/out".to_string());
+
+        let err = decode_args::<OutputFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--output-dir: $HAMMER_TEST_UNSET is not set".to_string());
+
+        let flags: LenientOutputFlags = decode_args(args.as_slice()).unwrap();
+        assert_eq!(flags.output_dir, "$HAMMER_TEST_UNSET/out".to_string());
+    }
+
+    #[test]
+    fn test_duplicate_policies() {
+        let args = owned(&["-v", "--include", "a", "--jobs", "1", "--verbose", "--output", "x", "--include", "b", "-v", "--jobs", "4"]);
+
+        let mut decoder = FlagDecoder::new::<BuildFlags>(args.as_slice());
+        let flags: BuildFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, BuildFlags {
+            verbose: true,
+            output: Some("x".to_string()),
+            include: vec!("a".to_string(), "b".to_string()),
+            jobs: 4
+        });
+        assert_eq!(decoder.remaining(), vec!());
+
+        let args = owned(&["--output", "x", "--jobs", "1", "--output", "y"]);
+
+        let err = decode_args::<BuildFlags>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--output was given more than once".to_string());
+        assert_eq!(err.index, Some(4));
+    }
+
+    #[test]
+    fn test_appended_flag_overrides() {
+        // a wrapper script appends `--jobs 8` to the user's command line
+        let args = owned(&["build", "--jobs", "2", "target", "--jobs", "8"]);
+
+        let mut decoder = FlagDecoder::new::<WrappedFlags>(args.as_slice());
+        let flags: WrappedFlags = Decodable::decode(&mut decoder).unwrap();
+
+        assert_eq!(flags, WrappedFlags { jobs: 8, rest: vec!("build".to_string(), "target".to_string()) });
+        assert_eq!(decoder.remaining(), vec!("build".to_string(), "target".to_string()));
+        assert!(decoder.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_policy_for_unknown_field() {
+        let err = decode_args::<MisconfiguredFlags>(&[]).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "duplicates_for refers to unknown field ouptut".to_string());
+    }
+
+    #[test]
+    fn test_every_unknown_field_reported() {
+        let err = decode_args::<RenamedFlags>(&[]).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "env refers to unknown field tls_key; \
+            required refers to unknown field cert_file; \
+            short refers to unknown field verbos".to_string());
+
+        assert!(decode_args::<ServeFlags>(&["--port".to_string(), "80".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_flag_collisions() {
+        let err = decode_args::<TwinFlags>(&[]).unwrap_err();
+        assert_eq!(err, HammerError::early(ConfigError, "--verbose is the flag of both verbose and common.verbose".to_string()));
+
+        let err = decode_args::<DoubledFlags>(&[]).unwrap_err();
+        assert_eq!(err, HammerError::early(ConfigError, "--dry-run is the flag of both dry_run and dry__run".to_string()));
+
+        let err = decode_args::<RenamedTwinFlags>(&[]).unwrap_err();
+        assert_eq!(err, HammerError::early(ConfigError, "--output is the flag of both output and out_file".to_string()));
+    }
+
+    #[test]
+    fn test_flatten_field() {
+        let args = owned(&["-q", "--port", "80", "x", "-v"]);
+        let flags: ServeFlags = decode_args(args.as_slice()).unwrap();
+
+        assert_eq!(flags, ServeFlags {
+            port: 80,
+            common: CommonFlags {
+                verbose: true,
+                log: LogFlags { log_level: Some("info".to_string()), quiet: true }
+            },
+            rest: vec!("x".to_string())
+        });
+
+        let args = owned(&["--port", "80", "--log-level", "debug"]);
+        let flags: ServeFlags = decode_args(args.as_slice()).unwrap();
+        assert_eq!(flags.common.log.log_level, Some("debug".to_string()));
+
+        let names: Vec<(String, Option<char>)> = describe::<ServeFlags>().move_iter().map(|s| (s.flag, s.short)).collect();
+        assert_eq!(names, vec!(
+            ("--port".to_string(), None),
+            ("--verbose".to_string(), Some('v')),
+            ("--log-level".to_string(), None),
+            ("--quiet".to_string(), Some('q'))
+        ));
+    }
+
+    #[test]
+    fn test_nested_without_flatten() {
+        let args = owned(&["--port", "80"]);
+        let err = decode_args::<NestedFlags>(args.as_slice()).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "--common is a struct; declare it with flatten_field".to_string());
+    }
+}
+
+/*
+    A struct of nothing but switches never reads a value, so the decoder
+    takes a different path through it than through the mixed structs above.
+*/
+#[cfg(test)]
+mod switch_tests {
+    use super::{HammerResult, HammerWarning, Decoded, DuplicateIgnored, ParsePlan};
+    use super::harness::{decode, owned};
+    use config::Error;
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct Toggles {
+        cache: bool,
+        color: bool,
+        verbose: bool
+    }
+
+    hammer_config!(Toggles |c| { c.short("color", 'c').short("verbose", 'v').negated("cache") })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct StrictToggles {
+        color: bool,
+        verbose: bool
+    }
+
+    hammer_config!(StrictToggles |c| { c.short("color", 'c').short("verbose", 'v').duplicates(Error) })
+
+    fn toggles(args: &[&str]) -> HammerResult<Decoded<Toggles>> {
+        decode::<Toggles>(owned(args).as_slice())
+    }
+
+    #[test]
+    fn test_nothing_given() {
+        let decoded = toggles(&[]).unwrap();
+
+        assert_eq!(decoded.into_inner(), Toggles { cache: true, color: false, verbose: false });
+    }
+
+    #[test]
+    fn test_clusters() {
+        assert_eq!(toggles(&["-cv"]).unwrap().into_inner(), Toggles { cache: true, color: true, verbose: true });
+        assert!(toggles(&["-vc"]).unwrap().remaining().is_empty());
+
+        let err = toggles(&["-vx"]).unwrap_err();
+        assert_eq!(err.message, "unknown flag -x in -vx".to_string());
+        assert_eq!(err.index, Some(0));
+
+        // a short letter of its own that isn't a flag is left over
+        assert_eq!(toggles(&["-x", "-v"]).unwrap().remaining(), owned(&["-x"]).as_slice());
+    }
+
+    #[test]
+    fn test_negation() {
+        let decoded = toggles(&["--no-cache", "--no-color", "-v"]).unwrap();
+        assert_eq!(decoded.into_inner(), Toggles { cache: false, color: false, verbose: true });
+
+        assert_eq!(toggles(&["-c", "--no-color"]).unwrap().color, false);
+        assert_eq!(toggles(&["--no-color", "-c"]).unwrap().color, true);
+
+        let err = toggles(&["--no-cache", "--cache"]).unwrap_err();
+        assert_eq!(err.message, "--cache and --no-cache can't both be given".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let decoded = toggles(&["--verbose", "--verbose"]).unwrap();
+        assert!(decoded.verbose);
+        assert_eq!(decoded.remaining(), owned(&["--verbose"]).as_slice());
+        assert_eq!(decoded.warnings(), vec!(HammerWarning {
+            kind: DuplicateIgnored,
+            message: "--verbose was given more than once; only the first was used".to_string(),
+            field: Some("verbose".to_string())
+        }).as_slice());
+
+        let args = owned(&["-v", "-cv"]);
+        let err = decode::<StrictToggles>(args.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--verbose was given more than once".to_string());
+        assert_eq!(err.index, Some(1));
+    }
+
+    #[test]
+    fn test_end_of_options() {
+        let decoded = toggles(&["-v", "--", "-c", "--no-cache"]).unwrap();
+
+        assert_eq!(decoded.value(), &Toggles { cache: true, color: false, verbose: true });
+        assert_eq!(decoded.remaining(), owned(&["-c", "--no-cache"]).as_slice());
+    }
+
+    #[test]
+    fn test_one_plan() {
+        let plan = ParsePlan::<Toggles>::new().unwrap();
+
+        let corpus = vec!(vec!("-cv"), vec!("--no-cache", "-c"), vec!("-vx", "--color"), vec!("-v", "--", "-c"));
+
+        for args in corpus.iter() {
+            let expected = toggles(args.as_slice()).and_then(|decoded| decoded.confirmed());
+            assert_eq!(plan.decode_args(owned(args.as_slice()).as_slice()), expected);
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::ascii::StrAsciiExt;
+use std::collections::hashmap::HashMap;
+use serialize::{Encoder, Encodable};
+
+use config::{FlagConfig, FlagConfiguration};
+use decode::{HammerError, ConfigError, checked_config};
+use text::shell_quote;
+
+/// The value of a single field, as it would be given on the command line
+#[deriving(PartialEq, Clone, Show)]
+pub enum Encoded {
+    EncodedSwitch(bool),
+    EncodedValue(String),
+    EncodedList(Vec<String>),
+    EncodedNone
+}
+
+/// Turns an option structure back into the values of its flags
+pub struct FlagEncoder {
+    current_field: Option<String>,
+    current: Option<Encoded>,
+    fields: Vec<(String, Encoded)>
+}
+
+impl FlagEncoder {
+    pub fn new() -> FlagEncoder {
+        FlagEncoder { current_field: None, current: None, fields: vec!() }
+    }
+
+    /// The encoded fields, in declaration order
+    pub fn fields(&self) -> Vec<(String, Encoded)> {
+        self.fields.clone()
+    }
+
+    pub fn field_map(&self) -> HashMap<String, Encoded> {
+        self.fields.iter().map(|&(ref name, ref value)| (name.clone(), value.clone())).collect()
+    }
+
+    fn value(&mut self, value: String) -> EncodeResult {
+        match self.current {
+            Some(EncodedList(ref mut values)) => { values.push(value); return Ok(()) },
+            _ => ()
+        }
+
+        self.current = Some(EncodedValue(value));
+        Ok(())
+    }
+
+    // only enums of plain variants can be flags, the same as when decoding
+    fn enum_with_fields(&self) -> EncodeResult {
+        let field = self.current_field.as_ref().map(|f| f.as_slice()).unwrap_or("a field");
+        let message = format!("{} is an enum with fields; only enums of plain variants can be flags", field);
+        Err(HammerError::early(ConfigError, message))
+    }
+}
+
+type EncodeResult = Result<(), HammerError>;
+
+impl Encoder<HammerError> for FlagEncoder {
+    fn emit_nil(&mut self) -> EncodeResult { unimplemented!() }
+
+    fn emit_uint(&mut self, v: uint) -> EncodeResult { self.value(v.to_string()) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult     { self.value(v.to_string()) }
+    fn emit_int(&mut self, v: int) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult   { self.value(v.to_string()) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult     { self.value(v.to_string()) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult {
+        self.current = Some(EncodedSwitch(v));
+        Ok(())
+    }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult { self.value(v.to_string()) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult { self.value(v.to_string()) }
+    fn emit_char(&mut self, v: char) -> EncodeResult { self.value(v.to_string()) }
+    fn emit_str(&mut self, v: &str) -> EncodeResult { self.value(v.to_string()) }
+
+    #[allow(unused_variable)]
+    fn emit_struct(&mut self, name: &str, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn emit_struct_field(&mut self, f_name: &str, f_idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        self.current_field = Some(f_name.to_string());
+        self.current = None;
+
+        try!(f(self));
+
+        let value = self.current.take().unwrap_or(EncodedNone);
+        self.fields.push((f_name.to_string(), value));
+        Ok(())
+    }
+
+    fn emit_option(&mut self, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        f(self)
+    }
+
+    fn emit_option_none(&mut self) -> EncodeResult {
+        self.current = Some(EncodedNone);
+        Ok(())
+    }
+
+    fn emit_option_some(&mut self, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn emit_seq(&mut self, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        self.current = Some(EncodedList(vec!()));
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn emit_seq_elt(&mut self, idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn emit_enum(&mut self, name: &str, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        f(self)
+    }
+
+    // a plain variant is given by its name, e.g. `--level warn` for `Warn`
+    #[allow(unused_variable)]
+    fn emit_enum_variant(&mut self, v_name: &str, v_id: uint, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        if len > 0 { return self.enum_with_fields() }
+        self.value(v_name.to_ascii_lower())
+    }
+
+    #[allow(unused_variable)]
+    fn emit_enum_variant_arg(&mut self, a_idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        self.enum_with_fields()
+    }
+
+    #[allow(unused_variable)]
+    fn emit_enum_struct_variant(&mut self, v_name: &str, v_id: uint, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        self.enum_with_fields()
+    }
+
+    #[allow(unused_variable)]
+    fn emit_enum_struct_variant_field(&mut self, f_name: &str, f_idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        self.enum_with_fields()
+    }
+
+    // the rest of these are pretty weird or hard to implement.
+
+    #[allow(unused_variable)]
+    fn emit_tuple(&mut self, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult { unimplemented!() }
+    #[allow(unused_variable)]
+    fn emit_tuple_arg(&mut self, idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult { unimplemented!() }
+    #[allow(unused_variable)]
+    fn emit_tuple_struct(&mut self, name: &str, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult { unimplemented!() }
+    #[allow(unused_variable)]
+    fn emit_tuple_struct_arg(&mut self, f_idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult { unimplemented!() }
+
+    // a `leading_assignments` map is a list of `NAME=VALUE` arguments
+    #[allow(unused_variable)]
+    fn emit_map(&mut self, len: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        self.current = Some(EncodedList(vec!()));
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn emit_map_elt_key(&mut self, idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        f(self)
+    }
+
+    #[allow(unused_variable)]
+    fn emit_map_elt_val(&mut self, idx: uint, f: |&mut FlagEncoder| -> EncodeResult) -> EncodeResult {
+        try!(f(self));
+
+        match self.current {
+            Some(EncodedList(ref mut values)) if values.len() >= 2 => {
+                let value = values.pop().unwrap();
+                let name = values.pop().unwrap();
+                values.push(format!("{}={}", name, value));
+            },
+            _ => ()
+        }
+
+        Ok(())
+    }
+}
+
+/** Reconstruct a shell-safe command line that decodes into `flags`
+
+Flags use their long form, values are quoted when the shell needs them to
+be, and `false` and `None` fields are left out. The `leading_assignments`
+come first and the rest field comes last.
+
+```
+let line = quoted_command("mytool", &opts);
+// mytool --count 5 --color 'a b' file.txt
+```
+*/
+
+pub fn quoted_command<T: FlagConfig + Encodable<FlagEncoder, HammerError>>(program: &str, flags: &T) -> String {
+    let config = checked_config::<T>().unwrap_or(FlagConfiguration::new());
+    let mut encoder = FlagEncoder::new();
+    let _ = flags.encode(&mut encoder);
+
+    let mut words = vec!(shell_quote(program));
+    let mut flags = vec!();
+    let mut rest = vec!();
+
+    for &(ref name, ref value) in encoder.fields.iter() {
+        let flag = config.display_name(name.as_slice());
+
+        match *value {
+            EncodedSwitch(true) => flags.push(flag),
+            EncodedSwitch(false) | EncodedNone => (),
+            EncodedValue(ref value) => {
+                flags.push(flag);
+                flags.push(shell_quote(value.as_slice()));
+            },
+            EncodedList(ref values) if *name == config.rest_field_name() => {
+                rest.extend(values.iter().map(|v| shell_quote(v.as_slice())));
+            },
+            EncodedList(ref values) if config.assignments_field() == Some(name.clone()) => {
+                words.extend(values.iter().map(|v| shell_quote(v.as_slice())));
+            },
+            EncodedList(ref values) => {
+                for value in values.iter() {
+                    flags.push(flag.clone());
+                    flags.push(shell_quote(value.as_slice()));
+                }
+            }
+        }
+    }
+
+    words.push_all_move(flags);
+    words.push_all_move(rest);
+    words.connect(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hashmap::HashMap;
+    use super::{FlagEncoder, EncodedSwitch, EncodedValue, EncodedList, EncodedNone, quoted_command};
+    use decode::ConfigError;
+    use serialize::Encodable;
+
+    #[deriving(Encodable)]
+    struct MixedOptions {
+        color: Option<String>,
+        line_count: uint,
+        temp: f64,
+        verbose: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(MixedOptions)
+
+    #[deriving(Encodable)]
+    struct CommandOptions {
+        name: String,
+        count: uint,
+        color: Option<String>,
+        quiet: bool,
+        include: Vec<String>,
+        files: Vec<String>
+    }
+
+    hammer_config!(CommandOptions |c| { c.rest_field("files") })
+
+    #[deriving(Encodable)]
+    enum Level {
+        Debug,
+        Warn
+    }
+
+    #[deriving(Encodable)]
+    struct LaunchOptions {
+        env: HashMap<String, String>,
+        level: Level,
+        rest: Vec<String>
+    }
+
+    hammer_config!(LaunchOptions |c| { c.leading_assignments("env") })
+
+    #[deriving(Encodable)]
+    enum Target {
+        Local,
+        Remote(String)
+    }
+
+    #[deriving(Encodable)]
+    struct DeployOptions {
+        target: Target
+    }
+
+    hammer_config!(DeployOptions)
+
+    #[test]
+    fn test_encode() {
+        let opts = MixedOptions { color: None, line_count: 3, temp: 1.5, verbose: true, rest: vec!("a".to_string()) };
+
+        let mut encoder = FlagEncoder::new();
+        opts.encode(&mut encoder).unwrap();
+
+        assert_eq!(encoder.fields(), vec!(
+            ("color".to_string(), EncodedNone),
+            ("line_count".to_string(), EncodedValue("3".to_string())),
+            ("temp".to_string(), EncodedValue("1.5".to_string())),
+            ("verbose".to_string(), EncodedSwitch(true)),
+            ("rest".to_string(), EncodedList(vec!("a".to_string())))
+        ));
+    }
+
+    #[test]
+    fn test_quoted_command() {
+        let opts = MixedOptions { color: Some("a b".to_string()), line_count: 5, temp: 0.5, verbose: true, rest: vec!() };
+        assert_eq!(quoted_command("prog", &opts), "prog --color 'a b' --line-count 5 --temp 0.5 --verbose".to_string());
+
+        let opts = CommandOptions {
+            name: "".to_string(),
+            count: 1,
+            color: None,
+            quiet: false,
+            include: vec!("src".to_string(), "it's".to_string()),
+            files: vec!("a.txt".to_string(), "b c.txt".to_string())
+        };
+
+        assert_eq!(quoted_command("my prog", &opts),
+            "'my prog' --name '' --count 1 --include src --include 'it'\\''s' a.txt 'b c.txt'".to_string());
+    }
+
+    #[test]
+    fn test_encode_enum_and_map() {
+        let mut env = HashMap::new();
+        env.insert("LANG".to_string(), "C".to_string());
+        let opts = LaunchOptions { env: env, level: Warn, rest: vec!("build".to_string()) };
+
+        let mut encoder = FlagEncoder::new();
+        opts.encode(&mut encoder).unwrap();
+
+        assert_eq!(encoder.fields(), vec!(
+            ("env".to_string(), EncodedList(vec!("LANG=C".to_string()))),
+            ("level".to_string(), EncodedValue("warn".to_string())),
+            ("rest".to_string(), EncodedList(vec!("build".to_string())))
+        ));
+
+        assert_eq!(quoted_command("run", &opts), "run LANG=C --level warn build".to_string());
+
+        let opts = LaunchOptions { env: HashMap::new(), level: Debug, rest: vec!() };
+        assert_eq!(quoted_command("run", &opts), "run --level debug".to_string());
+    }
+
+    #[test]
+    fn test_encode_enum_with_fields() {
+        let mut encoder = FlagEncoder::new();
+        DeployOptions { target: Local }.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.fields(), vec!(("target".to_string(), EncodedValue("local".to_string()))));
+
+        let mut encoder = FlagEncoder::new();
+        let err = DeployOptions { target: Remote("x".to_string()) }.encode(&mut encoder).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "target is an enum with fields; only enums of plain variants can be flags".to_string());
+    }
+}
@@ -41,11 +41,24 @@ fn main() {
 
 Several different types are allowed within the struct:
 
-* Any integer type (usage not yet implemented)
-* Any float type (usage not yet implemented)
+* Any integer type, listed by `usage` as `--count <INT>`
+* Any float type, listed as `--ratio <FLOAT>`
 * `String`
 * `bool`, for optional flags with no argument
 * `Option<T>`, for optional flags with an argument
+
+Flags can come before, after or between the other arguments, which end up
+in the rest field in the order they were given. Everything after `--` goes
+there too, without being read as flags.
+
+The crate root has what most programs need. The rest lives in `config`
+(`FlagConfiguration` and its settings), `decode` (the other ways of
+decoding and the errors they return), `usage` (help text and flag
+descriptions), `text` (quoting and error formatting) and `verbosity` (a
+ready-made `-v`/`-q` pair to embed in a flag struct).
+
+`examples/from_getopts.rs` writes a flag struct for a program's getopts
+options, to help port it.
 */
 
 #![crate_name = "hammer"]
@@ -53,18 +66,18 @@ Several different types are allowed within the struct:
 #![feature(macro_rules)]
 
 extern crate serialize;
-use serialize::{Decoder, Decodable};
-use std::collections::hashmap::HashMap;
+extern crate libc;
+use serialize::{Decodable, Encodable};
+use std::io::Buffer;
 
-pub use usage::usage;
+use decode::FlagDecoder;
 use usage::UsageDecoder;
-use util::{canonical_field_name};
 
-pub trait FlagConfig {
-    fn config(_: Option<Self>, c: FlagConfiguration) -> FlagConfiguration {
-        c
-    }
-}
+// the prelude: what most programs need
+pub use config::{FlagConfig, FlagConfiguration};
+pub use decode::{decode_args, HammerError, Decoded};
+pub use usage::usage;
+pub use verbosity::Verbosity;
 
 trait FlagParse : FlagConfig {
     fn decode_flags(d: &mut FlagDecoder) -> Result<Self, HammerError>;
@@ -138,390 +151,221 @@ macro_rules! hammer_config(
     )
 )
 
-mod util;
-mod usage;
-
-/** Contains the configuration associated with a FlagConfig,
-such as the short versions of flags and description of the program.
-*/
-#[deriving(Show, PartialEq)]
-pub struct FlagConfiguration {
-    short_aliases: HashMap<String, char>,
-    description: Option<String>,
-    rest_field: String
-}
+pub mod config;
+pub mod decode;
+pub mod usage;
+pub mod text;
+pub mod verbosity;
 
-impl FlagConfiguration {
-    pub fn new() -> FlagConfiguration {
-        FlagConfiguration {
-            short_aliases: HashMap::new(),
-            description: None,
-            rest_field: "rest".to_string()
-        }
-    }
+mod util;
+mod encode;
+mod token;
 
-    /// Add new "short" version of a flag
-    ///
-    /// ```flag_config.short("verbose", 'v')```
-    pub fn short(mut self, string: &str, char: char) -> FlagConfiguration {
-        self.short_aliases.insert(string.to_string(), char);
-        self
-    }
+// Everything below was available at the crate root before the modules above
+// existed; it's kept for one release so existing imports keep compiling.
 
-    /// Add a description
-    ///
-    /// ```flag_config.descr("Foo is a program to do bar")```
-    pub fn desc(mut self, string: &str) -> FlagConfiguration {
-        self.description = Some(string.to_string());
-        self
-    }
+pub use config::{DuplicatePolicy, KeepFirst, KeepLast, Accumulate, Error};
+pub use decode::{HammerResult, ErrorKind, InvalidFlags, HelpRequested, VersionRequested, ExitedEarly, ConfigError};
+pub use usage::FlagSpec;
 
-    /// Change the name of the "extra arguments" field
-    ///
-    /// The associated field must be of `type Vec<String>`
-    ///
-    /// ```flag_config.rest_field("remaining")```
-    pub fn rest_field(mut self, string: &str) -> FlagConfiguration {
-        self.rest_field = string.to_string();
-        self
-    }
+#[doc(hidden)]
+pub use decode::FlagEncoder;
 
-    pub fn short_for(&self, field: &str) -> Option<char> {
-        self.short_aliases.find_equiv(&field).map(|c| *c)
-    }
-
-    pub fn description(&self) -> Option<String> {
-        self.description.as_ref().map(|d| d.clone())
-    }
+#[deprecated = "use hammer::decode::decode_args_or_exit"]
+pub fn decode_args_or_exit<T: Flags>(args: &[String]) -> T {
+    decode::decode_args_or_exit(args)
 }
 
-#[deriving(Show, PartialEq)]
-enum DecoderState {
-    Processing,
-    ProcessingRest(int)
+#[deprecated = "use hammer::decode::decode_into"]
+pub fn decode_into<T: Flags + Encodable<FlagEncoder, HammerError>>(target: &mut T, args: &[String]) -> HammerResult<()> {
+    decode::decode_into(target, args)
 }
 
-#[deriving(Show, PartialEq)]
-pub struct FlagDecoder {
-    source: Vec<String>,
-    current_field: Option<String>,
-    error: Option<String>,
-    config: FlagConfiguration,
-    state: DecoderState,
-    done: bool
+#[deprecated = "use hammer::decode::decode_lines"]
+pub fn decode_lines<T: Flags>(reader: &mut Buffer) -> Vec<(uint, HammerResult<T>)> {
+    decode::decode_lines(reader)
 }
 
-impl FlagDecoder {
-    pub fn new<T: FlagConfig>(args: &[String]) -> FlagDecoder {
-        let flag_config = FlagConfiguration::new();
-        FlagDecoder{
-            source: Vec::from_slice(args),
-            current_field: None,
-            error: None,
-            config: FlagConfig::config(None::<T>, flag_config),
-            state: Processing,
-            done: false
-        }
-    }
-
-    pub fn remaining(&self) -> Vec<String> {
-        self.source.clone()
-    }
-
-    /*
-        These helper functions encapsulate the different ways of using a field name.
-        For now, this is limited to the field name prefixed by `--`, but I plan to
-        add short-name configuration and `--foo=bar` support soon. These methods should
-        be the only place that needs to be updated to support new forms.
-    */
-
-    fn canonical_field_name(&self) -> String {
-        canonical_field_name(self.current_field.get_ref().as_slice())
-    }
-
-    fn field_pos(&self) -> Option<uint> {
-        let source = &self.source;
-        let aliases = &self.config.short_aliases;
-
-        source.as_slice().position_elem(&self.canonical_field_name()).or_else(|| {
-            aliases.find(self.current_field.get_ref()).and_then(|&c| {
-                source.iter().position(|s| s.as_bytes()[0] == '-' as u8 && s.as_bytes()[1] == c as u8)
-            })
-        })
-    }
-
-    fn remove_bool_field(&mut self) {
-        let pos = self.field_pos();
-        self.source.remove(pos.unwrap());
-    }
-
-    fn remove_val_field(&mut self) {
-        let pos = self.field_pos();
-
-        // removes the flag and the value it's set to
-        self.source.remove(pos.unwrap());
-        self.source.remove(pos.unwrap());
-    }
+#[deprecated = "use hammer::usage::synopsis"]
+pub fn synopsis<T: UsageParse>(program: &str) -> String {
+    usage::synopsis::<T>(program)
 }
 
-pub type HammerResult<T> = Result<T, HammerError>;
-
-#[deriving(Clone, PartialEq, PartialOrd, Hash, Show)]
-pub struct HammerError {
-    pub message: String
+#[deprecated = "use hammer::usage::describe"]
+pub fn describe<T: UsageParse>() -> Vec<FlagSpec> {
+    usage::describe::<T>()
 }
 
-impl HammerError {
-    fn new<T>(message: String) -> HammerResult<T> {
-        Err(HammerError{ message: message })
-    }
+#[deprecated = "use hammer::text::format_error_annotated"]
+pub fn format_error_annotated(args: &[String], err: &HammerError) -> String {
+    text::format_error_annotated(args, err)
 }
 
-impl Decoder<HammerError> for FlagDecoder {
-    fn read_nil(&mut self) -> HammerResult<()> { unimplemented!() }
-
-    fn read_uint(&mut self) -> HammerResult<uint> {
-        match self.read_str() {
-            Ok(s) => {
-                match from_str(s.as_slice()) {
-                    Some(i) => Ok(i),
-                    None => Err(HammerError { message: format!("could not convert {} to an integer", s) })
-                }
-            },
-            Err(e) => Err(e)
-        }
-    }
+#[deprecated = "use hammer::text::quoted_command"]
+pub fn quoted_command<T: FlagConfig + Encodable<FlagEncoder, HammerError>>(program: &str, flags: &T) -> String {
+    text::quoted_command(program, flags)
+}
 
-    // doesn't handle "too large to represent" problems. will just truncate.
-    fn read_u64(&mut self) -> HammerResult<u64> { self.read_uint().map(|v| v as u64) }
-    fn read_u32(&mut self) -> HammerResult<u32> { self.read_uint().map(|v| v as u32) }
-    fn read_u16(&mut self) -> HammerResult<u16> { self.read_uint().map(|v| v as u16) }
-    fn read_u8(&mut self) -> HammerResult<u8>   { self.read_uint().map(|v| v as u8)  }
-    fn read_int(&mut self) -> HammerResult<int> { self.read_uint().map(|v| v as int) }
-    fn read_i64(&mut self) -> HammerResult<i64> { self.read_uint().map(|v| v as i64) }
-    fn read_i32(&mut self) -> HammerResult<i32> { self.read_uint().map(|v| v as i32) }
-    fn read_i16(&mut self) -> HammerResult<i16> { self.read_uint().map(|v| v as i16) }
-    fn read_i8(&mut self) -> HammerResult<i8>   { self.read_uint().map(|v| v as i8)  }
-
-    fn read_bool(&mut self) -> HammerResult<bool> {
-        match self.field_pos() {
-            None => Ok(false),
-            Some(_) => {
-                self.remove_bool_field();
-                Ok(true)
-            }
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use config::Expert;
+    use decode::harness::owned;
 
-    fn read_f64(&mut self) -> HammerResult<f64> {
-        match self.read_str() {
-            Ok(s) => {
-                match from_str(s.as_slice()) {
-                    Some(f) => Ok(f),
-                    None => Err(HammerError { message: format!("could not convert {} to a float", s) })
-                }
-            },
-            Err(e) => Err(e)
-        }
-    }
-    fn read_f32(&mut self) -> HammerResult<f32> { self.read_f64().map(|v| v as f32) }
-    fn read_char(&mut self) -> HammerResult<char> {
-        match self.read_str() {
-            Ok(s) => {
-                if s.as_slice().char_len() == 1 {
-                    Ok(s.as_slice().char_at(0))
-                } else {
-                    Err(HammerError { message: format!("{} is not a single character", s) })
-                }
-            },
-            Err(e) => Err(e)
-        }
+    #[deriving(Decodable, Encodable, Show, PartialEq)]
+    struct PathFlags {
+        count: uint,
+        verbose: bool,
+        rest: Vec<String>
     }
 
-    fn read_str(&mut self) -> HammerResult<String> {
-        match self.state {
-            ProcessingRest(i) => return Ok(self.remaining()[i as uint].to_string()),
-            _ => ()
-        }
-
-        let position = self.field_pos();
-
-        if position.is_none() {
-            return HammerError::new(format!("{} is required", self.canonical_field_name()));
-        }
+    hammer_config!(PathFlags |c| { c.short("verbose", 'v') })
 
-        let pos = position.unwrap();
-        let val = self.source[pos + 1].clone();
+    #[allow(dead_code)]
+    #[deriving(Decodable, Encodable)]
+    struct ReferenceFlags {
+        output: Option<String>,
+        jobs: uint,
+        dry_run: bool,
+        debug_gc: bool,
+        rest: Vec<String>
+    }
 
-        self.remove_val_field();
+    hammer_config!(ReferenceFlags |c| {
+        c.desc("Builds things\n\n")
+         .program_name("build")
+         .short("dry_run", 'n')
+         .shorts("jobs", &['j', 'J'])
+         .help("jobs", "how many to run at once  ")
+         .validate("jobs", nonzero)
+         .ignored_when("output", "dry_run", "nothing is written")
+         .help_tier("debug_gc", Expert)
+    })
 
-        Ok(val)
-        /* NOTE: when Vec has an indexing method that returns an Option, do
-         * this.
-        match val {
-            None => HammerError::new(format!("{} is missing a following string", self.canonical_field_name())),
-            Some(val) => Ok(val)
-        }
-        */
+    fn nonzero(value: &str) -> Result<(), String> {
+        if value == "0" { Err("must not be 0.".to_string()) } else { Ok(()) }
     }
 
-    #[allow(unused_variable)]
-    fn read_struct<T>(&mut self, s_name: &str, len: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
-        f(self)
+    fn args() -> Vec<String> {
+        vec!("--count".to_string(), "2".to_string(), "-v".to_string(), "a".to_string())
     }
 
-    #[allow(unused_variable)]
-    fn read_struct_field<T>(&mut self, f_name: &str, f_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
-        assert!(!self.done, "Flag struct must not contain any fields after {}", self.config.rest_field);
-
-        self.current_field = Some(f_name.to_string());
-        f(self)
+    // no whitespace at the end of a line, never two blank lines in a row,
+    // and exactly one newline at the end
+    fn assert_finished(text: &str) {
+        assert!(text.ends_with("\n") && !text.ends_with("\n\n"), "bad ending: {}", text);
+        assert!(!text.contains("\n\n\n"), "two blank lines in a row: {}", text);
+        assert!(text.lines().all(|line| line == line.trim_right()), "trailing whitespace: {}", text);
     }
 
-    fn read_option<T>(&mut self, f: |&mut FlagDecoder, bool| -> HammerResult<T>) -> HammerResult<T> {
-        match self.field_pos() {
-            None => f(self, false),
-            Some(_) => f(self, true)
-        }
+    fn expected() -> PathFlags {
+        PathFlags { count: 2, verbose: true, rest: vec!("a".to_string()) }
     }
 
-    // the rest of these are pretty weird or hard to implement.
-
-    #[allow(unused_variable)]
-    fn read_enum<T>(&mut self, name: &str, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_enum_variant<T>(&mut self, names: &[&str], f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_enum_variant_arg<T>(&mut self, a_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_enum_struct_variant<T>(&mut self, names: &[&str], f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_enum_struct_variant_field<T>(&mut self, f_name: &str, f_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-
-    #[allow(unused_variable)]
-    fn read_tuple<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_tuple_arg<T>(&mut self, a_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_tuple_struct<T>(&mut self, s_name: &str, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_tuple_struct_arg<T>(&mut self, a_idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-
-    #[allow(unused_variable)]
-    fn read_seq<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> {
-        let len = self.remaining().len();
-        let current_field = self.current_field.as_ref().unwrap().to_string();
-
-        if current_field.as_slice() != self.config.rest_field.as_slice() { unimplemented!() }
-        self.state = ProcessingRest(-1);
-        let ret = f(self, len);
-        self.done = true;
-        ret
-    }
+    #[test]
+    fn test_prelude() {
+        use {decode_args, usage, HammerError, Decoded};
+
+        let flags: PathFlags = decode_args(args().as_slice()).unwrap();
+        assert_eq!(flags, expected());
+        assert_eq!(usage::<PathFlags>(false), (None, "    --count <INT>\n-v, [--verbose]\n".to_string()));
 
-    #[allow(unused_variable)]
-    fn read_seq_elt<T>(&mut self, idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> {
-        self.state = match self.state {
-            ProcessingRest(i) => ProcessingRest(i + 1),
-            _ => unimplemented!()
-        };
+        let err: Result<PathFlags, HammerError> = decode_args(vec!().as_slice());
+        assert_eq!(err.unwrap_err().message, "--count is required".to_string());
 
-        f(self)
+        let _: Option<Decoded<PathFlags>> = None;
     }
 
-    #[allow(unused_variable)]
-    fn read_map<T>(&mut self, f: |&mut FlagDecoder, uint| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_map_elt_key<T>(&mut self, idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_map_elt_val<T>(&mut self, idx: uint, f: |&mut FlagDecoder| -> HammerResult<T>) -> HammerResult<T> { unimplemented!() }
-}
+    #[test]
+    fn test_module_paths() {
+        use config::{FlagConfig, FlagConfiguration, KeepLast};
+        use decode::{decode, decode_into, InvalidFlags};
+        use usage::{synopsis, describe};
+        use text::{quoted_command, shell_quote};
 
-/**
-Convert arguments into struct T
+        let decoded = decode::<PathFlags>(args().as_slice()).unwrap();
+        assert_eq!(decoded.value(), &expected());
+        assert!(decoded.warnings().is_empty());
 
-hammer_config! must be called on T beforehand.
-*/
-pub fn decode_args<T: FlagParse>(args: &[String]) -> HammerResult<T> {
-    let mut decoder = FlagDecoder::new::<T>(args);
-    FlagParse::decode_flags(&mut decoder)
-}
+        let mut flags = decoded.into_inner();
+        decode_into(&mut flags, vec!("--count".to_string(), "3".to_string()).as_slice()).unwrap();
+        assert_eq!(flags.count, 3);
 
-#[cfg(test)]
-mod tests {
-    use super::{FlagDecoder, HammerResult, HammerError};
-    use serialize::{Decoder,Decodable};
-
-    #[deriving(Decodable, Show, PartialEq)]
-    struct CompileFlags {
-        color: bool,
-        count: uint,
-        maybe: Option<uint>,
-        some_some: bool
-    }
+        assert_eq!(synopsis::<PathFlags>("prog"), "prog --count <INT> [-v] [rest...]".to_string());
+        assert_eq!(describe::<PathFlags>().len(), 2);
+        assert_eq!(quoted_command("prog", &flags), "prog --count 3 --verbose a".to_string());
+        assert_eq!(shell_quote("a b"), "'a b'".to_string());
 
-    hammer_config!(CompileFlags |c| {
-        c.short("color", 'c')
-    })
+        let config = FlagConfig::config(None::<PathFlags>, FlagConfiguration::new().duplicates(KeepLast));
+        assert_eq!(config.short_for("verbose"), Some('v'));
 
-    #[deriving(Decodable, Show, PartialEq)]
-    struct GlobalFlags {
-        color: bool,
-        verbose: bool,
-        rest: Vec<String>
+        let err = decode::<PathFlags>(vec!("--count".to_string(), "x".to_string()).as_slice());
+        assert_eq!(err.unwrap_err().kind, InvalidFlags);
     }
 
-    hammer_config!(GlobalFlags)
+    #[test]
+    #[allow(deprecated)]
+    fn test_old_paths() {
+        use {decode_args, decode_into, synopsis, describe, quoted_command, HammerResult, InvalidFlags};
 
-    #[deriving(Decodable, Show, PartialEq)]
-    struct AliasedRest {
-        color: bool,
-        verbose: bool,
-        remaining: Vec<String>
-    }
+        let flags: HammerResult<PathFlags> = decode_args(args().as_slice());
+        let mut flags = flags.unwrap();
 
-    hammer_config!(AliasedRest |c| {
-        c.short("verbose", 'v').rest_field("remaining")
-    })
+        decode_into(&mut flags, vec!("--count".to_string(), "3".to_string()).as_slice()).unwrap();
+        assert_eq!(flags.count, 3);
 
-    #[test]
-    fn test_example() {
-        let args = vec!("--count".to_string(), "1".to_string(), "foo".to_string(), "-c".to_string());
-        let mut decoder = FlagDecoder::new::<CompileFlags>(args.as_slice());
-        let flags: CompileFlags = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(synopsis::<PathFlags>("prog"), "prog --count <INT> [-v] [rest...]".to_string());
+        assert_eq!(describe::<PathFlags>().len(), 2);
+        assert_eq!(quoted_command("prog", &flags), "prog --count 3 --verbose a".to_string());
 
-        assert_eq!(decoder.remaining(), vec!("foo".to_string()));
-        assert_eq!(flags, CompileFlags{ color: true, count: 1u, maybe: None, some_some: false });
+        let err: HammerResult<PathFlags> = decode_args(vec!("--count".to_string(), "x".to_string()).as_slice());
+        assert_eq!(err.unwrap_err().kind, InvalidFlags);
     }
 
     #[test]
-    fn test_err() {
-        let mut decoder = FlagDecoder::new::<CompileFlags>(vec!().as_slice());
-        let flags: HammerResult<CompileFlags> = Decodable::decode(&mut decoder);
+    fn test_rendered_whitespace() {
+        use config::{Common, Full};
+        use decode::{decode, decode_args, render_errors, HammerError};
+        use usage::{usage_for, full_usage, flag_usage, synopsis};
+        use text::format_error_annotated;
+
+        let mut rendered = vec!();
+
+        for tier in [Common, Full, Expert].iter() {
+            let (desc, options) = usage_for::<ReferenceFlags>(tier.clone(), false);
+            rendered.push(desc.unwrap());
+            rendered.push(options);
+            let (_, options) = usage_for::<ReferenceFlags>(tier.clone(), true);
+            rendered.push(options);
+        }
 
-        assert_eq!(flags, Err(HammerError { message: "--count is required".to_string() }));
+        // a synopsis is a single line, finished by whoever prints it
+        rendered.push(format!("{}\n", synopsis::<ReferenceFlags>("build")));
 
-        assert!(decoder.error == None, "The decoder doesn't have an error");
-    }
+        rendered.push(full_usage::<ReferenceFlags>(None));
+        rendered.push(full_usage::<ReferenceFlags>(Some("prog")));
 
-    #[test]
-    fn test_rest() {
-        let args = vec!("--verbose".to_string(), "hello".to_string(), "goodbye".to_string());
+        for name in ["output", "--jobs", "-J", "-n", "debug_gc"].iter() {
+            rendered.push(flag_usage::<ReferenceFlags>(*name).unwrap());
+        }
 
-        let mut decoder = FlagDecoder::new::<GlobalFlags>(args.as_slice());
-        let flags: GlobalFlags = Decodable::decode(&mut decoder).unwrap();
+        for help in vec!(owned(&["--help"]), owned(&["-h"]), owned(&["--help", "--verbose"])).iter() {
+            let err: Result<ReferenceFlags, HammerError> = decode_args(help.as_slice());
+            rendered.push(err.unwrap_err().message);
+        }
 
-        assert_eq!(flags, GlobalFlags { color: false, verbose: true, rest: vec!("hello".to_string(), "goodbye".to_string()) });
-    }
+        let bad = owned(&["--jobs", "0", "--output"]);
+        let err: HammerError = decode_args::<ReferenceFlags>(bad.as_slice()).unwrap_err();
+        assert_eq!(err.message, "--jobs: 0 must not be 0".to_string());
 
-    #[test]
-    fn test_aliased_rest() {
-        let args = vec!("-v".to_string(), "hello".to_string(), "goodbye".to_string());
+        rendered.push(format_error_annotated(bad.as_slice(), &err));
+        rendered.push(format_error_annotated(&[], &err));
+        rendered.push(render_errors::<ReferenceFlags>(bad.as_slice(), &[err.clone(), err]));
 
-        let mut decoder = FlagDecoder::new::<AliasedRest>(args.as_slice());
-        let flags: AliasedRest = Decodable::decode(&mut decoder).unwrap();
+        let decoded = decode::<ReferenceFlags>(owned(&["--jobs", "2", "a"]).as_slice()).unwrap();
+        rendered.push(decoded.report());
 
-        assert_eq!(flags, AliasedRest { color: false, verbose: true, remaining: vec!("hello".to_string(), "goodbye".to_string()) });
+        for text in rendered.iter() {
+            assert_finished(text.as_slice());
+        }
     }
-
 }
@@ -0,0 +1,396 @@
+/*!
+Helpers for turning flags and command lines into text and back
+*/
+
+use std::cmp;
+
+use decode::HammerError;
+
+pub use encode::quoted_command;
+
+/// What the value of a `sensitive` flag is replaced with
+pub static REDACTED: &'static str = "*****";
+
+/// The most characters of an argument or value that an error message
+/// quotes; see `shortened`
+pub static QUOTED_CHARS: uint = 64;
+
+/// An argument or value as error messages quote it: anything longer than
+/// `QUOTED_CHARS` characters is cut to that many, followed by `...`, so a
+/// huge argument doesn't bury the message
+pub fn shortened(value: &str) -> String {
+    if value.char_len() <= QUOTED_CHARS {
+        value.to_string()
+    } else {
+        format!("{}...", value.slice_chars(0, QUOTED_CHARS))
+    }
+}
+
+/// The long form of a field's flag: `line_count` is `--line-count`, and so
+/// is `line__count`
+pub fn canonical_field_name(field: &str) -> String {
+    let words: Vec<&str> = field.split('_').filter(|word| !word.is_empty()).collect();
+    format!("--{}", words.connect("-"))
+}
+
+/// Splits a command line into arguments like a shell would: whitespace
+/// separates arguments, single quotes preserve everything literally, and
+/// double quotes and backslashes work as they do in sh
+pub fn shell_split(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec!();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = line.chars();
+
+    loop {
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break
+        };
+
+        match c {
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(format!("unterminated single quote in {}", line))
+                    }
+                }
+            },
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) if c == '"' || c == '\\' || c == '$' || c == '`' => current.push(c),
+                            Some(c) => { current.push('\\'); current.push(c) },
+                            None => return Err(format!("unterminated double quote in {}", line))
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(format!("unterminated double quote in {}", line))
+                    }
+                }
+            },
+            '\\' => {
+                in_arg = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => return Err(format!("trailing backslash in {}", line))
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(current);
+                    current = String::new();
+                    in_arg = false;
+                }
+            },
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
+}
+
+/// Quotes an argument so that a shell (or `shell_split`) reads it back
+/// unchanged; arguments that don't need quoting are left alone
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty() && arg.chars().all(|c| {
+        c.is_alphanumeric() || "-_=./:,@+%".contains_char(c)
+    });
+
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace("'", "'\\''"))
+    }
+}
+
+/**
+Tidies a block of rendered text the way every renderer in hammer leaves it:
+no whitespace at the end of a line, sections separated by exactly one blank
+line, and a single newline at the end, with no blank lines before or after.
+Empty text stays empty.
+*/
+pub fn finish_block(text: &str) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+
+    for line in text.lines() {
+        let line = line.trim_right();
+
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+
+        if blank { out.push_str("\n") }
+        blank = false;
+
+        out.push_str(line);
+        out.push_str("\n");
+    }
+
+    out
+}
+
+// the widest command line echoed by `format_error_annotated`
+static ANNOTATED_WIDTH: uint = 72;
+
+// the most arguments echoed by `format_error_annotated`
+static ANNOTATED_TOKENS: uint = 15;
+
+/**
+Render an error like rustc does: the command line, a caret line under the
+offending argument, then the message.
+
+`args` must be the same arguments that were decoded. Arguments containing
+spaces are quoted in the echo, and long command lines are cut down to 15
+arguments and 72 columns around the caret; `ErrorFormatter` changes those
+limits. Errors that don't point at an argument are rendered as just the
+message.
+*/
+pub fn format_error_annotated(args: &[String], err: &HammerError) -> String {
+    ErrorFormatter::new().format(args, err)
+}
+
+/**
+Renders errors like `format_error_annotated`, with its own limits on how
+much of the command line is echoed
+
+Arguments left out on either side of the offending one are replaced by a
+single `...`.
+
+```
+let rendered = ErrorFormatter::new().max_tokens(5).width(120).format(args, &err);
+```
+*/
+#[deriving(Clone, PartialEq, Show)]
+pub struct ErrorFormatter {
+    max_tokens: uint,
+    width: uint
+}
+
+impl ErrorFormatter {
+    pub fn new() -> ErrorFormatter {
+        ErrorFormatter { max_tokens: ANNOTATED_TOKENS, width: ANNOTATED_WIDTH }
+    }
+
+    /// Echo at most `max_tokens` arguments (and at least one)
+    pub fn max_tokens(mut self, max_tokens: uint) -> ErrorFormatter {
+        self.max_tokens = cmp::max(max_tokens, 1);
+        self
+    }
+
+    /// Echo at most `width` columns (and at least one)
+    pub fn width(mut self, width: uint) -> ErrorFormatter {
+        self.width = cmp::max(width, 1);
+        self
+    }
+
+    pub fn format(&self, args: &[String], err: &HammerError) -> String {
+        let index = match err.index {
+            Some(index) if index < args.len() => index,
+            _ => return finish_block(format!("error: {}", err.message).as_slice())
+        };
+
+        let (tokens, index) = self.echoed(args, index);
+        let line = tokens.connect(" ");
+
+        let column = tokens.slice_to(index).iter().fold(0, |col, t| col + t.as_slice().char_len() + 1);
+        let width = cmp::max(tokens[index].as_slice().char_len(), 1);
+
+        let (line, column, width) = window(line.as_slice(), column, width, self.width);
+
+        finish_block(format!("{}\n{}{}\nerror: {}", line, " ".repeat(column), "^".repeat(width), err.message).as_slice())
+    }
+
+    // the quoted arguments around `index`, with `...` for the ones left
+    // out, and where the argument at `index` ended up
+    fn echoed(&self, args: &[String], index: uint) -> (Vec<String>, uint) {
+        let half = self.max_tokens / 2;
+        let start = if index > half { index - half } else { 0 };
+        let end = cmp::min(start + self.max_tokens, args.len());
+        let start = if end > self.max_tokens { cmp::min(start, end - self.max_tokens) } else { 0 };
+
+        let mut tokens: Vec<String> = args.slice(start, end).iter().map(|a| shell_quote(a.as_slice())).collect();
+        let mut index = index - start;
+
+        if start > 0 {
+            tokens.insert(0, "...".to_string());
+            index += 1;
+        }
+
+        if end < args.len() {
+            tokens.push("...".to_string());
+        }
+
+        (tokens, index)
+    }
+}
+
+// cuts `line` down to at most `max` columns around the `width` columns at
+// `column`, marking what was cut with `...`, and returns the new line, and
+// the column and width of what's left of the marked part; an argument wider
+// than the window starts at its left edge
+fn window(line: &str, column: uint, width: uint, max: uint) -> (String, uint, uint) {
+    let len = line.char_len();
+
+    if len <= max {
+        return (line.to_string(), column, width);
+    }
+
+    let start = if column + width / 2 > max / 2 { column + width / 2 - max / 2 } else { 0 };
+    let start = cmp::min(cmp::min(start, len - max), column);
+    let end = start + max;
+    let width = cmp::min(width, end - column);
+
+    let mut out = String::new();
+    let mut column = column - start;
+
+    if start > 0 {
+        out.push_str("...");
+        column += 3;
+    }
+
+    out.push_str(line.chars().skip(start).take(max).collect::<String>().as_slice());
+
+    if end < len {
+        out.push_str("...");
+    }
+
+    (out, column, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shell_split, shell_quote, canonical_field_name, finish_block, format_error_annotated, ErrorFormatter, shortened};
+    use decode::{HammerError, InvalidFlags};
+
+    fn split(line: &str) -> Vec<String> {
+        shell_split(line).unwrap()
+    }
+
+    #[test]
+    fn test_shell_split() {
+        assert_eq!(split("  --count 1  foo "), vec!("--count".to_string(), "1".to_string(), "foo".to_string()));
+        assert_eq!(split("--name 'a b' \"c \\\"d\\\"\" e\\ f ''"),
+            vec!("--name".to_string(), "a b".to_string(), "c \"d\"".to_string(), "e f".to_string(), "".to_string()));
+    }
+
+    #[test]
+    fn test_shell_split_unterminated() {
+        assert_eq!(shell_split("--name 'a b"), Err("unterminated single quote in --name 'a b".to_string()));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("--count=5"), "--count=5".to_string());
+        assert_eq!(shell_quote("a b"), "'a b'".to_string());
+        assert_eq!(shell_quote(""), "''".to_string());
+        assert_eq!(shell_quote("it's"), "'it'\\''s'".to_string());
+        assert_eq!(split(shell_quote("it's \"here\"").as_slice()), vec!("it's \"here\"".to_string()));
+    }
+
+    fn long_args(odd: Option<(uint, &str)>) -> Vec<String> {
+        range(0u, 1000).map(|i| match odd {
+            Some((index, arg)) if index == i => arg.to_string(),
+            _ => format!("arg{}", i)
+        }).collect()
+    }
+
+    fn format_at(args: &[String], index: uint) -> Vec<String> {
+        let err = HammerError { kind: InvalidFlags, message: "bad".to_string(), line: None, index: Some(index) };
+        let rendered = ErrorFormatter::new().max_tokens(5).format(args, &err);
+
+        rendered.as_slice().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_error_window_start() {
+        assert_eq!(format_at(long_args(None).as_slice(), 0), vec!(
+            "arg0 arg1 arg2 arg3 arg4 ...".to_string(),
+            "^^^^".to_string(),
+            "error: bad".to_string()
+        ));
+    }
+
+    #[test]
+    fn test_error_window_middle() {
+        assert_eq!(format_at(long_args(Some((500, "a b"))).as_slice(), 500), vec!(
+            "... arg498 arg499 'a b' arg501 arg502 ...".to_string(),
+            "                  ^^^^^".to_string(),
+            "error: bad".to_string()
+        ));
+    }
+
+    #[test]
+    fn test_error_window_end() {
+        assert_eq!(format_at(long_args(None).as_slice(), 999), vec!(
+            "... arg995 arg996 arg997 arg998 arg999".to_string(),
+            "                                ^^^^^^".to_string(),
+            "error: bad".to_string()
+        ));
+    }
+
+    #[test]
+    fn test_error_window_wide_argument() {
+        let wide = "x".repeat(100);
+        let args = vec!("--name".to_string(), wide.clone(), "--count".to_string());
+        let err = HammerError { kind: InvalidFlags, message: "bad".to_string(), line: None, index: Some(1) };
+
+        let rendered = format_error_annotated(args.as_slice(), &err);
+        let lines: Vec<String> = rendered.as_slice().lines().map(|l| l.to_string()).collect();
+
+        assert_eq!(lines, vec!(
+            format!("...{}...", "x".repeat(72)),
+            format!("   {}", "^".repeat(72)),
+            "error: bad".to_string()
+        ));
+    }
+
+    #[test]
+    fn test_error_window_narrow() {
+        let args = vec!("--name".to_string(), "x".repeat(100), "--count".to_string());
+        let err = HammerError { kind: InvalidFlags, message: "bad".to_string(), line: None, index: Some(1) };
+
+        let lines = |width: uint| -> Vec<String> {
+            let rendered = ErrorFormatter::new().width(width).format(args.as_slice(), &err);
+            rendered.as_slice().lines().map(|l| l.to_string()).collect()
+        };
+
+        assert_eq!(lines(3), vec!("...xxx...".to_string(), "   ^^^".to_string(), "error: bad".to_string()));
+        assert_eq!(lines(0), vec!("...x...".to_string(), "   ^".to_string(), "error: bad".to_string()));
+    }
+
+    #[test]
+    fn test_shortened() {
+        assert_eq!(shortened("short"), "short".to_string());
+        assert_eq!(shortened(String::from_char(64, 'x').as_slice()), String::from_char(64, 'x'));
+        assert_eq!(shortened(String::from_char(65, 'é').as_slice()), format!("{}...", String::from_char(64, 'é')));
+    }
+
+    #[test]
+    fn test_finish_block() {
+        assert_eq!(finish_block("\n\nUsage: prog  \n\n\n\nDoes things\n\n    --count \n\n"),
+            "Usage: prog\n\nDoes things\n\n    --count\n".to_string());
+        assert_eq!(finish_block("error: bad"), "error: bad\n".to_string());
+        assert_eq!(finish_block(" \n"), "".to_string());
+    }
+
+    #[test]
+    fn test_canonical_field_name() {
+        assert_eq!(canonical_field_name("line_count"), "--line-count".to_string());
+        assert_eq!(canonical_field_name("line__count"), "--line-count".to_string());
+    }
+}
@@ -0,0 +1,131 @@
+// The command line as the decoder sees it: every argument, what it looks
+// like on its own, and which field took it
+
+use util::is_negative_number;
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum TokenKind {
+    // `--flag` or `--flag=value`
+    LongFlag,
+    // `-f` or `-f=value`
+    ShortFlag,
+    // several short flags in one argument, `-xyz`, or one with its value
+    // attached, `-n10`; which it is depends on the flags
+    Cluster,
+    // an argument a flag took as its value
+    Value,
+    // anything else, including `-`, negative numbers and everything after `--`
+    Positional,
+    // the first `--`
+    Separator
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct Token {
+    pub raw: String,
+    // where the argument is on the command line
+    pub index: uint,
+    pub kind: TokenKind,
+    // the field that took the argument, once one has; arguments the decoder
+    // takes for itself, like `override_flag` assignments, have none
+    pub consumed_by: Option<String>
+}
+
+impl Token {
+    pub fn new(raw: &str, index: uint, kind: TokenKind) -> Token {
+        Token { raw: raw.to_string(), index: index, kind: kind, consumed_by: None }
+    }
+
+    pub fn is_flag(&self) -> bool {
+        match self.kind {
+            LongFlag | ShortFlag | Cluster => true,
+            _ => false
+        }
+    }
+}
+
+// every argument as a token; with `passthrough`, nothing after the first
+// `--` is a flag, and otherwise `--` is an ordinary argument
+pub fn tokenize(args: &[String], passthrough: bool) -> Vec<Token> {
+    let mut tokens = vec!();
+    let mut separated = false;
+
+    for (index, arg) in args.iter().enumerate() {
+        let arg = arg.as_slice();
+
+        let kind = if separated {
+            Positional
+        } else if arg == "--" {
+            separated = passthrough;
+            if passthrough { Separator } else { Positional }
+        } else {
+            kind_of(arg)
+        };
+
+        tokens.push(Token::new(arg, index, kind));
+    }
+
+    tokens
+}
+
+fn kind_of(arg: &str) -> TokenKind {
+    if arg.starts_with("--") {
+        LongFlag
+    } else if arg.len() < 2 || !arg.starts_with("-") || is_negative_number(arg) {
+        Positional
+    } else if arg.len() == 2 || arg.as_bytes()[2] == '=' as u8 {
+        ShortFlag
+    } else {
+        Cluster
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Token, tokenize, LongFlag, ShortFlag, Cluster, Positional, Separator};
+    use decode::harness::owned;
+
+    fn kinds(args: &[&str]) -> Vec<(String, uint, super::TokenKind)> {
+        tokenize(owned(args).as_slice(), true).move_iter().map(|t| (t.raw, t.index, t.kind)).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(kinds(&["--a=b", "-xyz", "-", "@file", "-v", "-n=3", "-5", "-1.5", "build"]), vec!(
+            ("--a=b".to_string(), 0, LongFlag),
+            ("-xyz".to_string(), 1, Cluster),
+            ("-".to_string(), 2, Positional),
+            ("@file".to_string(), 3, Positional),
+            ("-v".to_string(), 4, ShortFlag),
+            ("-n=3".to_string(), 5, ShortFlag),
+            ("-5".to_string(), 6, Positional),
+            ("-1.5".to_string(), 7, Positional),
+            ("build".to_string(), 8, Positional)
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_separator() {
+        assert_eq!(kinds(&["-v", "--", "--verbose", "--", "-x"]), vec!(
+            ("-v".to_string(), 0, ShortFlag),
+            ("--".to_string(), 1, Separator),
+            ("--verbose".to_string(), 2, Positional),
+            ("--".to_string(), 3, Positional),
+            ("-x".to_string(), 4, Positional)
+        ));
+
+        assert_eq!(kinds(&[]), vec!());
+
+        let args = vec!("--".to_string(), "-v".to_string());
+        let tokens: Vec<(String, super::TokenKind)> = tokenize(args.as_slice(), false).move_iter().map(|t| (t.raw, t.kind)).collect();
+        assert_eq!(tokens, vec!(("--".to_string(), Positional), ("-v".to_string(), ShortFlag)));
+    }
+
+    #[test]
+    fn test_is_flag() {
+        assert!(Token::new("--color", 0, LongFlag).is_flag());
+        assert!(Token::new("-vc", 0, Cluster).is_flag());
+        assert!(!Token::new("-", 0, Positional).is_flag());
+        assert!(!Token::new("--", 0, Separator).is_flag());
+    }
+}
@@ -1,34 +1,140 @@
+/*!
+Help text and flag descriptions generated from a flag struct
+*/
+
+use std::ascii::StrAsciiExt;
+use std::cmp;
+use std::collections::TreeMap;
 use std::default::Default;
 use serialize::Decoder;
+use serialize::json;
+use serialize::json::{Json, ToJson};
 
-use util::canonical_field_name;
-use {UsageParse, FlagConfig, FlagConfiguration, HammerError};
+use UsageParse;
+use config::{FlagConfig, FlagConfiguration, HelpTier, Common, Full, ShortFirst};
+use decode::{HammerError, ConfigError, checked_config};
+use text::finish_block;
+use util::{ValueKind, Unsigned, Signed, Float, Character, Switch, Text};
 
 #[deriving(PartialEq, Clone, Show)]
 struct FieldUsage {
+    name: String,
     canonical: String,
-    alias: Option<char>,
-    optional: bool
+    aliases: Vec<char>,
+    optional: bool,
+    takes_value: bool,
+    negatable: bool,
+    counted: bool,
+    choices: Vec<String>,
+    type_hint: Option<&'static str>,
+    kind: ValueKind,
+    help: Option<String>,
+    notes: Vec<String>
 }
 
 impl FieldUsage {
-    fn new(canonical: &str) -> FieldUsage {
-        FieldUsage { canonical: canonical.to_string(), alias: None, optional: false }
+    // a `negated` switch is listed as the flag that does something
+    fn new(name: &str, config: &FlagConfiguration) -> FieldUsage {
+        let canonical = if config.is_negated(name) { config.negated_name(name) } else { config.display_name(name) };
+
+        FieldUsage {
+            name: name.to_string(),
+            canonical: canonical,
+            aliases: vec!(),
+            optional: false,
+            takes_value: false,
+            negatable: false,
+            counted: false,
+            choices: vec!(),
+            type_hint: None,
+            kind: Text,
+            help: None,
+            notes: vec!()
+        }
     }
 
     fn alias(&mut self, alias: char) {
-        self.alias = Some(alias);
+        self.aliases.push(alias);
+    }
+
+    // the first short alias, used wherever a flag is shown only once
+    fn short(&self) -> Option<char> {
+        self.aliases.as_slice().head().map(|&a| a)
+    }
+
+    // every short alias before the long form, e.g. `-h, -?, `, padded to
+    // the width of `indent` so the long forms line up
+    fn shorthand(&self, indent: &str) -> String {
+        let shorts: Vec<String> = self.aliases.iter().map(|a| format!("-{}, ", a)).collect();
+        let mut shorthand = shorts.concat();
+        let width = shorthand.as_slice().char_len();
+
+        if width < indent.len() {
+            shorthand.push_str(indent.slice_from(width));
+        }
+
+        shorthand
     }
 
     fn optional(&mut self) {
         self.optional = true;
     }
+
+    fn takes_value(&mut self) {
+        self.takes_value = true;
+    }
+
+    // the long forms the flag can be given as: a switch that can be negated
+    // also as `--no-flag`, or as `--flag` when it's `negated`
+    fn spellings(&self, config: &FlagConfiguration) -> Vec<String> {
+        let mut spellings = vec!(self.canonical.clone());
+
+        if self.negatable {
+            let name = self.name.as_slice();
+            let other = if config.is_negated(name) { config.display_name(name) } else { config.negated_name(name) };
+            spellings.push(other);
+        }
+
+        spellings
+    }
+
+    // the placeholder shown after a flag that takes a value, the same in the
+    // synopsis and the list of flags: its choices, e.g. `<debug|info|warn>`,
+    // the kind of value, e.g. `<INT>`, or else its name, e.g. `<color>`
+    fn placeholder(&self) -> String {
+        match self.type_hint {
+            _ if !self.choices.is_empty() => format!("<{}>", self.choices.connect("|")),
+            Some(hint) => format!("<{}>", hint),
+            None => format!("<{}>", self.canonical.as_slice().slice_from(2))
+        }
+    }
+
+    // the form of the flag used in a synopsis, e.g. `-v` or `--count <INT>`
+    fn synopsis(&self) -> String {
+        let flag = self.short()
+            .map(|a| format!("-{}", a))
+            .unwrap_or(self.canonical.clone());
+
+        let flag = if self.takes_value {
+            format!("{} {}", flag, self.placeholder())
+        } else {
+            flag
+        };
+
+        if self.optional { format!("[{}]", flag) } else { flag }
+    }
 }
 
+#[doc(hidden)]
 pub struct UsageDecoder {
     config: FlagConfiguration,
     current_field: Option<FieldUsage>,
     fields: Vec<FieldUsage>,
+    names: Vec<String>,
+    paths: Vec<String>,
+    flattened: Vec<String>,
+    has_assignments: bool,
+    has_rest: bool
 }
 
 struct SwallowUsage;
@@ -38,11 +144,19 @@ hammer_config!(SwallowUsage)
 impl UsageDecoder {
     pub fn new<T: FlagConfig>(dummy: Option<T>) -> UsageDecoder {
         let flag_config = FlagConfiguration::new();
+        UsageDecoder::with_config(FlagConfig::config(dummy, flag_config))
+    }
 
+    fn with_config(config: FlagConfiguration) -> UsageDecoder {
         UsageDecoder {
-            config: FlagConfig::config(dummy, flag_config),
+            config: config,
             current_field: None,
-            fields: vec!()
+            fields: vec!(),
+            names: vec!(),
+            paths: vec!(),
+            flattened: vec!(),
+            has_assignments: false,
+            has_rest: false
         }
     }
 
@@ -54,7 +168,72 @@ impl UsageDecoder {
     }
 
     fn field(&mut self) {
-        self.fields.push(self.current_field.take_unwrap())
+        let mut field = self.current_field.take_unwrap();
+
+        // up to here, `optional` only says whether the type is optional
+        field.optional = !self.config.is_required(field.name.as_slice(), field.optional);
+        field.help = self.config.help_for(field.name.as_slice());
+
+        for (name, other, reason) in self.config.ignore_rules().move_iter() {
+            if name == field.name {
+                field.notes.push(format!("ignored when {} is given ({})", self.config.display_name(other.as_slice()), reason));
+            }
+        }
+
+        let file_flag = self.config.values_file_for(field.name.as_slice());
+        let canonical = field.canonical.clone();
+
+        self.fields.push(field);
+
+        // the flag added by `values_from_file` is listed right after its list
+        match file_flag {
+            Some(flag) => {
+                let mut from = FieldUsage::new(flag.as_slice(), &self.config);
+                from.optional();
+                from.takes_value();
+                from.notes.push(format!("reads {} values from a file, one per line", canonical));
+                self.name(flag);
+                self.fields.push(from);
+            },
+            None => ()
+        }
+    }
+
+    // a field's name, and its path through the flattened structs it's in,
+    // e.g. `common.verbose`
+    fn name(&mut self, name: String) {
+        let mut path = self.flattened.clone();
+        path.push(name.clone());
+
+        self.paths.push(path.connect("."));
+        self.names.push(name);
+    }
+
+    // the first read decides, since `read_int` goes on to `read_uint`
+    fn kind(&mut self, kind: ValueKind) {
+        match self.current_field {
+            Some(ref mut f) if f.kind == Text => f.kind = kind,
+            _ => ()
+        }
+    }
+
+    fn value_field(&mut self) {
+        match self.current_field {
+            Some(ref mut f) => f.takes_value(),
+            None => fail!("No current field")
+        }
+
+        self.field()
+    }
+
+    // a flag listed with the kind of value it takes, such as `INT`
+    fn typed_field(&mut self, hint: &'static str) {
+        match self.current_field {
+            Some(ref mut f) => f.type_hint = Some(hint),
+            None => fail!("No current field")
+        }
+
+        self.value_field()
     }
 }
 
@@ -68,7 +247,18 @@ impl Decoder<HammerError> for UsageDecoder {
     fn read_nil(&mut self) -> UsageResult<()> { unimplemented!() }
 
     fn read_uint(&mut self) -> UsageResult<uint> {
-        self.field();
+        let counted = self.current_field.as_ref().map(|f| self.config.is_counted(f.name.as_slice())).unwrap_or(false);
+        self.kind(Unsigned);
+
+        // a counted flag is given like a switch
+        if counted {
+            self.optional();
+            self.current_field.get_mut_ref().counted = true;
+            self.field();
+        } else {
+            self.typed_field("INT");
+        }
+
         default()
     }
 
@@ -77,32 +267,47 @@ impl Decoder<HammerError> for UsageDecoder {
     fn read_u32(&mut self) -> UsageResult<u32> { self.read_uint().map(|v| v as u32) }
     fn read_u16(&mut self) -> UsageResult<u16> { self.read_uint().map(|v| v as u16) }
     fn read_u8(&mut self) -> UsageResult<u8>   { self.read_uint().map(|v| v as u8)  }
-    fn read_int(&mut self) -> UsageResult<int> { self.read_uint().map(|v| v as int) }
-    fn read_i64(&mut self) -> UsageResult<i64> { self.read_uint().map(|v| v as i64) }
-    fn read_i32(&mut self) -> UsageResult<i32> { self.read_uint().map(|v| v as i32) }
-    fn read_i16(&mut self) -> UsageResult<i16> { self.read_uint().map(|v| v as i16) }
-    fn read_i8(&mut self) -> UsageResult<i8>   { self.read_uint().map(|v| v as i8)  }
+    fn read_int(&mut self) -> UsageResult<int> {
+        self.kind(Signed);
+        self.read_uint().map(|v| v as int)
+    }
+
+    fn read_i64(&mut self) -> UsageResult<i64> { self.read_int().map(|v| v as i64) }
+    fn read_i32(&mut self) -> UsageResult<i32> { self.read_int().map(|v| v as i32) }
+    fn read_i16(&mut self) -> UsageResult<i16> { self.read_int().map(|v| v as i16) }
+    fn read_i8(&mut self) -> UsageResult<i8>   { self.read_int().map(|v| v as i8)  }
 
     fn read_bool(&mut self) -> UsageResult<bool> {
+        self.kind(Switch);
         self.optional();
+        self.current_field.get_mut_ref().negatable = self.config.accepted_syntax().negation;
         self.field();
         default()
     }
 
     fn read_f64(&mut self) -> UsageResult<f64> {
-        self.field();
+        self.kind(Float);
+        self.typed_field("FLOAT");
         default()
     }
 
     fn read_f32(&mut self) -> UsageResult<f32> { self.read_f64().map(|v| v as f32) }
 
     fn read_char(&mut self) -> UsageResult<char> {
-        self.field();
+        self.kind(Character);
+        self.value_field();
         default()
     }
 
     fn read_str(&mut self) -> UsageResult<String> {
-        self.field();
+        let path = self.current_field.as_ref().map(|f| self.config.is_path(f.name.as_slice())).unwrap_or(false);
+
+        if path {
+            self.typed_field("PATH");
+        } else {
+            self.value_field();
+        }
+
         default()
     }
 
@@ -113,16 +318,27 @@ impl Decoder<HammerError> for UsageDecoder {
 
     #[allow(unused_variable)]
     fn read_struct_field<T>(&mut self, f_name: &str, f_idx: uint, f: |&mut UsageDecoder| -> UsageResult<T>) -> UsageResult<T> {
-        let name = canonical_field_name(f_name);
-        let mut field = FieldUsage::new(name.as_slice());
+        self.name(f_name.to_string());
+
+        // a flattened field isn't a flag itself; its fields are
+        if self.config.is_flattened(f_name) {
+            self.current_field = None;
+            self.flattened.push(f_name.to_string());
+            let result = f(self);
+            self.flattened.pop();
+            return result;
+        }
 
-        self.config.short_for(f_name).map(|short| {
+        let mut field = FieldUsage::new(f_name, &self.config);
+
+        for &short in self.config.shorts_for(f_name).iter() {
             field.alias(short);
-        });
+        }
 
         self.current_field = Some(field);
 
-        if f_name == "rest" {
+        if f_name == self.config.rest_field_name().as_slice() {
+            self.has_rest = true;
             f(&mut UsageDecoder::new(None::<SwallowUsage>))
         } else {
             f(self)
@@ -137,11 +353,35 @@ impl Decoder<HammerError> for UsageDecoder {
     // the rest of these are pretty weird or hard to implement.
 
     #[allow(unused_variable)]
-    fn read_enum<T>(&mut self, name: &str, f: |&mut UsageDecoder| -> UsageResult<T>) -> UsageResult<T> { unimplemented!() }
-    #[allow(unused_variable)]
-    fn read_enum_variant<T>(&mut self, names: &[&str], f: |&mut UsageDecoder, uint| -> UsageResult<T>) -> UsageResult<T> { unimplemented!() }
+    fn read_enum<T>(&mut self, name: &str, f: |&mut UsageDecoder| -> UsageResult<T>) -> UsageResult<T> {
+        f(self)
+    }
+
+    // an enum field is a choice between the names of its variants
+    fn read_enum_variant<T>(&mut self, names: &[&str], f: |&mut UsageDecoder, uint| -> UsageResult<T>) -> UsageResult<T> {
+        match self.current_field {
+            Some(ref mut field) => {
+                field.choices = names.iter().map(|name| name.to_ascii_lower()).collect();
+                field.notes.push(format!("one of {}", field.choices.connect(", ")));
+            },
+            None => fail!("No current field")
+        }
+
+        self.value_field();
+        f(self, 0)
+    }
+
+    // only the names of variants can be given on the command line
     #[allow(unused_variable)]
-    fn read_enum_variant_arg<T>(&mut self, a_idx: uint, f: |&mut UsageDecoder| -> UsageResult<T>) -> UsageResult<T> { unimplemented!() }
+    fn read_enum_variant_arg<T>(&mut self, a_idx: uint, f: |&mut UsageDecoder| -> UsageResult<T>) -> UsageResult<T> {
+        let flag = match self.current_field {
+            Some(ref field) => field.canonical.clone(),
+            None => fail!("No current field")
+        };
+
+        let message = format!("{} is an enum with fields; only enums of plain variants can be flags", flag);
+        Err(HammerError::early(ConfigError, message))
+    }
     #[allow(unused_variable)]
     fn read_enum_struct_variant<T>(&mut self, names: &[&str], f: |&mut UsageDecoder, uint| -> UsageResult<T>) -> UsageResult<T> { unimplemented!() }
     #[allow(unused_variable)]
@@ -158,6 +398,12 @@ impl Decoder<HammerError> for UsageDecoder {
 
     #[allow(unused_variable)]
     fn read_seq<T>(&mut self, f: |&mut UsageDecoder, uint| -> UsageResult<T>) -> UsageResult<T> {
+        // lists other than the rest field are repeated, optional flags
+        if self.current_field.is_some() {
+            self.optional();
+            self.value_field();
+        }
+
         f(self, 0)
     }
 
@@ -166,8 +412,13 @@ impl Decoder<HammerError> for UsageDecoder {
         unimplemented!()
     }
 
-    #[allow(unused_variable)]
-    fn read_map<T>(&mut self, f: |&mut UsageDecoder, uint| -> UsageResult<T>) -> UsageResult<T> { unimplemented!() }
+    // the `leading_assignments` field isn't a flag; it only shows up in the synopsis
+    fn read_map<T>(&mut self, f: |&mut UsageDecoder, uint| -> UsageResult<T>) -> UsageResult<T> {
+        self.current_field = None;
+        self.has_assignments = true;
+        f(self, 0)
+    }
+
     #[allow(unused_variable)]
     fn read_map_elt_key<T>(&mut self, idx: uint, f: |&mut UsageDecoder| -> UsageResult<T>) -> UsageResult<T> { unimplemented!() }
     #[allow(unused_variable)]
@@ -183,29 +434,457 @@ hammer_config!(MyOpts)
 
 let (desc, usage_text) = usage::<MyOpts>(true);
 ```
+
+This is the `--help` output: flags in the `Expert` tier and hidden flags
+are left out. If the struct's `hammer_config!` fails, the flags are listed
+as if it weren't there.
 */
 
 pub fn usage<T: UsageParse>(force_indent: bool) -> (Option<String>, String) {
-    let mut decoder: UsageDecoder = UsageDecoder::new(None::<T>);
-    let _: Result<T, HammerError> = UsageParse::decode_usage(&mut decoder);
+    usage_for::<T>(Full, force_indent)
+}
+
+/** Get the description and usage for an option structure, listing the
+flags up to and including `tier`
+
+`Common` gives the short help shown by `-h`, and `Expert` every flag that
+isn't hidden.
 
-    let fields = decoder.fields;
-    let desc = decoder.config.description();
-    let options = print_usage(fields.as_slice(), force_indent);
+```
+let (desc, usage_text) = usage_for::<MyOpts>(Expert, false);
+```
+*/
 
-    (desc, options)
+pub fn usage_for<T: UsageParse>(tier: HelpTier, force_indent: bool) -> (Option<String>, String) {
+    // help is still worth showing when the configuration is broken
+    usage_with::<T>(checked_config::<T>().unwrap_or(FlagConfiguration::new()), tier, force_indent)
 }
 
-fn print_usage(fields: &[FieldUsage], force_indent: bool) -> String {
-    let mut out = String::new();
-    let shorthands = fields.iter().any(|f| f.alias.is_some());
+// `usage_for` with a configuration that's already been built, such as the
+// one a `ParsePlan` decodes with
+#[doc(hidden)]
+pub fn usage_with<T: UsageParse>(config: FlagConfiguration, tier: HelpTier, force_indent: bool) -> (Option<String>, String) {
+    let decoder = collect_usage_with::<T>(config);
+    let config = &decoder.config;
+
+    let mut fields: Vec<FieldUsage> = decoder.fields.iter().filter(|f| {
+        let name = f.name.as_slice();
+        !config.is_hidden(name) && config.help_tier_for(name) <= tier
+    }).map(|f| {
+        // the short help leaves the notes out
+        let mut f = f.clone();
+        if tier == Common { f.notes.clear() }
+        f
+    }).collect();
+
+    if config.usage_order() == ShortFirst {
+        fields.sort_by(|a, b| {
+            (a.short().is_none(), a.short(), a.canonical.as_slice()).cmp(&(b.short().is_none(), b.short(), b.canonical.as_slice()))
+        });
+    }
+
+    let desc = config.description().map(|desc| finish_block(desc.as_slice()));
 
-    let indent = if force_indent || shorthands {
-        "    "
+    let options = if decoder.config.uses_starred_usage() {
+        print_starred_usage(fields.as_slice(), force_indent)
     } else {
-        ""
+        print_usage(fields.as_slice(), force_indent)
     };
 
+    (desc, finish_block(options.as_slice()))
+}
+
+/** Get the usage entry of a single flag, as `--help` shows it, with its help
+text and notes
+
+`name` is a field name, a flag like `--line-count`, or a short alias like
+`-v`. Hidden and `Expert` flags are found too; an unknown name gives `None`.
+
+```
+let entry = flag_usage::<MyOpts>("--verbose");
+// Some("-v, [--verbose]\n        print more\n")
+```
+*/
+
+pub fn flag_usage<T: UsageParse>(name: &str) -> Option<String> {
+    let config = checked_config::<T>().unwrap_or(FlagConfiguration::new());
+    let decoder = collect_usage_with::<T>(config);
+    let config = &decoder.config;
+
+    decoder.fields.iter().find(|f| {
+        f.name.as_slice() == name ||
+            f.canonical.as_slice() == name ||
+            config.display_name(f.name.as_slice()).as_slice() == name ||
+            f.aliases.iter().any(|a| format!("-{}", a).as_slice() == name)
+    }).map(|f| {
+        let entry = if config.uses_starred_usage() {
+            print_starred_usage(&[f.clone()], false)
+        } else {
+            print_usage(&[f.clone()], false)
+        };
+
+        finish_block(entry.as_slice())
+    })
+}
+
+/** Get a compact, single-line synopsis for an option structure
+
+Required flags are listed first, followed by the optional ones in brackets
+and finally the rest field, if there is one. An empty `program` is replaced
+by the name configured with `program_name`.
+
+```
+let line = synopsis::<MyOpts>("mytool");
+// mytool --count <INT> [-v] [--color <color>] [rest...]
+```
+*/
+
+pub fn synopsis<T: UsageParse>(program: &str) -> String {
+    print_synopsis(&collect_usage::<T>(), program)
+}
+
+/** Get the complete usage message: the synopsis, the description and the
+flags shown by `--help`
+
+`program` overrides the name configured with `program_name`; without
+either, the synopsis starts with the flags.
+
+```
+let text = full_usage::<MyOpts>(None);
+// Usage: mytool --count <INT> [-v]
+//
+// Does things
+//
+//     --count <INT>
+// -v, [--verbose]
+```
+*/
+
+pub fn full_usage<T: UsageParse>(program: Option<&str>) -> String {
+    let decoder = collect_usage::<T>();
+    let mut out = format!("Usage: {}\n", print_synopsis(&decoder, program.unwrap_or("")));
+
+    match usage::<T>(false) {
+        (Some(desc), options) => out.push_str(format!("\n{}\n\n{}", desc, options).as_slice()),
+        (None, options) => out.push_str(format!("\n{}", options).as_slice())
+    }
+
+    finish_block(out.as_slice())
+}
+
+fn print_synopsis(decoder: &UsageDecoder, program: &str) -> String {
+    let config = &decoder.config;
+
+    let program = if program.is_empty() { config.program().unwrap_or(String::new()) } else { program.to_string() };
+    let mut parts = if program.is_empty() { vec!() } else { vec!(program) };
+
+    if decoder.has_assignments {
+        parts.push("[NAME=VALUE...]".to_string());
+    }
+
+    let shown = decoder.fields.iter().filter(|f| !config.is_hidden(f.name.as_slice())).map(|f| f.clone());
+    let (optional, mandatory) = shown.collect::<Vec<FieldUsage>>().partition(|f| f.optional);
+
+    for field in mandatory.iter().chain(optional.iter()) {
+        parts.push(field.synopsis());
+    }
+
+    if decoder.has_rest {
+        parts.push(format!("[{}...]", decoder.config.rest_field_name()));
+    }
+
+    parts.connect(" ")
+}
+
+/// A description of a single flag, for callers that render their own help
+#[deriving(PartialEq, Clone, Show)]
+pub struct FlagSpec {
+    pub field: String,
+    pub flag: String,
+    /// the first short alias, if there are several
+    pub short: Option<char>,
+    /// every short alias, in the order they were configured
+    pub shorts: Vec<char>,
+    pub takes_value: bool,
+    /// whether `--no-flag` is accepted too, or `--flag` for a `negated` one
+    pub negatable: bool,
+    /// whether the flag is `counted`, given any number of times without a value
+    pub counted: bool,
+    pub required: bool,
+    pub default: Option<String>,
+    pub tier: HelpTier,
+    pub hidden: bool,
+    pub help: Option<String>
+}
+
+/** Describe every flag of an option structure, in declaration order
+
+```
+let specs = describe::<MyOpts>();
+```
+*/
+
+pub fn describe<T: UsageParse>() -> Vec<FlagSpec> {
+    let decoder = collect_usage::<T>();
+    let config = &decoder.config;
+
+    decoder.fields.iter().map(|f| {
+        FlagSpec {
+            field: f.name.clone(),
+            flag: f.canonical.clone(),
+            short: f.short(),
+            shorts: f.aliases.clone(),
+            takes_value: f.takes_value,
+            negatable: f.negatable,
+            counted: f.counted,
+            required: !f.optional,
+            default: config.default_for(f.name.as_slice()),
+            tier: config.help_tier_for(f.name.as_slice()),
+            hidden: config.is_hidden(f.name.as_slice()),
+            help: f.help.clone()
+        }
+    }).collect()
+}
+
+// a flag as an object in `schema_json`
+impl ToJson for FlagSpec {
+    fn to_json(&self) -> Json {
+        let mut obj = TreeMap::new();
+        obj.insert("field".to_string(), self.field.to_json());
+        obj.insert("flag".to_string(), self.flag.to_json());
+        obj.insert("short".to_string(), self.short.map(|c| format!("-{}", c)).to_json());
+        obj.insert("shorts".to_string(), self.shorts.iter().map(|c| format!("-{}", c)).collect::<Vec<String>>().to_json());
+        obj.insert("takes_value".to_string(), self.takes_value.to_json());
+        obj.insert("negatable".to_string(), self.negatable.to_json());
+        obj.insert("counted".to_string(), self.counted.to_json());
+        obj.insert("required".to_string(), self.required.to_json());
+        obj.insert("default".to_string(), self.default.to_json());
+        obj.insert("tier".to_string(), format!("{}", self.tier).as_slice().to_ascii_lower().to_json());
+        obj.insert("help".to_string(), self.help.to_json());
+        json::Object(obj)
+    }
+}
+
+/**
+A command of a program with subcommands, for `schema_json`
+
+Hammer doesn't dispatch subcommands itself; each command's flags are
+usually a struct of their own, decoded from the arguments after the
+command's name.
+
+```
+let remote = CommandSpec::new::<RemoteOpts>("remote").alias("r")
+    .command(CommandSpec::new::<AddOpts>("add"));
+```
+*/
+#[deriving(PartialEq, Clone, Show)]
+pub struct CommandSpec {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub flags: Vec<FlagSpec>,
+    pub commands: Vec<CommandSpec>
+}
+
+impl CommandSpec {
+    /// The command `name`, with the description and flags of `T`
+    pub fn new<T: UsageParse>(name: &str) -> CommandSpec {
+        CommandSpec {
+            name: name.to_string(),
+            aliases: vec!(),
+            description: checked_config::<T>().ok().and_then(|config| config.description()),
+            flags: describe::<T>(),
+            commands: vec!()
+        }
+    }
+
+    /// Another name the command can be given by
+    pub fn alias(mut self, alias: &str) -> CommandSpec {
+        self.aliases.push(alias.to_string());
+        self
+    }
+
+    /// A command nested under this one
+    pub fn command(mut self, command: CommandSpec) -> CommandSpec {
+        self.commands.push(command);
+        self
+    }
+}
+
+// a command as an object in `schema_json`
+impl ToJson for CommandSpec {
+    fn to_json(&self) -> Json {
+        let mut obj = TreeMap::new();
+        obj.insert("name".to_string(), self.name.to_json());
+        obj.insert("aliases".to_string(), self.aliases.to_json());
+        obj.insert("description".to_string(), self.description.to_json());
+        obj.insert("flags".to_string(), shown_flags(self.flags.as_slice()));
+        obj.insert("commands".to_string(), self.commands.to_json());
+        json::Object(obj)
+    }
+}
+
+// the flags that aren't hidden
+fn shown_flags(flags: &[FlagSpec]) -> Json {
+    flags.iter().filter(|f| !f.hidden).map(|f| f.clone()).collect::<Vec<FlagSpec>>().to_json()
+}
+
+/** Describe a whole program as JSON: the program's name and description, the
+global flags of `T`, and `commands` with their own flags and subcommands
+
+Every flag is an object with its `field`, `flag`, `short`, `takes_value`,
+`negatable`, `counted`, `required`, `default`, `tier` and `help`; hidden
+flags are left out. Every command has a `name`, `aliases`, `description`,
+`flags` and `commands`.
+
+```
+let schema = schema_json::<GlobalOpts>(&[
+    CommandSpec::new::<BuildOpts>("build").alias("b"),
+    CommandSpec::new::<RemoteOpts>("remote").command(CommandSpec::new::<AddOpts>("add"))
+]);
+```
+*/
+
+pub fn schema_json<T: UsageParse>(commands: &[CommandSpec]) -> String {
+    let config = checked_config::<T>().unwrap_or(FlagConfiguration::new());
+
+    let mut obj = TreeMap::new();
+    obj.insert("program".to_string(), config.program().to_json());
+    obj.insert("description".to_string(), config.description().to_json());
+    obj.insert("flags".to_string(), shown_flags(describe::<T>().as_slice()));
+    obj.insert("commands".to_string(), Vec::from_slice(commands).to_json());
+
+    json::Object(obj).to_pretty_str()
+}
+
+/** Suggest completions for the last of `words`, a partly typed command
+line without the program name
+
+After a flag that takes a value, the suggestions come from the flag's
+`complete_with` completer, if it has one; a word starting with `-` is
+completed to the flags that aren't hidden, including the `--no-` forms of
+switches. A counted flag takes no value, so nothing follows it.
+`decode_args` answers `--hammer-complete WORDS...` with these, one per
+line, as a `CompletionRequested` error, when the program asks for that
+with `shell_completion`.
+
+```
+let words = completions::<MyOpts>(&["--device".to_string(), "sd".to_string()]);
+// sda, sdb
+```
+*/
+
+pub fn completions<T: UsageParse>(words: &[String]) -> Vec<String> {
+    completions_with::<T>(checked_config::<T>().unwrap_or(FlagConfiguration::new()), words)
+}
+
+// `completions` with a configuration that's already been built
+#[doc(hidden)]
+pub fn completions_with<T: UsageParse>(config: FlagConfiguration, words: &[String]) -> Vec<String> {
+    let decoder = collect_usage_with::<T>(config);
+    let config = &decoder.config;
+
+    let current = words.last().map(|w| w.as_slice()).unwrap_or("");
+    let previous = if words.len() > 1 { Some(words[words.len() - 2].as_slice()) } else { None };
+
+    let value_flag = previous.and_then(|previous| decoder.fields.iter().find(|f| {
+        f.takes_value && (f.canonical.as_slice() == previous || f.aliases.iter().any(|a| format!("-{}", a).as_slice() == previous))
+    }));
+
+    match value_flag {
+        Some(field) => {
+            let candidates = match config.completer_for(field.name.as_slice()) {
+                Some(completer) => completer(current),
+                None => vec!()
+            };
+
+            candidates.move_iter().filter(|c| c.as_slice().starts_with(current)).collect()
+        },
+        None if current.starts_with("-") => {
+            decoder.fields.iter()
+                .filter(|f| !config.is_hidden(f.name.as_slice()))
+                .flat_map(|f| f.spellings(config).move_iter())
+                .filter(|flag| flag.as_slice().starts_with(current))
+                .collect()
+        },
+        None => vec!()
+    }
+}
+
+// the canonical names of every flag accepted by an option structure,
+// including both forms of `negated` switches
+#[doc(hidden)]
+pub fn flag_names<T: UsageParse>() -> Vec<String> {
+    flags_of(&collect_usage::<T>())
+}
+
+// the short aliases of the flags that take a value
+#[doc(hidden)]
+pub fn value_shorts<T: UsageParse>() -> Vec<char> {
+    value_shorts_of(&collect_usage::<T>())
+}
+
+// `field_names`, the path of each of those fields through flattened structs,
+// `flag_names`, `value_shorts` and what each flag converts its value to
+// under `config`, from a single walk of `T`
+#[doc(hidden)]
+pub fn struct_layout<T: UsageParse>(config: FlagConfiguration) -> (Vec<String>, Vec<String>, Vec<String>, Vec<char>, Vec<(String, ValueKind)>) {
+    let decoder = collect_usage_with::<T>(config);
+    let kinds = decoder.fields.iter().map(|f| (f.name.clone(), f.kind)).collect();
+    (decoder.names.clone(), decoder.paths.clone(), flags_of(&decoder), value_shorts_of(&decoder), kinds)
+}
+
+fn flags_of(decoder: &UsageDecoder) -> Vec<String> {
+    let mut names = vec!();
+
+    for field in decoder.fields.iter() {
+        if decoder.config.is_negated(field.name.as_slice()) {
+            names.push(decoder.config.display_name(field.name.as_slice()));
+        }
+
+        names.push(field.canonical.clone());
+    }
+
+    names
+}
+
+fn value_shorts_of(decoder: &UsageDecoder) -> Vec<char> {
+    decoder.fields.iter().filter(|f| f.takes_value).flat_map(|f| f.aliases.iter().map(|&a| a)).collect()
+}
+
+// the name of every field of an option structure, including flattened
+// structs and the rest field, and of the flags added by `values_from_file`
+#[doc(hidden)]
+pub fn field_names<T: UsageParse>() -> Vec<String> {
+    collect_usage::<T>().names
+}
+
+// a broken configuration is left out rather than taking the caller down;
+// decoding reports it
+fn collect_usage<T: UsageParse>() -> UsageDecoder {
+    collect_usage_with::<T>(checked_config::<T>().unwrap_or(FlagConfiguration::new()))
+}
+
+fn collect_usage_with<T: UsageParse>(config: FlagConfiguration) -> UsageDecoder {
+    let mut decoder = UsageDecoder::with_config(config);
+    let _: Result<T, HammerError> = UsageParse::decode_usage(&mut decoder);
+    decoder
+}
+
+// the indent that lines up the long forms: as wide as the widest short
+// aliases, and at least four spaces when asked for
+fn shorthand_indent(fields: &[FieldUsage], force_indent: bool) -> String {
+    let widest = fields.iter().map(|f| f.shorthand("").as_slice().char_len()).max().unwrap_or(0);
+    let width = if force_indent { cmp::max(widest, 4) } else { widest };
+
+    String::from_char(width, ' ')
+}
+
+fn print_usage(fields: &[FieldUsage], force_indent: bool) -> String {
+    let mut out = String::new();
+    let indent = shorthand_indent(fields, force_indent);
+    let indent = indent.as_slice();
+
     let (optional, mandatory) = Vec::from_slice(fields).partition(|f| f.optional);
 
     out.push_str(print_fields(mandatory.as_slice(), indent, |f| f.to_string()).as_slice());
@@ -214,17 +893,57 @@ fn print_usage(fields: &[FieldUsage], force_indent: bool) -> String {
     out
 }
 
+// every field in declaration order, required ones marked with `*` and
+// flags that take a value followed by their placeholder
+fn print_starred_usage(fields: &[FieldUsage], force_indent: bool) -> String {
+    let mut out = String::new();
+    let indent = shorthand_indent(fields, force_indent);
+    let indent = indent.as_slice();
+
+    for field in fields.iter() {
+        let marker = if field.optional { "  " } else { "* " };
+
+        let shorthand = field.shorthand(indent);
+
+        let value = if field.takes_value { format!(" {}", field.placeholder()) } else { String::new() };
+
+        out.push_str(format!("{}{}{}{}\n", marker, shorthand, field.canonical, value).as_slice());
+        out.push_str(print_notes(field, format!("  {}", indent).as_slice()).as_slice());
+    }
+
+    out
+}
+
 fn print_fields(fields: &[FieldUsage], indent: &str, format: |&str| -> String) -> String {
     let mut out = String::new();
 
     for field in fields.iter() {
-        let shorthand = field.alias
-            .map(|a| format!("-{}, ", a))
-            .unwrap_or(indent.to_string());
+        let shorthand = field.shorthand(indent);
 
-        let longhand = format(field.canonical.as_slice());
+        let longhand = if field.takes_value {
+            format(format!("{} {}", field.canonical, field.placeholder()).as_slice())
+        } else {
+            format(field.canonical.as_slice())
+        };
 
         out.push_str(format!("{}{}\n", shorthand, longhand).as_slice());
+        out.push_str(print_notes(field, indent).as_slice());
+    }
+
+    out
+}
+
+// help text and notes go on their own lines, indented under the flag
+fn print_notes(field: &FieldUsage, indent: &str) -> String {
+    let mut out = String::new();
+
+    match field.help {
+        Some(ref help) => out.push_str(format!("{}    {}\n", indent, help).as_slice()),
+        None => ()
+    }
+
+    for note in field.notes.iter() {
+        out.push_str(format!("{}    note: {}\n", indent, note).as_slice());
     }
 
     out
@@ -232,7 +951,12 @@ fn print_fields(fields: &[FieldUsage], indent: &str, format: |&str| -> String) -
 
 #[cfg(test)]
 mod tests {
-    use super::usage;
+    use super::{usage, usage_for, flag_usage, synopsis, full_usage, describe, completions, schema_json, CommandSpec, UsageDecoder};
+    use UsageParse;
+    use config::{Common, Full, Expert, ShortFirst};
+    use decode::{decode_args, HammerError, ConfigError};
+    use decode::harness::owned;
+    use serialize::json;
 
     #[allow(dead_code)]
     #[deriving(Decodable)]
@@ -260,13 +984,446 @@ mod tests {
 
     hammer_config!(NoShorthandOptions)
 
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct TieredOptions {
+        verbose: bool,
+        jobs: Option<uint>,
+        debug_gc: bool,
+        trace_internals: bool
+    }
+
+    hammer_config!(TieredOptions |c| {
+        c.help_tier("jobs", Full)
+         .help_tier("debug_gc", Expert)
+         .help_tier("trace_internals", Expert)
+         .hidden("trace_internals")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct StarredOptions {
+        color: Option<String>,
+        line_count: uint,
+        verbose: bool,
+        temp: f64,
+        rest: Vec<String>
+    }
+
+    hammer_config!(StarredOptions |c| {
+        c.short("verbose", 'v').starred_usage()
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct RenamedRestOptions {
+        verbose: bool,
+        files: Vec<String>
+    }
+
+    hammer_config!(RenamedRestOptions |c| {
+        c.short("verbose", 'v').rest_field("files")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct OnlyFlags {
+        quiet: bool,
+        count: uint
+    }
+
+    hammer_config!(OnlyFlags)
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct SortedOptions {
+        zeta: bool,
+        verbose: bool,
+        name: String,
+        alpha: bool,
+        count: bool
+    }
+
+    hammer_config!(SortedOptions |c| {
+        c.short("verbose", 'v').short("count", 'c').sort_usage(ShortFirst)
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    enum Level {
+        Debug,
+        Info,
+        Warn
+    }
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct ChoiceOptions {
+        level: Level,
+        fallback: Option<Level>
+    }
+
+    hammer_config!(ChoiceOptions)
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    enum Target {
+        Remote(String),
+        Local
+    }
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct DeployOptions {
+        target: Target
+    }
+
+    hammer_config!(DeployOptions)
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct DeviceOptions {
+        device: Option<String>,
+        verbose: bool,
+        debug_gc: bool
+    }
+
+    fn list_devices(_: &str) -> Vec<String> {
+        vec!("sda".to_string(), "sdb".to_string(), "nvme0".to_string())
+    }
+
+    hammer_config!(DeviceOptions |c| {
+        c.short("device", 'd').complete_with("device", list_devices).hidden("debug_gc")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct HelpOptions {
+        verbose: bool,
+        count: uint
+    }
+
+    hammer_config!(HelpOptions |c| {
+        c.flag("verbose", 'v', "enable verbose output").help("count", "how many to make")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct NegatableOptions {
+        color: bool,
+        verbose: uint,
+        dry_run: bool,
+        output: Option<String>
+    }
+
+    hammer_config!(NegatableOptions |c| { c.negated("color").counted("verbose").short("verbose", 'v') })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct ReportOptions {
+        output: Option<String>,
+        dry_run: bool
+    }
+
+    hammer_config!(ReportOptions |c| {
+        c.help("output", "where to write the report, which is a file of one line per finding, or standard output when it isn't given")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct ExportOptions {
+        output: Option<String>,
+        dry_run: bool
+    }
+
+    hammer_config!(ExportOptions |c| {
+        c.ignored_when("output", "dry_run", "output is not written in dry-run mode")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct NamedOptions {
+        count: uint,
+        verbose: bool,
+        files: Vec<String>
+    }
+
+    hammer_config!(NamedOptions "Counts things", |c| {
+        c.program_name("mytool").short("verbose", 'v').rest_field("files")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct GlobalOptions {
+        verbose: bool,
+        trace: bool
+    }
+
+    hammer_config!(GlobalOptions "Manages repositories", |c| {
+        c.program_name("repo").short("verbose", 'v').hidden("trace")
+    })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct CloneOptions {
+        depth: uint,
+        rest: Vec<String>
+    }
+
+    hammer_config!(CloneOptions "Copies a repository", |c| { c.default("depth", "1").help_tier("depth", Full) })
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct RemoteOptions {
+        rest: Vec<String>
+    }
+
+    hammer_config!(RemoteOptions)
+
+    #[allow(dead_code)]
+    #[deriving(Decodable)]
+    struct AddOptions {
+        url: String
+    }
+
+    hammer_config!(AddOptions "Adds a remote", |c| { c.help("url", "where the remote is") })
+
+    #[deriving(Decodable)]
+    struct QuietOptions {
+        quiet: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(QuietOptions |c| {
+        c.shorts("quiet", &['q', 's'])
+    })
+
+    #[deriving(Decodable)]
+    struct ChattyOptions {
+        quiet: bool,
+        verbose: bool,
+        color: bool
+    }
+
+    hammer_config!(ChattyOptions |c| {
+        c.shorts("quiet", &['q', 's']).short("verbose", 'v')
+    })
+
     #[test]
     fn test_mixed_usage() {
-        assert_eq!(usage::<MixedOptions>(false), (None, "    --line-count\n    --temp\n    [--color]\n-v, [--verbose]\n".to_string()))
+        assert_eq!(usage::<MixedOptions>(false), (None, "    --line-count <INT>\n    --temp <FLOAT>\n    [--color <color>]\n-v, [--verbose]\n".to_string()))
     }
 
     #[test]
     fn test_no_shorthand_usage() {
-        assert_eq!(usage::<NoShorthandOptions>(false), (None, "--line-count\n--temp\n[--color]\n[--verbose]\n".to_string()))
+        assert_eq!(usage::<NoShorthandOptions>(false), (None, "--line-count <INT>\n--temp <FLOAT>\n[--color <color>]\n[--verbose]\n".to_string()))
+    }
+
+    #[test]
+    fn test_starred_usage() {
+        assert_eq!(usage::<StarredOptions>(false), (None,
+            "      --color <color>\n*     --line-count <INT>\n  -v, --verbose\n*     --temp <FLOAT>\n".to_string()))
+    }
+
+    #[test]
+    fn test_help_tiers() {
+        assert_eq!(usage_for::<TieredOptions>(Common, false), (None, "[--verbose]\n".to_string()));
+        assert_eq!(usage_for::<TieredOptions>(Full, false), (None, "[--verbose]\n[--jobs <INT>]\n".to_string()));
+        assert_eq!(usage_for::<TieredOptions>(Expert, false), (None, "[--verbose]\n[--jobs <INT>]\n[--debug-gc]\n".to_string()));
+        assert_eq!(usage::<TieredOptions>(false), usage_for::<TieredOptions>(Full, false));
+
+        let tiers: Vec<(String, bool)> = describe::<TieredOptions>().move_iter().map(|s| (format!("{}", s.tier), s.hidden)).collect();
+        assert_eq!(tiers, vec!(
+            ("Common".to_string(), false),
+            ("Full".to_string(), false),
+            ("Expert".to_string(), false),
+            ("Expert".to_string(), true)
+        ));
+
+        assert_eq!(synopsis::<TieredOptions>("prog"), "prog [--verbose] [--jobs <INT>] [--debug-gc]".to_string());
+    }
+
+    #[test]
+    fn test_ignored_when_note() {
+        assert_eq!(usage::<ExportOptions>(false), (None,
+            "[--output <output>]\n    note: ignored when --dry-run is given (output is not written in dry-run mode)\n[--dry-run]\n".to_string()));
+        assert_eq!(usage_for::<ExportOptions>(Common, false), (None, "[--output <output>]\n[--dry-run]\n".to_string()));
+    }
+
+    #[test]
+    fn test_help_text() {
+        assert_eq!(usage::<HelpOptions>(false), (None,
+            "    --count <INT>\n        how many to make\n-v, [--verbose]\n        enable verbose output\n".to_string()));
+
+        let specs = describe::<HelpOptions>();
+        assert_eq!(specs[0].short, Some('v'));
+        assert_eq!(specs[0].help, Some("enable verbose output".to_string()));
+    }
+
+    #[test]
+    fn test_help_text_only_where_given() {
+        let long = "where to write the report, which is a file of one line per finding, or standard output when it isn't given";
+
+        assert_eq!(usage::<ReportOptions>(false), (None, format!("[--output <output>]\n    {}\n[--dry-run]\n", long)));
+        assert_eq!(flag_usage::<ReportOptions>("dry_run"), Some("[--dry-run]\n".to_string()));
+    }
+
+    #[test]
+    fn test_flag_usage() {
+        let verbose = Some("-v, [--verbose]\n        enable verbose output\n".to_string());
+
+        assert_eq!(flag_usage::<HelpOptions>("verbose"), verbose);
+        assert_eq!(flag_usage::<HelpOptions>("--verbose"), verbose);
+        assert_eq!(flag_usage::<HelpOptions>("-v"), verbose);
+        assert_eq!(flag_usage::<HelpOptions>("count"), Some("--count <INT>\n    how many to make\n".to_string()));
+        assert_eq!(flag_usage::<HelpOptions>("--verbosity"), None);
+        assert_eq!(flag_usage::<HelpOptions>("-c"), None);
+    }
+
+    #[test]
+    fn test_choices() {
+        assert_eq!(usage::<ChoiceOptions>(false), (None,
+            "--level <debug|info|warn>\n    note: one of debug, info, warn\n[--fallback <debug|info|warn>]\n    note: one of debug, info, warn\n".to_string()));
+        assert_eq!(synopsis::<ChoiceOptions>("prog"), "prog --level <debug|info|warn> [--fallback <debug|info|warn>]".to_string());
+    }
+
+    #[test]
+    fn test_choices_with_fields() {
+        let mut decoder = UsageDecoder::new(None::<DeployOptions>);
+        let result: Result<DeployOptions, HammerError> = UsageParse::decode_usage(&mut decoder);
+        let err = result.err().unwrap();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "--target is an enum with fields; only enums of plain variants can be flags".to_string());
+    }
+
+    #[test]
+    fn test_completions() {
+        let complete = |words: &[&str]| completions::<DeviceOptions>(owned(words).as_slice());
+
+        assert_eq!(complete(&["--device", ""]), vec!("sda".to_string(), "sdb".to_string(), "nvme0".to_string()));
+        assert_eq!(complete(&["--verbose", "-d", "sd"]), vec!("sda".to_string(), "sdb".to_string()));
+        assert_eq!(complete(&["--device", "x"]), vec!());
+        assert_eq!(complete(&["--v"]), vec!("--verbose".to_string()));
+        assert_eq!(complete(&["-"]), vec!("--device".to_string(), "--verbose".to_string(), "--no-verbose".to_string()));
+        assert_eq!(complete(&["build"]), vec!());
+
+        let words = vec!("--color".to_string(), "".to_string());
+        assert_eq!(completions::<MixedOptions>(words.as_slice()), vec!());
+    }
+
+    #[test]
+    fn test_negated_and_counted_completions() {
+        let complete = |words: &[&str]| {
+            let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+            completions::<NegatableOptions>(words.as_slice())
+        };
+        assert_eq!(complete(&["--no"]), owned(&["--no-color", "--no-dry-run"]));
+        assert_eq!(complete(&["--col"]), owned(&["--color"]));
+        assert_eq!(complete(&["--verbose", ""]), vec!());
+        assert_eq!(complete(&["-v", "--"]), owned(&["--no-color", "--color", "--verbose", "--dry-run", "--no-dry-run", "--output"]));
+
+        let specs: Vec<(String, bool, bool)> = describe::<NegatableOptions>().move_iter()
+            .map(|s| (s.flag, s.negatable, s.counted)).collect();
+        assert_eq!(specs, vec!(
+            ("--no-color".to_string(), true, false),
+            ("--verbose".to_string(), false, true),
+            ("--dry-run".to_string(), true, false),
+            ("--output".to_string(), false, false)
+        ));
+    }
+
+    #[test]
+    fn test_short_first_usage() {
+        assert_eq!(usage::<SortedOptions>(false), (None,
+            "    --name <name>\n-c, [--count]\n-v, [--verbose]\n    [--alpha]\n    [--zeta]\n".to_string()));
+    }
+
+    #[test]
+    fn test_synopsis() {
+        assert_eq!(synopsis::<MixedOptions>("prog"),
+            "prog --line-count <INT> --temp <FLOAT> [--color <color>] [-v] [rest...]".to_string())
+    }
+
+    #[test]
+    fn test_full_usage() {
+        assert_eq!(full_usage::<NamedOptions>(None),
+            "Usage: mytool --count <INT> [-v] [files...]\n\nCounts things\n\n    --count <INT>\n-v, [--verbose]\n".to_string());
+        assert_eq!(synopsis::<NamedOptions>(""), "mytool --count <INT> [-v] [files...]".to_string());
+
+        assert_eq!(full_usage::<NamedOptions>(Some("other")).as_slice().lines().next(),
+            Some("Usage: other --count <INT> [-v] [files...]"));
+        assert_eq!(full_usage::<OnlyFlags>(None), "Usage: --count <INT> [--quiet]\n\n--count <INT>\n[--quiet]\n".to_string());
+    }
+
+    #[test]
+    fn test_synopsis_without_rest() {
+        assert_eq!(synopsis::<RenamedRestOptions>("prog"), "prog [-v] [files...]".to_string());
+        assert_eq!(synopsis::<OnlyFlags>("prog"), "prog --count <INT> [--quiet]".to_string())
+    }
+
+    #[test]
+    fn test_several_shorts() {
+        for arg in ["-q", "-s", "--quiet"].iter() {
+            let flags = decode_args::<QuietOptions>(&[arg.to_string(), "file".to_string()]).unwrap();
+            assert!(flags.quiet);
+            assert_eq!(flags.rest, vec!("file".to_string()));
+        }
+
+        assert_eq!(usage::<QuietOptions>(false), (None, "-q, -s, [--quiet]\n".to_string()));
+        assert_eq!(synopsis::<QuietOptions>("prog"), "prog [-q] [rest...]".to_string());
+
+        // the long forms line up under the widest shorthand
+        assert_eq!(usage::<ChattyOptions>(false), (None, "-q, -s, [--quiet]\n-v,     [--verbose]\n        [--color]\n".to_string()));
+        assert_eq!(describe::<ChattyOptions>()[0].shorts, vec!('q', 's'));
+    }
+
+    #[test]
+    fn test_schema_json() {
+        let schema = schema_json::<GlobalOptions>(&[
+            CommandSpec::new::<CloneOptions>("clone").alias("cl"),
+            CommandSpec::new::<RemoteOptions>("remote").command(CommandSpec::new::<AddOptions>("add"))
+        ]);
+
+        let expected = r#"{
+            "program": "repo",
+            "description": "Manages repositories",
+            "flags": [
+                {"field": "verbose", "flag": "--verbose", "short": "-v", "shorts": ["-v"], "takes_value": false, "negatable": true, "counted": false,
+                 "required": false, "default": null, "tier": "common", "help": null}
+            ],
+            "commands": [
+                {
+                    "name": "clone",
+                    "aliases": ["cl"],
+                    "description": "Copies a repository",
+                    "flags": [
+                        {"field": "depth", "flag": "--depth", "short": null, "shorts": [], "takes_value": true, "negatable": false, "counted": false,
+                         "required": false, "default": "1", "tier": "full", "help": null}
+                    ],
+                    "commands": []
+                },
+                {
+                    "name": "remote",
+                    "aliases": [],
+                    "description": null,
+                    "flags": [],
+                    "commands": [
+                        {
+                            "name": "add",
+                            "aliases": [],
+                            "description": "Adds a remote",
+                            "flags": [
+                                {"field": "url", "flag": "--url", "short": null, "shorts": [], "takes_value": true, "negatable": false, "counted": false,
+                                 "required": true, "default": null, "tier": "common", "help": "where the remote is"}
+                            ],
+                            "commands": []
+                        }
+                    ]
+                }
+            ]
+        }"#;
+
+        assert_eq!(json::from_str(schema.as_slice()), json::from_str(expected));
     }
 }
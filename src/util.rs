@@ -1,4 +1,344 @@
-pub fn canonical_field_name(field: &str) -> String {
-    format!("--{}", field.chars().map(|c|
-        if c == '_' {'-'} else {c}).collect::<String>())
+use std::ascii::StrAsciiExt;
+use std::collections::hashmap::HashMap;
+use std::io::{File, IoResult};
+use std::os;
+
+// the spellings of a switch's value, on and off
+pub static SWITCH_SPELLINGS: [(&'static str, &'static str), ..4] = [("true", "false"), ("yes", "no"), ("on", "off"), ("1", "0")];
+
+// reads a switch's value in any of its spellings, ignoring case
+pub fn parse_switch(value: &str) -> Option<bool> {
+    for &(on, off) in SWITCH_SPELLINGS.iter() {
+        if value.eq_ignore_ascii_case(on) { return Some(true) }
+        if value.eq_ignore_ascii_case(off) { return Some(false) }
+    }
+
+    None
+}
+
+// what a field converts its value to, so a value can be checked before
+// the field is read, as a default is
+#[deriving(PartialEq, Clone, Show)]
+pub enum ValueKind {
+    Unsigned,
+    Signed,
+    Float,
+    Character,
+    Switch,
+    Text
+}
+
+impl ValueKind {
+    // the value as it's named in errors, e.g. `an integer`
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Unsigned | Signed => "an integer",
+            Float => "a float",
+            Character => "a single character",
+            Switch => "a bool",
+            Text => "text"
+        }
+    }
+
+    pub fn converts(&self, value: &str) -> bool {
+        match *self {
+            Unsigned => from_str::<uint>(value).is_some(),
+            Signed => from_str::<int>(value).is_some(),
+            Float => from_str::<f64>(value).is_some(),
+            Character => value.char_len() == 1,
+            Switch => parse_switch(value).is_some(),
+            Text => true
+        }
+    }
+}
+
+// splits `--name=value` into `--name` and `value`; any other token is
+// returned whole, without a value
+pub fn split_flag<'a>(token: &'a str) -> (&'a str, Option<&'a str>) {
+    if !token.starts_with("--") {
+        return (token, None);
+    }
+
+    match token.find('=') {
+        Some(i) => (token.slice_to(i), Some(token.slice_from(i + 1))),
+        None => (token, None)
+    }
+}
+
+// `-5` and `-2.5`, which are values rather than short flags
+pub fn is_negative_number(token: &str) -> bool {
+    if !token.starts_with("-") { return false }
+
+    let number = token.slice_from(1);
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_digit());
+
+    match number.find('.') {
+        Some(i) => digits(number.slice_to(i)) && digits(number.slice_from(i + 1)),
+        None => digits(number)
+    }
+}
+
+// splits an environment-style `NAME=value` argument; the name must be a
+// valid variable name, so flags like `--a=b` are never assignments
+pub fn split_assignment<'a>(token: &'a str) -> Option<(&'a str, &'a str)> {
+    let i = match token.find('=') {
+        Some(i) if i > 0 => i,
+        _ => return None
+    };
+
+    let name = token.slice_to(i);
+    let valid = name.chars().enumerate().all(|(n, c)| c == '_' || c.is_alphabetic() || (n > 0 && c.is_digit()));
+
+    if valid { Some((name, token.slice_from(i + 1))) } else { None }
+}
+
+// reads `name = value` lines from a file; blank lines and `#` comments are
+// skipped, names may use dashes or underscores, and an unreadable file has
+// no values
+pub fn read_assignments(path: &str) -> HashMap<String, String> {
+    match File::open(&Path::new(path)).read_to_string() {
+        Ok(contents) => parse_assignments(contents.as_slice()),
+        Err(_) => HashMap::new()
+    }
+}
+
+pub fn parse_assignments(contents: &str) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+
+    for line in content_lines(contents).move_iter() {
+        match line.find('=') {
+            Some(i) => {
+                let name = line.slice_to(i).trim().replace("-", "_");
+                values.insert(name, line.slice_from(i + 1).trim().to_string());
+            },
+            None => ()
+        }
+    }
+
+    values
+}
+
+// reads one value per line from a file; blank lines and `#` comments are
+// skipped
+pub fn read_values(path: &str) -> IoResult<Vec<String>> {
+    let contents = try!(File::open(&Path::new(path)).read_to_string());
+    Ok(parse_values(contents.as_slice()))
+}
+
+pub fn parse_values(contents: &str) -> Vec<String> {
+    content_lines(contents).move_iter().map(|line| line.to_string()).collect()
+}
+
+// reads an argument file: each line is an argument just as it's written,
+// spaces, `#` and all; only a byte order mark at the start and the `\r` of
+// `\r\n` line endings are removed
+pub fn read_args(path: &str) -> IoResult<Vec<String>> {
+    let contents = try!(File::open(&Path::new(path)).read_to_string());
+    Ok(parse_args(contents.as_slice()))
+}
+
+pub fn parse_args(contents: &str) -> Vec<String> {
+    contents.trim_left_chars('\ufeff').lines_any().map(|line| line.to_string()).collect()
+}
+
+// what a line of a config, values or batch file says, if anything: files
+// saved on Windows may start with a byte order mark and end lines with
+// `\r\n`, so both are removed along with surrounding whitespace; blank lines
+// and `#` comments say nothing
+pub fn content_line<'a>(line: &'a str, first: bool) -> Option<&'a str> {
+    let line = if first { line.trim_left_chars('\ufeff') } else { line };
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with("#") { None } else { Some(line) }
+}
+
+fn content_lines<'a>(contents: &'a str) -> Vec<&'a str> {
+    contents.lines().enumerate().filter_map(|(i, line)| content_line(line, i == 0)).collect()
+}
+
+// expands `$NAME` and `${NAME}` using `lookup`, with `$$` standing for a
+// literal `$`; an unknown variable is an error naming it, unless `lenient`,
+// in which case the reference is left as it was
+pub fn expand_vars(value: &str, lenient: bool, lookup: |&str| -> Option<String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = value.chars().peekable();
+
+    loop {
+        match chars.next() {
+            None => break,
+            Some('$') => (),
+            Some(c) => { out.push(c); continue }
+        }
+
+        let (name, reference) = match chars.peek().map(|c| *c) {
+            Some('$') => {
+                chars.next();
+                out.push('$');
+                continue
+            },
+            Some('{') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let reference = format!("${{{}}}", name);
+                (name, reference)
+            },
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+
+                loop {
+                    match chars.peek().map(|c| *c) {
+                        Some(c) if c.is_alphanumeric() || c == '_' => { name.push(c); chars.next(); },
+                        _ => break
+                    }
+                }
+
+                let reference = format!("${}", name);
+                (name, reference)
+            },
+            _ => {
+                out.push('$');
+                continue
+            }
+        };
+
+        match lookup(name.as_slice()) {
+            Some(value) => out.push_str(value.as_slice()),
+            None if lenient => out.push_str(reference.as_slice()),
+            None => return Err(name)
+        }
+    }
+
+    Ok(out)
+}
+
+// replaces a leading `~` with the home directory, which is `$HOME`, or
+// `%USERPROFILE%` on Windows; `~user` isn't supported and is left alone,
+// like a `~` anywhere else or any path when there's no home directory
+pub fn expand_home(path: &str) -> String {
+    replace_home(path, os::homedir().and_then(|home| home.as_str().map(|h| h.to_string())))
+}
+
+fn replace_home(path: &str, home: Option<String>) -> String {
+    if !path.starts_with("~") {
+        return path.to_string();
+    }
+
+    let rest = path.slice_from(1);
+
+    match home {
+        Some(home) if rest.is_empty() || rest.starts_with("/") || rest.starts_with("\\") => format!("{}{}", home, rest),
+        _ => path.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_assignment, is_negative_number, parse_assignments, parse_values, parse_args, content_line, expand_vars, expand_home, replace_home};
+    use std::os;
+    use std::str;
+
+    #[test]
+    fn test_parse_assignments() {
+        let values = parse_assignments("# comment\n\nline-count = 3\ncolor=red=blue\nnonsense\n");
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(values.find_equiv(&"line_count"), Some(&"3".to_string()));
+        assert_eq!(values.find_equiv(&"color"), Some(&"red=blue".to_string()));
+    }
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("FOO=1"), Some(("FOO", "1")));
+        assert_eq!(split_assignment("_X2=a=b"), Some(("_X2", "a=b")));
+        assert_eq!(split_assignment("EMPTY="), Some(("EMPTY", "")));
+        assert_eq!(split_assignment("--foo=1"), None);
+        assert_eq!(split_assignment("2X=1"), None);
+        assert_eq!(split_assignment("=1"), None);
+        assert_eq!(split_assignment("build"), None);
+    }
+
+    #[test]
+    fn test_is_negative_number() {
+        assert!(is_negative_number("-5"));
+        assert!(is_negative_number("-10.25"));
+        assert!(!is_negative_number("5"));
+        assert!(!is_negative_number("-"));
+        assert!(!is_negative_number("-v"));
+        assert!(!is_negative_number("-5v"));
+        assert!(!is_negative_number("-5."));
+        assert!(!is_negative_number("-.5"));
+        assert!(!is_negative_number("--5"));
+    }
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse_values("# excluded\n\ntarget\n  *.o  \n#*.rs\n"), vec!("target".to_string(), "*.o".to_string()));
+        assert!(parse_values("").is_empty());
+    }
+
+    #[test]
+    fn test_windows_files() {
+        let bytes = b"\xef\xbb\xbftarget\r\n# excluded\r\n\r\n  *.o \r\nname = x\r\nlast";
+        let contents = str::from_utf8(bytes).unwrap();
+
+        assert_eq!(parse_values(contents), vec!("target".to_string(), "*.o".to_string(), "name = x".to_string(), "last".to_string()));
+
+        let values = parse_assignments(contents);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values.find_equiv(&"name"), Some(&"x".to_string()));
+
+        assert_eq!(content_line("\ufeff# comment\r", true), None);
+        assert_eq!(content_line("\ufeffx", false), Some("\ufeffx"));
+
+        // argument files keep everything but the byte order mark and `\r`
+        assert_eq!(parse_args(contents), vec!("target".to_string(), "# excluded".to_string(), "".to_string(),
+            "  *.o ".to_string(), "name = x".to_string(), "last".to_string()));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args("--name\n two words \n#1\n"), vec!("--name".to_string(), " two words ".to_string(), "#1".to_string()));
+        assert!(parse_args("").is_empty());
+    }
+
+    fn expand(value: &str, lenient: bool) -> Result<String, String> {
+        expand_vars(value, lenient, |name| {
+            match name {
+                "HOME" => Some("/home/me".to_string()),
+                "EMPTY" => Some("".to_string()),
+                _ => None
+            }
+        })
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        assert_eq!(expand("$HOME/out", false), Ok("/home/me/out".to_string()));
+        assert_eq!(expand("${HOME}out", false), Ok("/home/meout".to_string()));
+        assert_eq!(expand("a$EMPTY-b", false), Ok("a-b".to_string()));
+        assert_eq!(expand("$$HOME costs $5 $", false), Ok("$HOME costs $5 $".to_string()));
+        assert_eq!(expand("no variables", false), Ok("no variables".to_string()));
+    }
+
+    #[test]
+    fn test_expand_unknown_vars() {
+        assert_eq!(expand("${MISSING}/out", false), Err("MISSING".to_string()));
+        assert_eq!(expand("$MISSING/out", false), Err("MISSING".to_string()));
+        assert_eq!(expand("${MISSING}/$MISSING/$HOME", true), Ok("${MISSING}/$MISSING//home/me".to_string()));
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let replace = |path: &str| replace_home(path, Some("/home/me".to_string()));
+
+        assert_eq!(replace("~/notes.txt"), "/home/me/notes.txt".to_string());
+        assert_eq!(replace("~"), "/home/me".to_string());
+        assert_eq!(replace("notes.txt"), "notes.txt".to_string());
+        assert_eq!(replace("notes/~/old"), "notes/~/old".to_string());
+        assert_eq!(replace("~bob/notes.txt"), "~bob/notes.txt".to_string());
+        assert_eq!(replace_home("~/notes.txt", None), "~/notes.txt".to_string());
+
+        let home = os::homedir().unwrap();
+        assert_eq!(expand_home("~"), home.as_str().unwrap().to_string());
+    }
 }
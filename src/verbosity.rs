@@ -0,0 +1,135 @@
+/*!
+A ready-made `-v`/`-q` verbosity flag pair
+
+Embed `Verbosity` in a flag struct with `FlagConfiguration::flatten`, which
+also brings in its short aliases:
+
+```ignore
+#[deriving(Decodable)]
+struct MyOpts {
+    verbosity: Verbosity,
+    rest: Vec<String>
+}
+
+hammer_config!(MyOpts |c| { c.flatten::<Verbosity>("verbosity") })
+
+let level = opts.verbosity.level();
+```
+*/
+
+/// How much a program should log
+#[deriving(Clone, PartialEq, PartialOrd, Show)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+/// `-v` given any number of times, and `-q`
+#[deriving(Decodable, Encodable, Clone, PartialEq, Show)]
+pub struct Verbosity {
+    pub verbose: uint,
+    pub quiet: bool
+}
+
+hammer_config!(Verbosity |c| {
+    c.short("verbose", 'v').counted("verbose")
+     .short("quiet", 'q')
+})
+
+impl Verbosity {
+    /// `Warn` by default, one level more for each `-v`, and only `Error`
+    /// with `-q`, which wins over `-v`
+    pub fn level(&self) -> LogLevel {
+        if self.quiet { return Error }
+
+        match self.verbose {
+            0 => Warn,
+            1 => Info,
+            2 => Debug,
+            _ => Trace
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Verbosity, Error, Warn, Info, Debug, Trace};
+    use decode::{decode_args, ConfigError};
+    use decode::harness::owned;
+    use usage::usage;
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ToolFlags {
+        verbosity: Verbosity,
+        dry_run: bool,
+        rest: Vec<String>
+    }
+
+    hammer_config!(ToolFlags |c| { c.flatten::<Verbosity>("verbosity").short("dry_run", 'n') })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct ClashingFlags {
+        verbosity: Verbosity,
+        version_info: bool
+    }
+
+    hammer_config!(ClashingFlags |c| { c.flatten::<Verbosity>("verbosity").short("version_info", 'v') })
+
+    #[deriving(Decodable, Show, PartialEq)]
+    struct RenamedFlags {
+        verbosity: Verbosity
+    }
+
+    hammer_config!(RenamedFlags |c| { c.short("verbose", 'x').flatten::<Verbosity>("verbosity") })
+
+    fn verbosity(args: &[&str]) -> Verbosity {
+        decode_args::<ToolFlags>(owned(args).as_slice()).unwrap().verbosity
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(verbosity(&[]), Verbosity { verbose: 0, quiet: false });
+        assert_eq!(verbosity(&[]).level(), Warn);
+        assert_eq!(verbosity(&["-v"]).level(), Info);
+        assert_eq!(verbosity(&["-vv"]), Verbosity { verbose: 2, quiet: false });
+        assert_eq!(verbosity(&["-vv"]).level(), Debug);
+        assert_eq!(verbosity(&["-vv", "-n", "--verbose"]).level(), Trace);
+        assert_eq!(verbosity(&["-v", "-v", "--verbose"]), Verbosity { verbose: 3, quiet: false });
+        assert_eq!(verbosity(&["--verbose", "-n", "-v"]), Verbosity { verbose: 2, quiet: false });
+        assert_eq!(verbosity(&["-q"]).level(), Error);
+        assert_eq!(verbosity(&["-v", "-q"]).level(), Error);
+        assert_eq!(verbosity(&["--ver"]).level(), Info);
+        assert_eq!(verbosity(&["--qui", "-v"]).level(), Error);
+    }
+
+    #[test]
+    fn test_embedded() {
+        let args = owned(&["-v", "-n", "build", "-v"]);
+        let flags = decode_args::<ToolFlags>(args.as_slice()).unwrap();
+
+        assert_eq!(flags.verbosity.verbose, 2);
+        assert!(flags.dry_run);
+        assert_eq!(flags.rest, vec!("build".to_string()));
+
+        assert_eq!(usage::<ToolFlags>(false), (None, "-v, [--verbose]\n-q, [--quiet]\n-n, [--dry-run]\n".to_string()));
+    }
+
+    #[test]
+    fn test_short_collision() {
+        let err = decode_args::<ClashingFlags>(&[]).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "-v is the short form of both verbose and version_info".to_string());
+    }
+
+    #[test]
+    fn test_flatten_conflict() {
+        let err = decode_args::<RenamedFlags>(&[]).unwrap_err();
+
+        assert_eq!(err.kind, ConfigError);
+        assert_eq!(err.message, "flattening verbosity: short is configured twice for verbose: -x and -v".to_string());
+    }
+}